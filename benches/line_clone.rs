@@ -0,0 +1,56 @@
+//! Times cloning lines with 10 substitutions, which share their substitutions and text
+//! rather than copying them:
+//!
+//! ```text
+//! cargo bench --bench line_clone
+//! ```
+
+mod common;
+
+use std::{hint::black_box, time::Instant};
+
+use bevy_mod_yarn::prelude::BevyYarnLine;
+use chapter::Line;
+
+use common::count_allocations;
+
+const SUBSTITUTIONS: usize = 10;
+const CLONES: u32 = 100_000;
+
+fn main() {
+    let line = Line {
+        id: "line:1".into(),
+        substitutions: (0..SUBSTITUTIONS)
+            .map(|index| format!("substitution number {index}"))
+            .collect(),
+    };
+    let bevy_line = BevyYarnLine::new(line.clone(), "A line with ten substitutions");
+
+    let start = Instant::now();
+    for _ in 0..CLONES {
+        black_box(black_box(&bevy_line).clone());
+    }
+    let line_mean = start.elapsed() / CLONES;
+
+    let start = Instant::now();
+    for _ in 0..CLONES {
+        black_box(black_box(&line).clone());
+    }
+    let vm_line_mean = start.elapsed() / CLONES;
+
+    let line_allocations = count_allocations(|| {
+        black_box(bevy_line.clone());
+    });
+    let vm_line_allocations = count_allocations(|| {
+        black_box(line.clone());
+    });
+
+    println!("BevyYarnLine clone: {line_mean:>10?}, {line_allocations} allocations");
+    println!("chapter Line clone: {vm_line_mean:>10?}, {vm_line_allocations} allocations");
+
+    assert_eq!(
+        bevy_line.clone().substitutions(),
+        line.substitutions.as_slice()
+    );
+    assert!(line_allocations < vm_line_allocations);
+}
@@ -0,0 +1,97 @@
+//! Counts the allocations made building lines, to check that lines without substitutions
+//! share the text of their string table entry rather than copying it:
+//!
+//! ```text
+//! cargo bench --bench line_text
+//! ```
+
+mod common;
+
+use std::{collections::HashMap, fmt::Write, hint::black_box};
+
+use bevy::prelude::Entity;
+use bevy_mod_yarn::{assets::NodeHeaders, prelude::*};
+use chapter::Line;
+
+use common::count_allocations;
+
+const LINES: usize = 100;
+
+fn main() {
+    let mut csv = String::from("id,text,file,node,lineNumber\n");
+    for index in 0..LINES {
+        let _ = writeln!(
+            csv,
+            "line:{index},Guard: Static line {index},bench.yarn,Start,{index}"
+        );
+        let _ = writeln!(
+            csv,
+            "line:sub{index},Guard: Hello {{0}},bench.yarn,Start,{index}"
+        );
+    }
+    let string_table =
+        BevyYarnStringTable::from_csv(csv.as_bytes()).expect("bench table is valid csv");
+    let metadata_table = BevyYarnMetadataTable::default();
+    let named_substitutions = BevyYarnNamedSubstitutions::default();
+    let missing_string_policy = YarnMissingStringPolicy::default();
+    let timing = YarnTimingConfig::default();
+    let tag_keys = YarnLineTagKeys::default();
+    let actor_config = YarnActorConfig::default();
+    let node_headers = NodeHeaders::new();
+    let visited_nodes = HashMap::new();
+
+    let context = YarnTranslationContext {
+        string_table: &string_table,
+        metadata_table: &metadata_table,
+        locale_tables: &[],
+        named_substitutions: &named_substitutions,
+        missing_string_policy: &missing_string_policy,
+        timing: &timing,
+        tag_keys: &tag_keys,
+        actor_config: &actor_config,
+        node_headers: &node_headers,
+        visited_nodes: &visited_nodes,
+        engine_entity: Entity::PLACEHOLDER,
+    };
+
+    let static_lines = (0..LINES)
+        .map(|index| Line {
+            id: format!("line:{index}"),
+            substitutions: Vec::new(),
+        })
+        .collect::<Vec<_>>();
+    let substituted_lines = (0..LINES)
+        .map(|index| Line {
+            id: format!("line:sub{index}"),
+            substitutions: vec!["Sam".into()],
+        })
+        .collect::<Vec<_>>();
+
+    let mut built = Vec::with_capacity(LINES);
+    let static_allocations = count_allocations(|| {
+        built.extend(static_lines.iter().map(|line| context.line(line)));
+    });
+    let shared = built
+        .iter()
+        .filter(|line| {
+            std::ptr::eq(
+                &*line.formatted_text,
+                &*context.line(&line.line).formatted_text,
+            )
+        })
+        .count();
+    black_box(&built);
+    built.clear();
+
+    let substituted_allocations = count_allocations(|| {
+        built.extend(substituted_lines.iter().map(|line| context.line(line)));
+    });
+    black_box(&built);
+
+    println!("{LINES} static lines:      {static_allocations:>6} allocations, {shared} sharing the table text");
+    println!("{LINES} substituted lines: {substituted_allocations:>6} allocations");
+
+    // each substituted line copies and formats its text, which static lines skip
+    assert_eq!(shared, LINES);
+    assert!(static_allocations < substituted_allocations);
+}
@@ -0,0 +1,76 @@
+//! Compares the string table layouts. Run with and without the `optimized-tables` feature:
+//!
+//! ```text
+//! cargo bench --bench string_table
+//! cargo bench --bench string_table --features optimized-tables
+//! ```
+
+use std::{fmt::Write, hint::black_box, time::Instant};
+
+use bevy_mod_yarn::prelude::BevyYarnStringTable;
+
+const LINES: usize = 10_000;
+const ITERATIONS: u32 = 100;
+
+/// A lines table with a character name on every other line
+fn build_table() -> (BevyYarnStringTable, Vec<String>) {
+    let mut csv = String::from("id,text,file,node,lineNumber\n");
+    let mut ids = Vec::with_capacity(LINES);
+
+    for index in 0..LINES {
+        let id = format!("line:{index:08x}");
+        let speaker = if index % 2 == 0 { "Guard: " } else { "" };
+        let _ = writeln!(
+            csv,
+            "{id},{speaker}Line number {index},bench.yarn,Start,{index}"
+        );
+        ids.push(id);
+    }
+
+    let table = BevyYarnStringTable::from_csv(csv.as_bytes()).expect("bench table is valid csv");
+    (table, ids)
+}
+
+/// Runs the function the given number of times and prints the mean time of each run
+fn bench(name: &str, mut f: impl FnMut()) {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    let mean = start.elapsed() / ITERATIONS;
+
+    println!("{name:<28} {mean:>12?}");
+}
+
+fn main() {
+    let layout = if cfg!(feature = "optimized-tables") {
+        "optimized-tables"
+    } else {
+        "hash map"
+    };
+    println!("string table layout: {layout}, {LINES} lines");
+
+    let (table, ids) = build_table();
+
+    bench("line_info every line", || {
+        for id in ids.iter() {
+            black_box(table.line_info(black_box(id)));
+        }
+    });
+
+    bench("iter every line", || {
+        for (id, line_info) in table.iter() {
+            black_box((id, line_info));
+        }
+    });
+
+    bench("character_name_set", || {
+        black_box(table.character_name_set());
+    });
+
+    bench("from_csv and merge", || {
+        let (mut merged, _) = build_table();
+        merged.merge(build_table().0);
+        black_box(merged);
+    });
+}
@@ -0,0 +1,162 @@
+#![deny(missing_docs)]
+//! Helpers for compiling yarn files with the Yarn Spinner Console (`ysc`) from a
+//! `build.rs`, so the compiled yarnc, lines and metadata files are ready to be loaded by
+//! `bevy_mod_yarn`.
+//!
+//! ```ignore
+//! // build.rs
+//! fn main() {
+//!     bevy_mod_yarn_build::compile_yarn("assets/story.yarn", "assets")
+//!         .unwrap_or_else(|e| panic!("{e}"));
+//! }
+//! ```
+//!
+//! `ysc` must be downloaded separately from
+//! <https://github.com/YarnSpinnerTool/YarnSpinner-Console/releases>. It is found using the
+//! [YSC_PATH_VAR] environment variable, then `./ysc` in the package root and finally `ysc`
+//! on the `PATH`.
+
+use std::{
+    fmt,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// The environment variable that sets the path of the Yarn Spinner Console, the same as
+/// used by the `compile-on-load` feature of `bevy_mod_yarn`
+pub const YSC_PATH_VAR: &str = "BEVY_MOD_YARN_YSC";
+
+/// Where to download the Yarn Spinner Console from
+pub const YSC_DOWNLOAD_URL: &str =
+    "https://github.com/YarnSpinnerTool/YarnSpinner-Console/releases";
+
+/// The ways compiling a yarn file can fail
+#[derive(Debug)]
+pub enum CompileYarnError {
+    /// The Yarn Spinner Console wasn't found at the given path
+    MissingYsc(PathBuf),
+    /// The Yarn Spinner Console couldn't be run, or its output couldn't be renamed
+    Io(std::io::Error),
+    /// The Yarn Spinner Console failed to compile the file, with its output
+    Compile {
+        /// The yarn file that failed to compile
+        source: PathBuf,
+        /// The error output of the Yarn Spinner Console
+        stderr: String,
+    },
+}
+
+impl fmt::Display for CompileYarnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingYsc(ysc) => write!(
+                f,
+                "unable to find the Yarn Spinner Console at {}. Download it from \
+                 {YSC_DOWNLOAD_URL} and place it in the package root, add it to the PATH or \
+                 set {YSC_PATH_VAR} to its path",
+                ysc.display()
+            ),
+            Self::Io(e) => write!(f, "unable to run the Yarn Spinner Console: {e}"),
+            Self::Compile { source, stderr } => write!(
+                f,
+                "unable to compile {}:\n{}",
+                source.display(),
+                stderr.trim_end()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CompileYarnError {}
+
+impl From<std::io::Error> for CompileYarnError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Finds the Yarn Spinner Console, see the [crate documentation](crate)
+pub fn ysc_path() -> PathBuf {
+    if let Some(ysc) = std::env::var_os(YSC_PATH_VAR) {
+        return ysc.into();
+    }
+
+    let local = Path::new("./ysc");
+    if local.exists() || local.with_extension("exe").exists() {
+        local.to_owned()
+    } else {
+        "ysc".into()
+    }
+}
+
+/// Compiles the yarn file at `src` into `out_dir` with the Yarn Spinner Console, and tells
+/// cargo to rerun the build script when it changes.
+///
+/// The compiler writes `<name>-Lines.csv` and `<name>-Metadata.csv` next to the yarnc
+/// file, which are renamed to `<name>.lines.csv` and `<name>.metadata.csv` as bevy can't
+/// load different asset types with the same extension. See [compile_yarn_with_ysc_names]
+/// to keep the names for the `ysc-table-names` feature of `bevy_mod_yarn`.
+pub fn compile_yarn<S: AsRef<Path>, O: AsRef<Path>>(
+    src: S,
+    out_dir: O,
+) -> Result<(), CompileYarnError> {
+    let out_dir = out_dir.as_ref();
+    let name = run_ysc(src.as_ref(), out_dir)?;
+
+    std::fs::rename(
+        out_dir.join(format!("{name}-Lines.csv")),
+        out_dir.join(format!("{name}.lines.csv")),
+    )?;
+    std::fs::rename(
+        out_dir.join(format!("{name}-Metadata.csv")),
+        out_dir.join(format!("{name}.metadata.csv")),
+    )?;
+
+    Ok(())
+}
+
+/// Compiles the yarn file at `src` into `out_dir` like [compile_yarn], keeping the
+/// `<name>-Lines.csv` and `<name>-Metadata.csv` names written by the Yarn Spinner Console.
+/// These are loaded by `bevy_mod_yarn` with its `ysc-table-names` feature.
+pub fn compile_yarn_with_ysc_names<S: AsRef<Path>, O: AsRef<Path>>(
+    src: S,
+    out_dir: O,
+) -> Result<(), CompileYarnError> {
+    run_ysc(src.as_ref(), out_dir.as_ref()).map(|_| ())
+}
+
+/// Runs the Yarn Spinner Console to compile the yarn file, returning the name of the
+/// compiled files
+fn run_ysc<'a>(src: &'a Path, out_dir: &Path) -> Result<&'a str, CompileYarnError> {
+    println!("cargo:rerun-if-changed={}", src.display());
+    println!("cargo:rerun-if-env-changed={YSC_PATH_VAR}");
+
+    let name = src
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("program");
+
+    let ysc = ysc_path();
+    let output = Command::new(&ysc)
+        .arg("compile")
+        .arg("--output-directory")
+        .arg(out_dir)
+        .arg("--output-name")
+        .arg(name)
+        .arg(src)
+        .output()
+        .map_err(|e| match e.kind() {
+            ErrorKind::NotFound => CompileYarnError::MissingYsc(ysc.clone()),
+            _ => CompileYarnError::Io(e),
+        })?;
+
+    if !output.status.success() {
+        return Err(CompileYarnError::Compile {
+            source: src.to_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+
+    Ok(name)
+}
@@ -1,44 +1,18 @@
-use std::process::Command;
+use std::path::Path;
 
-fn main() {
-    println!("cargo:rerun-if-changed=assets/minimal.yarn");
-    println!("cargo:rerun-if-changed=assets/kitchen_sink.yarn");
-
-    if let Err(e) = Command::new("./ysc")
-        .arg("compile")
-        .arg("-o")
-        .arg("./assets")
-        .arg("./assets/minimal.yarn")
-        .output()
-    {
-        eprintln!("Failed to compile, maybe ysc wasn't in the root directory? Error: {e:?}")
-    }
+use bevy_mod_yarn_build::{compile_yarn, CompileYarnError};
 
-    if let Err(e) = Command::new("./ysc")
-        .arg("compile")
-        .arg("-o")
-        .arg("./assets")
-        .arg("./assets/kitchen_sink.yarn")
-        .output()
-    {
-        eprintln!("Failed to compile, maybe ysc wasn't in the root directory? Error: {e:?}")
+fn main() {
+    for source in ["./assets/minimal.yarn", "./assets/kitchen_sink.yarn"] {
+        match compile_yarn(source, "./assets") {
+            Ok(()) => {}
+            // the compiled files are checked in, so the examples still run without ysc
+            Err(e @ CompileYarnError::MissingYsc(_))
+                if Path::new(source).with_extension("yarnc").exists() =>
+            {
+                println!("cargo:warning=Using the existing compiled {source}, {e}");
+            }
+            Err(e) => panic!("{e}"),
+        }
     }
-
-    // we need to rename the *-Lines.csv and *-Metadata.csv files
-    // as bevy currently doesn't support loading multiple asset types
-    // with the same extension. This is really only important for running the examples,
-    // so we're just ignoring errors :shrug:
-    let _ = std::fs::rename("./assets/minimal-Lines.csv", "./assets/minimal.lines.csv");
-    let _ = std::fs::rename(
-        "./assets/minimal-Metadata.csv",
-        "./assets/minimal.metadata.csv",
-    );
-    let _ = std::fs::rename(
-        "./assets/kitchen_sink-Lines.csv",
-        "./assets/kitchen_sink.lines.csv",
-    );
-    let _ = std::fs::rename(
-        "./assets/kitchen_sink-Metadata.csv",
-        "./assets/kitchen_sink.metadata.csv",
-    );
 }
@@ -0,0 +1,116 @@
+// Bevy code commonly triggers these lints and they may be important signals
+// about code quality. They are sometimes hard to avoid though, and the CI
+// workflow treats them as errors, so this allows them throughout the project.
+// Feel free to delete this line.
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+
+use bevy::prelude::*;
+use bevy_mod_yarn::{
+    prelude::{BevyYarnEvent, BevyYarnStepDialogueEvent, SelectChoiceCommand, YarnData},
+    yarn_command_provider, YarnPluginBuilder,
+};
+
+fn main() {
+    App::new()
+        .add_plugins((
+            DefaultPlugins,
+            // Every command listed in the `yarn_command_provider!` call below is registered
+            // at once, named after its function
+            YarnPluginBuilder::default()
+                .add_yarn_commands_from_provider::<StageCommands>()
+                .build(),
+        ))
+        .insert_resource(ClearColor(Color::BLACK))
+        .add_systems(Update, handle_yarn_steps)
+        .add_systems(Startup, setup)
+        .run();
+}
+
+/// Groups the handlers for the commands used to stage the story. Each function is
+/// registered as a yarn command with the same name, so `<<play_sound door>>` calls
+/// [StageCommands::play_sound].
+struct StageCommands;
+
+impl StageCommands {
+    fn echo(_world: &mut World, args: Vec<String>) {
+        info!("Echo: {}", args.join(" "));
+    }
+
+    fn set_background(world: &mut World, args: Vec<String>) {
+        let color = match args.first().map(String::as_str) {
+            Some("one") => Color::MIDNIGHT_BLUE,
+            Some("two") => Color::DARK_GREEN,
+            _ => Color::BLACK,
+        };
+        world.insert_resource(ClearColor(color));
+    }
+
+    fn play_sound(_world: &mut World, args: Vec<String>) {
+        info!("Playing sound {args:?}");
+    }
+
+    fn shake_camera(world: &mut World, _args: Vec<String>) {
+        let mut cameras = world.query_filtered::<&mut Transform, With<Camera>>();
+        for mut transform in cameras.iter_mut(world) {
+            transform.translation.x += 4.0;
+        }
+    }
+
+    fn give_item(_world: &mut World, args: Vec<String>) {
+        info!("Giving the player {args:?}");
+    }
+}
+
+// The kitchen sink story only uses `echo` and `set_background`, the other commands show
+// how a larger set of commands is registered
+yarn_command_provider!(StageCommands {
+    echo,
+    set_background,
+    play_sound,
+    shake_camera,
+    give_item,
+});
+
+fn setup(mut commands: Commands) {
+    commands.spawn(Camera2dBundle::default());
+
+    // Spawn the yarn data file, starting the story
+    commands.spawn(YarnData::new("../assets/kitchen_sink.yarnc"));
+}
+
+/// Steps through the dialogue, always picking the first choice
+fn handle_yarn_steps(
+    mut events: EventReader<BevyYarnEvent>,
+    mut commands: Commands,
+    mut event_sender: EventWriter<BevyYarnStepDialogueEvent>,
+) {
+    for event in events.iter() {
+        match event {
+            BevyYarnEvent::Say(line) => {
+                info!("{}", line.formatted_text);
+                event_sender.send(BevyYarnStepDialogueEvent);
+            }
+            BevyYarnEvent::Choices { engine_entity, .. } => {
+                // selecting the choice also steps the dialogue
+                commands.add(SelectChoiceCommand {
+                    entity: *engine_entity,
+                    choice_index: 0,
+                });
+            }
+            BevyYarnEvent::Command(cmd) => {
+                if !cmd.handled {
+                    warn!("Received an unregistered command: `{cmd:?}`");
+                }
+            }
+            BevyYarnEvent::EndConversation { .. } => {
+                info!("Reached end of conversation, stopping");
+            }
+            BevyYarnEvent::DialogueReady(_) => {}
+            BevyYarnEvent::VariableSet { .. } => {}
+            BevyYarnEvent::ChoiceSelected { .. } => {}
+            BevyYarnEvent::LineRevealComplete => {}
+            BevyYarnEvent::CharacterChanged { .. } => {}
+            BevyYarnEvent::Error(e) => warn!("Error while stepping dialogue: {e}"),
+        }
+    }
+}
@@ -7,7 +7,7 @@
 use bevy::prelude::*;
 use bevy_mod_yarn::{
     commands::AddBevyCommandHandlerExt,
-    prelude::{BevyYarnEvent, YarnData},
+    prelude::{BevyYarnEvent, YarnCharacterStyle, YarnCharacterStyles, YarnData},
     YarnPluginBuilder,
 };
 
@@ -24,6 +24,14 @@ fn main() {
                 .build(),
         ))
         .insert_resource(ClearColor(Color::BLACK))
+        // Lines said by a character can be styled per character
+        .insert_resource(YarnCharacterStyles::default().with_style(
+            "CharacterA",
+            YarnCharacterStyle {
+                name_color: Color::CYAN,
+                ..default()
+            },
+        ))
         // This is another way to register commands. This is also available on World.
         .add_yarn_command("echo", echo_handler)
         .add_systems(Update, (handle_yarn_steps,))
@@ -38,9 +46,7 @@ fn setup(mut commands: Commands) {
     commands.spawn(Camera2dBundle::default());
 
     // Spawn the yarn data file, starting the story
-    commands.spawn(YarnData {
-        yarnc_path: "../assets/kitchen_sink.yarnc".to_string(),
-    });
+    commands.spawn(YarnData::new("../assets/kitchen_sink.yarnc"));
 
     commands.spawn((TextBundle::from_section(
         "",
@@ -54,7 +60,11 @@ fn setup(mut commands: Commands) {
 
 /// This function listens for BevyYarnEvents, which are sent by the Yarn Engine when
 /// something new occurs within the dialogue. Here we can display the scene to the user.
-fn handle_yarn_steps(mut events: EventReader<BevyYarnEvent>, mut texts: Query<&mut Text>) {
+fn handle_yarn_steps(
+    mut events: EventReader<BevyYarnEvent>,
+    styles: Res<YarnCharacterStyles>,
+    mut texts: Query<&mut Text>,
+) {
     for event in events.iter() {
         match event {
             BevyYarnEvent::Say(line) => {
@@ -63,25 +73,30 @@ fn handle_yarn_steps(mut events: EventReader<BevyYarnEvent>, mut texts: Query<&m
                     line.formatted_text
                 );
 
+                let style = styles.get_style(line.character.as_deref());
                 let mut text = texts.single_mut();
-                text.sections.push(TextSection {
-                    value: format!(
-                        "{}{}\n",
-                        if let Some(ref character) = line.character {
-                            format!("{character} said: ")
-                        } else {
-                            "".into()
+
+                if let Some(ref character) = line.character {
+                    text.sections.push(TextSection {
+                        value: format!("{character} said: "),
+                        style: TextStyle {
+                            font_size: 16.,
+                            color: style.name_color,
+                            ..default()
                         },
-                        line.formatted_text
-                    ),
+                    });
+                }
+
+                text.sections.push(TextSection {
+                    value: format!("{}\n", line.formatted_text),
                     style: TextStyle {
                         font_size: 16.,
-                        color: Color::WHITE,
+                        color: style.text_color,
                         ..default()
                     },
                 });
             }
-            BevyYarnEvent::Choices(ref choices) => {
+            BevyYarnEvent::Choices { ref choices, .. } => {
                 let mut text = texts.single_mut();
 
                 let section = TextSection {
@@ -107,9 +122,15 @@ fn handle_yarn_steps(mut events: EventReader<BevyYarnEvent>, mut texts: Query<&m
                     warn!("Received an unexpected command: `{cmd:?}`. You should probably do something about it");
                 }
             }
-            BevyYarnEvent::EndConversation => {
+            BevyYarnEvent::EndConversation { .. } => {
                 info!("Reached end of conversation, stopping");
             }
+            BevyYarnEvent::DialogueReady(_) => {}
+            BevyYarnEvent::VariableSet { .. } => {}
+            BevyYarnEvent::ChoiceSelected { .. } => {}
+            BevyYarnEvent::LineRevealComplete => {}
+            BevyYarnEvent::CharacterChanged { .. } => {}
+            BevyYarnEvent::Error(e) => warn!("Error while stepping dialogue: {e}"),
         }
     }
 }
@@ -22,9 +22,7 @@ fn setup(mut commands: Commands) {
     commands.spawn(Camera2dBundle::default());
 
     // Spawn the yarn data file, starting the story
-    commands.spawn(YarnData {
-        yarnc_path: "../assets/minimal.yarnc".to_string(),
-    });
+    commands.spawn(YarnData::new("../assets/minimal.yarnc"));
 
     commands.spawn((TextBundle::from_section(
         "My story is below...\n\n",
@@ -61,13 +59,19 @@ fn handle_yarn_steps(
 
                 event_sender.send(BevyYarnStepDialogueEvent);
             }
-            BevyYarnEvent::Choices(_) | BevyYarnEvent::Command(_) => {
+            BevyYarnEvent::Choices { .. } | BevyYarnEvent::Command(_) => {
                 warn!("Unexpected event for minimal example, ignoring. Event: {event:?}");
                 event_sender.send(BevyYarnStepDialogueEvent);
             }
-            BevyYarnEvent::EndConversation => {
+            BevyYarnEvent::EndConversation { .. } => {
                 info!("Reached end of conversation, stopping");
             }
+            BevyYarnEvent::DialogueReady(_) => {}
+            BevyYarnEvent::VariableSet { .. } => {}
+            BevyYarnEvent::ChoiceSelected { .. } => {}
+            BevyYarnEvent::LineRevealComplete => {}
+            BevyYarnEvent::CharacterChanged { .. } => {}
+            BevyYarnEvent::Error(e) => warn!("Error while stepping dialogue: {e}"),
         }
     }
 }
@@ -0,0 +1,56 @@
+// Bevy code commonly triggers these lints and they may be important signals
+// about code quality. They are sometimes hard to avoid though, and the CI
+// workflow treats them as errors, so this allows them throughout the project.
+// Feel free to delete this line.
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+
+use bevy::prelude::*;
+use bevy_mod_yarn::{
+    prelude::{BevyYarnDialogueEngine, BevyYarnEvent, BevyYarnStepDialogueEvent, YarnDialogue},
+    YarnPlugin,
+};
+
+fn main() {
+    App::new()
+        .add_plugins((DefaultPlugins, YarnPlugin::default()))
+        .add_systems(Update, (handle_yarn_steps, restart_dialogue))
+        .add_systems(Startup, setup)
+        .run();
+}
+
+fn setup(mut commands: Commands) {
+    commands.spawn(Camera2dBundle::default());
+
+    // Start the story in a resource, no entity is needed
+    YarnDialogue::start(&mut commands, "../assets/minimal.yarnc");
+}
+
+fn handle_yarn_steps(
+    mut events: EventReader<BevyYarnEvent>,
+    mut event_sender: EventWriter<BevyYarnStepDialogueEvent>,
+) {
+    for event in events.iter() {
+        match event {
+            BevyYarnEvent::Say(line) => {
+                info!("{}", line.formatted_text);
+                event_sender.send(BevyYarnStepDialogueEvent);
+            }
+            BevyYarnEvent::EndConversation { .. } => {
+                info!("Reached end of conversation, press R to restart");
+            }
+            _ => {}
+        }
+    }
+}
+
+fn restart_dialogue(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    engine: Option<Res<BevyYarnDialogueEngine>>,
+) {
+    let is_complete = engine.is_some_and(|engine| engine.is_complete);
+
+    if is_complete && keys.just_pressed(KeyCode::R) {
+        YarnDialogue::start(&mut commands, "../assets/minimal.yarnc");
+    }
+}
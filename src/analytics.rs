@@ -0,0 +1,150 @@
+//! Measuring how long players spend reading lines and choosing between choices, for tuning
+//! the pacing of dialogue. Added with the `analytics` feature.
+
+use std::fmt::Write;
+
+use bevy::prelude::*;
+
+use crate::{
+    events::{BevyYarnEvent, BevyYarnStepDialogueEvent},
+    ids::LineId,
+};
+
+/// A resource that records how long each line was shown before the dialogue was stepped,
+/// and how long each set of choices was shown before one was selected, while it exists.
+/// Insert it to start recording:
+///
+/// ```ignore
+/// app.init_resource::<DialogueTimingAnalytics>();
+///
+/// // at the end of the session
+/// std::fs::write("timings.csv", analytics.export_csv())?;
+/// ```
+///
+/// Lines and choices are timed from their [BevyYarnEvent::Say] and [BevyYarnEvent::Choices]
+/// events, which don't say which dialogue raised them, so timings are only meaningful while
+/// one dialogue is running at a time. Choices are timed until their
+/// [BevyYarnEvent::ChoiceSelected] event, so aren't recorded if
+/// [crate::prelude::YarnEventMask::CHOICE_SELECTED] is disabled.
+#[derive(Resource, Debug, Default, Clone, PartialEq)]
+pub struct DialogueTimingAnalytics {
+    /// The id of each line that was stepped past, with the milliseconds it was shown for
+    pub lines: Vec<(LineId, u64)>,
+    /// The [crate::prelude::BevyYarnChoice::option_index] of each selected choice, with the
+    /// milliseconds the choices were shown for before it was selected
+    pub choices: Vec<(usize, u64)>,
+    shown_line: Option<(LineId, f64)>,
+    shown_choices: Option<(Vec<LineId>, f64)>,
+}
+
+impl DialogueTimingAnalytics {
+    /// Exports the timings as csv with `kind`, `id` and `milliseconds` columns, where `kind`
+    /// is `line` with the line id or `choice` with the option index of the selected choice
+    pub fn export_csv(&self) -> String {
+        let mut csv = String::from("kind,id,milliseconds\n");
+
+        for (line_id, milliseconds) in self.lines.iter() {
+            let _ = writeln!(csv, "line,{line_id},{milliseconds}");
+        }
+
+        for (option_index, milliseconds) in self.choices.iter() {
+            let _ = writeln!(csv, "choice,{option_index},{milliseconds}");
+        }
+
+        csv
+    }
+
+    /// Times the lines stepped past and choices selected this frame, then starts timing the
+    /// lines and choices raised this frame
+    pub(crate) fn record(
+        mut analytics: ResMut<DialogueTimingAnalytics>,
+        time: Res<Time>,
+        mut step_events: EventReader<BevyYarnStepDialogueEvent>,
+        mut yarn_events: EventReader<BevyYarnEvent>,
+    ) {
+        let now = time.elapsed_seconds_f64();
+        let milliseconds_since = |shown_at: f64| ((now - shown_at) * 1000.0).round() as u64;
+        let analytics = analytics.as_mut();
+
+        if step_events.iter().count() > 0 {
+            if let Some((line_id, shown_at)) = analytics.shown_line.take() {
+                analytics
+                    .lines
+                    .push((line_id, milliseconds_since(shown_at)));
+            }
+        }
+
+        // replayed lines and choices were timed when they were first shown
+        for event in yarn_events.iter().filter(|event| !event.is_replay()) {
+            match event {
+                BevyYarnEvent::ChoiceSelected { line_id, .. } => {
+                    let Some((line_ids, shown_at)) = analytics.shown_choices.take() else {
+                        continue;
+                    };
+
+                    if let Some(option_index) = line_ids.iter().position(|id| id == line_id) {
+                        analytics
+                            .choices
+                            .push((option_index, milliseconds_since(shown_at)));
+                    }
+                }
+                BevyYarnEvent::Say(line) => {
+                    analytics.shown_line = Some((LineId(line.line.id.clone()), now));
+                }
+                BevyYarnEvent::Choices { choices, .. } => {
+                    analytics.shown_line = None;
+                    analytics.shown_choices = Some((
+                        choices
+                            .iter()
+                            .map(|choice| choice.line_id.clone())
+                            .collect(),
+                        now,
+                    ));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+    use crate::testing::TestDialogue;
+
+    #[test]
+    fn records_how_long_lines_and_choices_are_shown() {
+        let mut dialogue = TestDialogue::kitchen_sink();
+        dialogue
+            .app
+            .init_resource::<DialogueTimingAnalytics>()
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs(2)));
+
+        // each frame is two seconds after the last
+        for _ in 0..3 {
+            dialogue.step();
+        }
+
+        let position = dialogue.option_3_position();
+        dialogue.update();
+        dialogue.engine().select_option(position);
+        dialogue.step();
+
+        let analytics = dialogue.app.world.resource::<DialogueTimingAnalytics>();
+        assert_eq!(
+            analytics.lines[..2],
+            [
+                (LineId::from("line:4a18032a"), 2000),
+                (LineId::from("line:dc28045d"), 2000)
+            ]
+        );
+        assert_eq!(analytics.choices, [(position, 4000)]);
+        assert!(analytics
+            .export_csv()
+            .starts_with("kind,id,milliseconds\nline,line:4a18032a,2000\n"));
+    }
+}
@@ -1,18 +1,125 @@
 //! Custom asset loaders for compiled Yarn files (yarnc) and
 //! the associated string and metadata files as defined in [crate::data].
+//!
+//! # Program versions
+//!
+//! Only Yarn Spinner v2.x compiled programs are supported. Yarn Spinner v3.x programs
+//! record a language version in the compiled program, and the [BevyYarnProjectAssetLoader]
+//! fails to load these with an error rather than running them incorrectly.
+//!
+//! To migrate a v3.x project, compile the yarn files with a v2.x release of the
+//! Yarn Spinner Console (`ysc`) and remove any v3.x only syntax (for instance
+//! `<<once>>` blocks, enums and line groups) from the yarn files.
+//!
+//! # Compiling on load
+//!
+//! With the `compile-on-load` feature, `.yarn` source files can be loaded directly by the
+//! [BevyYarnSourceAssetLoader], which compiles them with the Yarn Spinner Console.
+//!
+//! # Multiple yarnc files
+//!
+//! Projects that compile each yarn file separately can load them as a single program with
+//! a `.yarnmanifest` file, which is a JSON array of yarnc paths relative to the manifest:
+//!
+//! ```json
+//! ["town.yarnc", "forest.yarnc"]
+//! ```
+//!
+//! The [BevyYarnManifestAssetLoader] merges the nodes and variable declarations of every
+//! file into one [BevyYarnProgram], so jumps between files resolve, and merges the string
+//! and metadata tables next to each yarnc file into combined tables. Spawn a
+//! [crate::prelude::YarnData] with the path of the manifest to run it. Node names must be
+//! unique across all files.
+//!
+//! The translated tables next to each yarnc file, such as `town.de.lines.csv`, are merged in
+//! the same way for each locale in the [crate::prelude::YarnLocaleChain] when the manifest
+//! is loaded. Files that haven't been translated into a locale can leave out its table.
 
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use bevy::{
     asset::{AssetLoader, AssetPath, LoadedAsset},
-    prelude::{warn, Handle},
+    prelude::{error, warn, Handle, Resource},
     reflect::{TypePath, TypeUuid},
 };
-use chapter::{expand_format_functions, Line, LineInfo, MetadataInfo, Program};
+use chapter::{
+    expand_format_functions, operand::Value, Line, LineInfo, MetadataInfo, Program, YarnValue,
+};
 use csv::{Reader, ReaderBuilder};
 use prost::Message;
 use regex::Regex;
 
+use crate::{
+    diagnostics::YarnLoaderDiagnostics,
+    graph::{command_names, function_names, ContentReport, NodeGraph},
+    ids::LineId,
+    locale::YarnManifestLocales,
+};
+
+/// The major version of Yarn Spinner compiled programs that can be loaded
+pub const SUPPORTED_PROGRAM_VERSION: u32 = 2;
+
+/// Decodes only the language version from a compiled program. This field does
+/// not exist in v2.x programs, so it decodes as zero for those.
+#[derive(Clone, PartialEq, Message)]
+struct ProgramHeader {
+    #[prost(int32, tag = "4")]
+    language_version: i32,
+}
+
+/// Decodes only the node headers from a compiled program, as these aren't decoded into
+/// the nodes of a [Program]
+#[derive(Clone, PartialEq, Message)]
+struct ProgramNodeHeaders {
+    #[prost(map = "string, message", tag = "2")]
+    nodes: HashMap<String, NodeHeaderList>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct NodeHeaderList {
+    #[prost(message, repeated, tag = "6")]
+    headers: Vec<NodeHeader>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct NodeHeader {
+    #[prost(string, tag = "1")]
+    key: String,
+    #[prost(string, tag = "2")]
+    value: String,
+}
+
+/// The headers of each node in a program, such as `title: Start`, by node name and then by
+/// header key
+pub type NodeHeaders = HashMap<String, HashMap<String, String>>;
+
+/// The node header used as the display title of a node, see [BevyYarnProgram::node_title]
+pub const TITLE_HEADER: &str = "title";
+
+/// The node header that lists the tags of a node, separated by spaces
+pub const TAGS_HEADER: &str = "tags";
+
+/// Decodes the headers of each node from a compiled program
+pub(crate) fn decode_node_headers(bytes: &[u8]) -> Result<NodeHeaders, prost::DecodeError> {
+    Ok(ProgramNodeHeaders::decode(bytes)?
+        .nodes
+        .into_iter()
+        .map(|(name, node)| {
+            let headers = node
+                .headers
+                .into_iter()
+                .map(|header| (header.key, header.value))
+                .collect();
+            (name, headers)
+        })
+        .collect())
+}
+
 /// A newtype wrapping a yarn spinner program that can be loaded
 /// into the bevy engine.
 #[derive(Debug, TypeUuid, TypePath)]
@@ -21,6 +128,12 @@ pub struct BevyYarnProgram {
     /// The program loaded from the yarnc file
     pub program: Program,
 
+    /// The major Yarn Spinner version of the compiled program
+    pub format_version: u32,
+
+    /// The headers of each node in the program, see [BevyYarnProgram::node_headers]
+    pub headers: NodeHeaders,
+
     /// A handle for the string table for this yarnc file
     pub string_table: Handle<BevyYarnStringTable>,
 
@@ -28,21 +141,163 @@ pub struct BevyYarnProgram {
     pub metadata_table: Handle<BevyYarnMetadataTable>,
 }
 
+impl BevyYarnProgram {
+    /// Exports the node graph of the program in Graphviz DOT format. Nodes are drawn as
+    /// boxes with edges for jumps between them. If a string table is provided, edges that
+    /// follow an option are labelled with the (truncated) option text.
+    ///
+    /// Nodes that can't be reached from the `Start` node are grey, jumps to missing nodes are
+    /// red, and jumps that form cycles are dashed. Jumps to nodes computed at runtime can't
+    /// be resolved, so nodes containing them are annotated instead.
+    pub fn to_dot(&self, string_table: Option<&BevyYarnStringTable>) -> String {
+        NodeGraph::new(&self.program).to_dot(string_table)
+    }
+
+    /// Statically analyses the program's node graph from the given start node, reporting
+    /// nodes that can't be reached, nodes with no exit and jumps to nodes that don't exist.
+    /// This walks the compiled instructions without running the program, so can be used
+    /// to check content in CI.
+    pub fn content_report(&self, start_node: &str) -> ContentReport {
+        NodeGraph::new(&self.program).content_report(start_node)
+    }
+
+    /// Lists the names of the functions the program calls, sorted and without duplicates.
+    /// This includes functions provided by the virtual machine such as `visited`, but not
+    /// the operator functions like `Number.Add` that expressions are compiled to.
+    pub fn referenced_functions(&self) -> Vec<String> {
+        function_names(&self.program).into_iter().collect()
+    }
+
+    /// Gets the headers of the given node, such as `title: Start`, by header key. Returns
+    /// `None` if the program has no node with that name.
+    pub fn node_headers(&self, node: &str) -> Option<&HashMap<String, String>> {
+        self.headers.get(node)
+    }
+
+    /// Gets the display title of the given node from its [TITLE_HEADER] header, falling back
+    /// to the node name if the node has no title header
+    pub fn node_title<'a>(&'a self, node: &'a str) -> &'a str {
+        node_title(&self.headers, node)
+    }
+
+    /// The initial values of the variables declared with `<<declare>>` in the program, by
+    /// variable name including the leading `$`
+    pub fn initial_values(&self) -> HashMap<String, YarnValue> {
+        self.program
+            .initial_values
+            .iter()
+            .filter_map(|(name, operand)| {
+                let value = match operand.value.as_ref()? {
+                    Value::StringValue(value) => YarnValue::Str(value.clone()),
+                    Value::BoolValue(value) => YarnValue::Bool(*value),
+                    Value::FloatValue(value) => YarnValue::Number(*value),
+                };
+
+                Some((name.clone(), value))
+            })
+            .collect()
+    }
+
+    /// Lists the names of the commands the program runs, sorted and without duplicates.
+    /// Commands whose name is built from an expression can't be determined without running
+    /// the program, so are not included.
+    pub fn referenced_commands(&self) -> Vec<String> {
+        command_names(&self.program).into_iter().collect()
+    }
+}
+
+/// Gets the display title of a node from the node headers, see [BevyYarnProgram::node_title]
+pub(crate) fn node_title<'a>(headers: &'a NodeHeaders, node: &'a str) -> &'a str {
+    headers
+        .get(node)
+        .and_then(|headers| headers.get(TITLE_HEADER))
+        .map_or(node, String::as_str)
+}
+
+/// Gets the path of a table next to a yarnc file, for instance `story.lines.csv` for the
+/// `lines` table of `story.yarnc`. With the `ysc-table-names` feature, the `lines` and
+/// `metadata` tables are the file names written by the Yarn Spinner Console instead,
+/// `story-Lines.csv` and `story-Metadata.csv`, which the [BevyYarnProjectAssetLoader] reads
+/// itself.
 pub(crate) fn get_table_pathbuf_from_yarnc_path<P>(yarnc_path: P, prefix: &str) -> PathBuf
 where
     P: Into<PathBuf>,
 {
     let mut pb: PathBuf = yarnc_path.into();
-    pb.set_file_name(format!(
-        "{}.{prefix}.csv",
-        pb.file_stem().unwrap().to_str().unwrap()
-    ));
+    let stem = pb.file_stem().unwrap().to_str().unwrap();
+    let file_name = match prefix {
+        #[cfg(feature = "ysc-table-names")]
+        "lines" => format!("{stem}-Lines.csv"),
+        #[cfg(feature = "ysc-table-names")]
+        "metadata" => format!("{stem}-Metadata.csv"),
+        _ => format!("{stem}.{prefix}.csv"),
+    };
+    pb.set_file_name(file_name);
     pb
 }
 
+/// Decodes a compiled program, checking the program version first as newer programs
+/// decode without error but can't be run correctly. Errors give the path of the program.
+pub(crate) fn decode_program(
+    bytes: &[u8],
+    path: &Path,
+) -> Result<(Program, u32), bevy::asset::Error> {
+    let decode_error = |e: prost::DecodeError| {
+        let message = format!(
+            "Failed to decode Yarn program from '{}': {e}",
+            path.display()
+        );
+        error!("{message}");
+        bevy::asset::Error::msg(message)
+    };
+
+    let format_version = match ProgramHeader::decode(bytes)
+        .map_err(decode_error)?
+        .language_version
+    {
+        version if version <= 0 => SUPPORTED_PROGRAM_VERSION,
+        version => version as u32,
+    };
+
+    if format_version != SUPPORTED_PROGRAM_VERSION {
+        let message = format!(
+            "[{path:?}] Expected Yarn Spinner v{SUPPORTED_PROGRAM_VERSION}.x program, got v{format_version}.x"
+        );
+        error!("{message}");
+        return Err(bevy::asset::Error::msg(message));
+    }
+
+    Ok((
+        Program::decode(bytes).map_err(decode_error)?,
+        format_version,
+    ))
+}
+
+/// Describes a string or metadata table that couldn't be parsed, with its path and the row
+/// of the file the error is on if known
+pub(crate) fn describe_table_error(path: &Path, error: &csv::Error) -> String {
+    match error.position() {
+        Some(position) => format!(
+            "Failed to parse table '{}' at row {}: {error}",
+            path.display(),
+            position.line()
+        ),
+        None => format!("Failed to parse table '{}': {error}", path.display()),
+    }
+}
+
 /// A custom loader for BevyYarnProgram assets.
+///
+/// The string and metadata tables next to the yarnc file are loaded by their own loaders, as
+/// `story.lines.csv` and `story.metadata.csv`. With the `ysc-table-names` feature they are
+/// read from `story-Lines.csv` and `story-Metadata.csv` by this loader instead, as labeled
+/// `lines` and `metadata` assets of the program such as `story.yarnc#lines`, because both
+/// names end in `.csv`, which bevy can't tell apart by extension.
 #[derive(Default)]
-pub struct BevyYarnProjectAssetLoader;
+pub struct BevyYarnProjectAssetLoader {
+    #[cfg_attr(not(feature = "ysc-table-names"), allow(dead_code))]
+    pub(crate) diagnostics: YarnLoaderDiagnostics,
+}
 
 impl AssetLoader for BevyYarnProjectAssetLoader {
     fn load<'a>(
@@ -52,28 +307,61 @@ impl AssetLoader for BevyYarnProjectAssetLoader {
     ) -> bevy::utils::BoxedFuture<'a, Result<(), bevy::asset::Error>> {
         Box::pin(async move {
             // First load in the program from the yarnc file
-            let program = Program::decode(bytes)?;
+            let (program, format_version) = decode_program(bytes, load_context.path())?;
+            let headers = decode_node_headers(bytes)?;
+
+            // Next load the string table, it should have the name `<yarnc-file-name>.lines.csv`
+            #[cfg(not(feature = "ysc-table-names"))]
+            let (string_table, metadata_table, dependencies) = {
+                let path = get_table_pathbuf_from_yarnc_path(load_context.path(), "lines");
+                let string_asset_path = AssetPath::new(path, None);
+                let string_table: Handle<BevyYarnStringTable> =
+                    load_context.get_handle(string_asset_path.clone());
+
+                // Next load the metadata table, it should have the name `<yarnc-file-name>.metadata.csv`
+                let path = get_table_pathbuf_from_yarnc_path(load_context.path(), "metadata");
+                let metadata_asset_path = AssetPath::new(path, None);
+                let metadata_table: Handle<BevyYarnMetadataTable> =
+                    load_context.get_handle(metadata_asset_path.clone());
+
+                (
+                    string_table,
+                    metadata_table,
+                    vec![string_asset_path, metadata_asset_path],
+                )
+            };
+
+            // The ysc names of both tables end in `.csv`, so they are read here as labeled
+            // assets rather than by their own loaders
+            #[cfg(feature = "ysc-table-names")]
+            let (string_table, metadata_table, dependencies) = {
+                let path = get_table_pathbuf_from_yarnc_path(load_context.path(), "lines");
+                let bytes = load_context.read_asset_bytes(&path).await?;
+                let string_table = BevyYarnStringTable::from_csv(&bytes)
+                    .map_err(|e| self.diagnostics.table_error(&path, &e))?;
 
-            // Next load the string table, it should have the name `<yarnc-file-name>-Lines.csv`
-            let path = get_table_pathbuf_from_yarnc_path(load_context.path(), "lines");
-            let string_asset_path = AssetPath::new(path, None);
-            let string_table: Handle<BevyYarnStringTable> =
-                load_context.get_handle(string_asset_path.clone());
+                let path = get_table_pathbuf_from_yarnc_path(load_context.path(), "metadata");
+                let bytes = load_context.read_asset_bytes(&path).await?;
+                let metadata_table = BevyYarnMetadataTable::from_csv(&bytes)
+                    .map_err(|e| self.diagnostics.table_error(&path, &e))?;
 
-            // Next load the metadata table, it should have the name `<yarnc-file-name>-Metadata.csv`
-            let path = get_table_pathbuf_from_yarnc_path(load_context.path(), "metadata");
-            let metadata_asset_path = AssetPath::new(path, None);
-            let metadata_table: Handle<BevyYarnMetadataTable> =
-                load_context.get_handle(metadata_asset_path.clone());
+                (
+                    load_context.set_labeled_asset("lines", LoadedAsset::new(string_table)),
+                    load_context.set_labeled_asset("metadata", LoadedAsset::new(metadata_table)),
+                    Vec::<AssetPath>::new(),
+                )
+            };
 
             // Finally set all the loaded assets and mark the tables as dependencies
             load_context.set_default_asset(
                 LoadedAsset::new(BevyYarnProgram {
                     program,
+                    format_version,
+                    headers,
                     string_table,
                     metadata_table,
                 })
-                .with_dependencies(vec![string_asset_path, metadata_asset_path]),
+                .with_dependencies(dependencies),
             );
 
             Ok(())
@@ -85,45 +373,539 @@ impl AssetLoader for BevyYarnProjectAssetLoader {
     }
 }
 
+/// A custom loader that merges the yarnc files listed in a `.yarnmanifest` file into one
+/// BevyYarnProgram, see the [module documentation](self#multiple-yarnc-files).
+#[derive(Default)]
+pub struct BevyYarnManifestAssetLoader {
+    pub(crate) diagnostics: YarnLoaderDiagnostics,
+    pub(crate) locales: YarnManifestLocales,
+}
+
+impl AssetLoader for BevyYarnManifestAssetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut bevy::asset::LoadContext,
+    ) -> bevy::utils::BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let yarnc_paths: Vec<String> = serde_json::from_slice(bytes)?;
+            let directory = load_context
+                .path()
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default();
+
+            let mut program = Program::default();
+            let mut headers = NodeHeaders::new();
+            let mut string_table = BevyYarnStringTable::default();
+            let mut metadata_table = BevyYarnMetadataTable::default();
+            let locales = self.locales.translated();
+            let mut locale_tables: HashMap<String, BevyYarnStringTable> = HashMap::new();
+
+            for yarnc_path in yarnc_paths {
+                let yarnc_path = directory.join(yarnc_path);
+                let bytes = load_context.read_asset_bytes(&yarnc_path).await?;
+                let (file_program, _) = decode_program(&bytes, &yarnc_path)?;
+                headers.extend(decode_node_headers(&bytes)?);
+
+                for (name, node) in file_program.nodes {
+                    if program.nodes.contains_key(&name) {
+                        let message = format!(
+                            "[{:?}] Node {name} in {yarnc_path:?} is already defined in another yarnc file",
+                            load_context.path()
+                        );
+                        error!("{message}");
+                        return Err(bevy::asset::Error::msg(message));
+                    }
+
+                    program.nodes.insert(name, node);
+                }
+                program.initial_values.extend(file_program.initial_values);
+
+                let table_path = get_table_pathbuf_from_yarnc_path(&yarnc_path, "lines");
+                let bytes = load_context.read_asset_bytes(&table_path).await?;
+                let file_table = BevyYarnStringTable::from_csv(&bytes)
+                    .map_err(|e| self.diagnostics.table_error(&table_path, &e))?;
+                string_table.merge(file_table);
+
+                let table_path = get_table_pathbuf_from_yarnc_path(&yarnc_path, "metadata");
+                let bytes = load_context.read_asset_bytes(&table_path).await?;
+                let file_table = BevyYarnMetadataTable::from_csv(&bytes)
+                    .map_err(|e| self.diagnostics.table_error(&table_path, &e))?;
+                metadata_table.0.extend(file_table.0);
+
+                for locale in &locales {
+                    let table_path =
+                        get_table_pathbuf_from_yarnc_path(&yarnc_path, &format!("{locale}.lines"));
+
+                    // the file may not have been translated into this locale yet
+                    let Ok(bytes) = load_context.read_asset_bytes(&table_path).await else {
+                        continue;
+                    };
+                    let file_table = BevyYarnStringTable::from_csv(&bytes)
+                        .map_err(|e| self.diagnostics.table_error(&table_path, &e))?;
+                    locale_tables
+                        .entry(locale.clone())
+                        .or_default()
+                        .merge(file_table);
+                }
+            }
+
+            for (locale, table) in locale_tables {
+                load_context.set_labeled_asset(&format!("{locale}.lines"), LoadedAsset::new(table));
+            }
+
+            let string_table =
+                load_context.set_labeled_asset("lines", LoadedAsset::new(string_table));
+            let metadata_table =
+                load_context.set_labeled_asset("metadata", LoadedAsset::new(metadata_table));
+
+            load_context.set_default_asset(LoadedAsset::new(BevyYarnProgram {
+                program,
+                format_version: SUPPORTED_PROGRAM_VERSION,
+                headers,
+                string_table,
+                metadata_table,
+            }));
+
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["yarnmanifest"]
+    }
+}
+
+/// The environment variable that sets the path of the Yarn Spinner Console used by the
+/// [BevyYarnSourceAssetLoader], defaulting to `ysc` on the `PATH`
+#[cfg(all(feature = "compile-on-load", not(target_arch = "wasm32")))]
+pub const YSC_PATH_VAR: &str = "BEVY_MOD_YARN_YSC";
+
+/// A custom loader that compiles `.yarn` source files into BevyYarnProgram assets when they
+/// are loaded, so yarn files can be used without a build step. The string and metadata
+/// tables are loaded from the compiler output as part of the program asset.
+///
+/// This runs the Yarn Spinner Console (`ysc`), which must be installed separately, see
+/// [YSC_PATH_VAR]. The compiler writes its output to a temporary directory that is removed
+/// once the tables have been read. Compiling takes much longer than loading a yarnc file
+/// and blocks an IO thread while it runs, so this is intended for development. Not
+/// available on wasm.
+#[cfg(all(feature = "compile-on-load", not(target_arch = "wasm32")))]
+#[derive(Default)]
+pub struct BevyYarnSourceAssetLoader;
+
+#[cfg(all(feature = "compile-on-load", not(target_arch = "wasm32")))]
+impl BevyYarnSourceAssetLoader {
+    /// Compiles the yarn source with `ysc`, returning the yarnc, lines and metadata files
+    fn compile(source: &[u8], name: &str) -> Result<[Vec<u8>; 3], bevy::asset::Error> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static COMPILE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        let directory = std::env::temp_dir().join(format!(
+            "bevy_mod_yarn-{}-{}",
+            std::process::id(),
+            COMPILE_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&directory)?;
+
+        let result = (|| -> Result<[Vec<u8>; 3], bevy::asset::Error> {
+            let source_path = directory.join(format!("{name}.yarn"));
+            std::fs::write(&source_path, source)?;
+
+            let ysc = std::env::var(YSC_PATH_VAR).unwrap_or_else(|_| "ysc".into());
+            let output = std::process::Command::new(&ysc)
+                .arg("compile")
+                .arg("--output-directory")
+                .arg(&directory)
+                .arg("--output-name")
+                .arg(name)
+                .arg(&source_path)
+                .output()
+                .map_err(|e| bevy::asset::Error::msg(format!("Unable to run {ysc}: {e}")))?;
+
+            if !output.status.success() {
+                return Err(bevy::asset::Error::msg(format!(
+                    "Unable to compile {name}.yarn: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                )));
+            }
+
+            Ok([
+                std::fs::read(directory.join(format!("{name}.yarnc")))?,
+                std::fs::read(directory.join(format!("{name}-Lines.csv")))?,
+                std::fs::read(directory.join(format!("{name}-Metadata.csv")))?,
+            ])
+        })();
+
+        if let Err(e) = std::fs::remove_dir_all(&directory) {
+            warn!("Unable to remove yarn compiler output at {directory:?}: {e}");
+        }
+
+        result
+    }
+}
+
+#[cfg(all(feature = "compile-on-load", not(target_arch = "wasm32")))]
+impl AssetLoader for BevyYarnSourceAssetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut bevy::asset::LoadContext,
+    ) -> bevy::utils::BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let name = load_context
+                .path()
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or("program")
+                .to_owned();
+
+            let [yarnc, lines, metadata] = Self::compile(bytes, &name).map_err(|e| {
+                error!("[{:?}] {e}", load_context.path());
+                e
+            })?;
+
+            let (program, format_version) = decode_program(&yarnc, load_context.path())?;
+            let headers = decode_node_headers(&yarnc)?;
+            let string_table = load_context.set_labeled_asset(
+                "lines",
+                LoadedAsset::new(BevyYarnStringTable::from_csv(&lines)?),
+            );
+            let metadata_table = load_context.set_labeled_asset(
+                "metadata",
+                LoadedAsset::new(BevyYarnMetadataTable::from_csv(&metadata)?),
+            );
+
+            load_context.set_default_asset(LoadedAsset::new(BevyYarnProgram {
+                program,
+                format_version,
+                headers,
+                string_table,
+                metadata_table,
+            }));
+
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["yarn"]
+    }
+}
+
+/// A line in a [BevyYarnStringTable], with its text split from its character once when it
+/// is added, and shared so lines without substitutions can be said without copying it
+#[derive(Debug, Clone)]
+struct TableLine {
+    info: LineInfo,
+    raw_text: Arc<str>,
+    character: Option<String>,
+    text: Arc<str>,
+}
+
+impl From<LineInfo> for TableLine {
+    fn from(info: LineInfo) -> Self {
+        let (character, text) = BevyYarnStringTable::split_character(&info.text);
+        let text = text.into();
+
+        Self {
+            raw_text: info.text.as_str().into(),
+            info,
+            character,
+            text,
+        }
+    }
+}
+
+/// The lines in a [BevyYarnStringTable], keyed by line id
+#[cfg(not(feature = "optimized-tables"))]
+type TableLines = HashMap<LineId, TableLine>;
+
+/// The lines in a [BevyYarnStringTable], keyed by line id
+#[cfg(feature = "optimized-tables")]
+type TableLines = InternedLines;
+
+/// Lines stored contiguously in a [Vec] with their ids, and a map from line id to index.
+/// Lookups by id cost the same as a [HashMap], but iterating all lines is faster and the
+/// lines have better cache locality. Provides the subset of the [HashMap] API used with
+/// string tables.
+#[cfg(feature = "optimized-tables")]
+#[derive(Default, Debug, Clone)]
+struct InternedLines {
+    lines: Vec<(LineId, TableLine)>,
+    index: HashMap<LineId, usize>,
+}
+
+#[cfg(feature = "optimized-tables")]
+impl InternedLines {
+    /// Gets the line with the given id
+    fn get(&self, id: &str) -> Option<&TableLine> {
+        self.index.get(id).map(|index| &self.lines[*index].1)
+    }
+
+    /// Adds or replaces the line with the given id
+    fn insert(&mut self, id: LineId, line: TableLine) {
+        match self.index.get(&id) {
+            Some(index) => self.lines[*index].1 = line,
+            None => {
+                self.index.insert(id.clone(), self.lines.len());
+                self.lines.push((id, line));
+            }
+        }
+    }
+
+    /// The number of lines
+    fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Whether there are no lines
+    fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Iterates over the line ids and lines, in the order they were added
+    fn iter(&self) -> impl Iterator<Item = (&LineId, &TableLine)> {
+        self.lines.iter().map(|(id, line)| (id, line))
+    }
+
+    /// Iterates over the lines, in the order they were added
+    fn values(&self) -> impl Iterator<Item = &TableLine> {
+        self.lines.iter().map(|(_, line)| line)
+    }
+}
+
+#[cfg(feature = "optimized-tables")]
+impl Extend<(LineId, TableLine)> for InternedLines {
+    fn extend<T: IntoIterator<Item = (LineId, TableLine)>>(&mut self, iter: T) {
+        for (id, line) in iter {
+            self.insert(id, line);
+        }
+    }
+}
+
+#[cfg(feature = "optimized-tables")]
+impl FromIterator<(LineId, TableLine)> for InternedLines {
+    fn from_iter<T: IntoIterator<Item = (LineId, TableLine)>>(iter: T) -> Self {
+        let mut lines = Self::default();
+        lines.extend(iter);
+        lines
+    }
+}
+
+#[cfg(feature = "optimized-tables")]
+impl IntoIterator for InternedLines {
+    type Item = (LineId, TableLine);
+    type IntoIter = std::vec::IntoIter<(LineId, TableLine)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.lines.into_iter()
+    }
+}
+
+/// Converts the contents of a table file to UTF-8 without a byte order mark, as tables
+/// exported from spreadsheets often start with one, which would otherwise end up in the
+/// first column name.
+///
+/// Without the `encoding` feature tables must be UTF-8, and only the byte order mark is
+/// removed. With the `encoding` feature, UTF-16 tables with a byte order mark are also
+/// supported, and tables without one that aren't valid UTF-8 are read as Windows-1252.
+pub fn decode_table(bytes: &[u8]) -> Cow<'_, [u8]> {
+    #[cfg(not(feature = "encoding"))]
+    {
+        Cow::Borrowed(bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes))
+    }
+
+    #[cfg(feature = "encoding")]
+    {
+        let encoding = match encoding_rs::Encoding::for_bom(bytes) {
+            Some((encoding, _)) => encoding,
+            None if std::str::from_utf8(bytes).is_ok() => encoding_rs::UTF_8,
+            None => encoding_rs::WINDOWS_1252,
+        };
+
+        // decoding also removes the byte order mark
+        match encoding.decode(bytes).0 {
+            Cow::Borrowed(text) => Cow::Borrowed(text.as_bytes()),
+            Cow::Owned(text) => Cow::Owned(text.into_bytes()),
+        }
+    }
+}
+
 /// A resource to contain the string table
+///
+/// The lines are keyed by line id. With the `optimized-tables` feature they are stored
+/// contiguously, which is faster to iterate for large tables, but the methods are the same
+/// with either layout.
+///
+/// This is a breaking change from earlier versions, where the lines were the public
+/// `HashMap` field `.0`. The lines are now private with either layout, as each line also
+/// caches its character and shared text. Use [BevyYarnStringTable::line_info],
+/// [BevyYarnStringTable::iter] and [BevyYarnStringTable::len] to read them, and
+/// [BevyYarnStringTable::apply_patch] and [BevyYarnStringTable::merge] to change them.
 #[derive(Default, Debug, TypeUuid, TypePath)]
 #[uuid = "d11069b5-98c8-4db0-8616-58d86ee1deb3"]
-pub struct BevyYarnStringTable(pub HashMap<String, LineInfo>);
+pub struct BevyYarnStringTable {
+    lines: TableLines,
+}
 
 impl BevyYarnStringTable {
-    /// Finds the string for a line from the given string table
-    fn find_string_in_table(&self, id: &String) -> String {
-        if let Some(text) = self.0.get(id).map(|line_info| line_info.text.clone()) {
-            text
-        } else {
-            warn!("Line id {id} missing from string table. Skipping");
-            format!("<missing_string: {id}>")
+    /// Gets the raw string table entry for the given line ID, including the source file,
+    /// node and line number columns from the CSV file.
+    pub fn line_info(&self, id: &str) -> Option<&LineInfo> {
+        self.lines.get(id).map(|line| &line.info)
+    }
+
+    /// The number of lines in the string table
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Whether the string table has no lines
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Iterates over the line ids and string table entries, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&LineId, &LineInfo)> {
+        self.lines.iter().map(|(id, line)| (id, &line.info))
+    }
+
+    /// Gets the text of the string table entry for the given line ID, before substitutions
+    /// are completed and format functions are expanded
+    pub fn raw_text(&self, id: &str) -> Option<&str> {
+        self.line_info(id).map(|line_info| line_info.text.as_str())
+    }
+
+    /// The text of the string table entry for the given line ID, shared with the table
+    pub(crate) fn shared_raw_text(&self, id: &str) -> Option<Arc<str>> {
+        self.lines.get(id).map(|line| line.raw_text.clone())
+    }
+
+    /// The names of every character that speaks a line in the string table, for instance
+    /// for building a list of voice actors
+    pub fn character_name_set(&self) -> HashSet<String> {
+        self.lines
+            .values()
+            .filter_map(|line| line.character.clone())
+            .collect()
+    }
+
+    /// The number of lines in the string table spoken by the given character
+    pub fn line_count_for_character(&self, name: &str) -> usize {
+        self.lines
+            .values()
+            .filter(|line| line.character.as_deref() == Some(name))
+            .count()
+    }
+
+    /// Adds or replaces only the given entries in the string table, leaving the
+    /// rest of the table untouched
+    pub fn apply_patch(&mut self, patch: Vec<(LineId, LineInfo)>) {
+        self.lines
+            .extend(patch.into_iter().map(|(id, info)| (id, info.into())));
+    }
+
+    /// Adds or replaces the entries of the given string table, for instance to combine the
+    /// tables of several yarn files into one
+    pub fn merge(&mut self, other: BevyYarnStringTable) {
+        self.lines.extend(other.lines);
+    }
+
+    /// Finds the character and the rest of the string for a line from the given string
+    /// table, using the policy if the line is missing. Missing lines are logged by the
+    /// [crate::YarnPlugin] systems, see [crate::prelude::YarnMissingLines]
+    fn find_string_in_table<'a>(
+        &'a self,
+        id: &str,
+        missing_string_policy: &YarnMissingStringPolicy,
+    ) -> (Option<String>, Cow<'a, str>) {
+        match self.lines.get(id) {
+            Some(line) => (line.character.clone(), Cow::Borrowed(&line.text)),
+            None => Self::extract_character(missing_string_policy.missing_text(id)),
         }
     }
 
     /// Completes variable substitutions in the given string
-    fn perform_variable_substitutions(initial: String, substitutions: &[String]) -> String {
-        substitutions
-            .iter()
-            .enumerate()
-            .fold(initial, |current, (idx, next_sub)| {
-                current.replace(&format!("{{{idx}}}"), next_sub)
-            })
+    fn perform_variable_substitutions<'a>(
+        initial: Cow<'a, str>,
+        substitutions: &[String],
+    ) -> Cow<'a, str> {
+        if substitutions.is_empty() {
+            return initial;
+        }
+
+        Cow::Owned(
+            substitutions
+                .iter()
+                .enumerate()
+                .fold(initial.into_owned(), |current, (idx, next_sub)| {
+                    current.replace(&format!("{{{idx}}}"), next_sub)
+                }),
+        )
+    }
+
+    /// Completes named substitutions such as `{player_name}` in the given string. These are
+    /// applied after positional substitutions, and missing names are replaced with an empty string.
+    fn perform_named_substitutions<'a>(
+        initial: Cow<'a, str>,
+        named_substitutions: &BevyYarnNamedSubstitutions,
+    ) -> Cow<'a, str> {
+        let named_regex: Regex = Regex::new(r"\{([a-zA-Z_][a-zA-Z0-9_]*)\}").unwrap();
+
+        if !named_regex.is_match(&initial) {
+            return initial;
+        }
+
+        Cow::Owned(
+            named_regex
+                .replace_all(&initial, |captures: &regex::Captures| {
+                    let name = &captures[1];
+                    match named_substitutions.0.get(name) {
+                        Some(value) => value.clone(),
+                        None => {
+                            warn!("Named substitution {{{name}}} has no value. Skipping");
+                            String::new()
+                        }
+                    }
+                })
+                .into_owned(),
+        )
     }
 
     /// Pulls out the character (if any) from the given formatted string.
-    /// Characters are represented by e.g. "character 1 name: line" in the yarn file
-    fn extract_character(formatted_text: String) -> (Option<String>, String) {
+    /// Characters are represented by e.g. "character 1 name: line" in the yarn file.
+    /// The [crate::prelude::BevyYarnLine::actor_type] is found from the character once the
+    /// line's tags are known, see [crate::prelude::YarnActorConfig].
+    pub(crate) fn extract_character(
+        formatted_text: Cow<'_, str>,
+    ) -> (Option<String>, Cow<'_, str>) {
+        match formatted_text {
+            Cow::Borrowed(text) => {
+                let (character, text) = Self::split_character(text);
+                (character, Cow::Borrowed(text))
+            }
+            Cow::Owned(text) => {
+                let (character, remaining) = Self::split_character(&text);
+                (character, Cow::Owned(remaining.to_owned()))
+            }
+        }
+    }
+
+    /// Splits the character (if any) from the rest of the text
+    fn split_character(formatted_text: &str) -> (Option<String>, &str) {
         let character_regex: Regex = Regex::new(r"([a-zA-Z0-9]+:)?\s*(.*)").unwrap();
 
-        match character_regex.captures(&formatted_text) {
+        match character_regex.captures(formatted_text) {
             Some(captures) => {
                 if captures.len() == 3 {
                     (
                         captures
                             .get(1)
                             .map(|val| val.as_str().to_owned().replace(':', "")),
-                        captures.get(2).unwrap().as_str().to_owned(),
+                        captures.get(2).unwrap().as_str(),
                     )
                 } else {
                     (None, formatted_text)
@@ -133,18 +915,153 @@ impl BevyYarnStringTable {
         }
     }
 
-    /// Gets the final substituted and formatted text
-    pub fn get_final_text(&self, line: &Line, local_code: &str) -> (Option<String>, String) {
-        let initial = self.find_string_in_table(&line.id);
-        let (character, initial) = Self::extract_character(initial);
+    /// Parses a string table from the contents of a lines CSV file, see [decode_table] for
+    /// the supported encodings
+    pub fn from_csv(bytes: &[u8]) -> Result<Self, csv::Error> {
+        Reader::from_reader(decode_table(bytes).as_ref())
+            .deserialize()
+            .map(|result| {
+                result.map(|line_info: LineInfo| (line_info.id.clone().into(), line_info.into()))
+            })
+            .collect::<Result<TableLines, _>>()
+            .map(|lines| Self { lines })
+    }
+
+    /// Gets the final substituted and formatted text. The text is borrowed from the string
+    /// table if the line has no substitutions or format functions.
+    pub fn get_final_text(&self, line: &Line, local_code: &str) -> (Option<String>, Cow<'_, str>) {
+        self.get_final_text_with_named(line, local_code, &BevyYarnNamedSubstitutions::default())
+    }
+
+    /// Gets the final substituted and formatted text, also completing named substitutions
+    pub fn get_final_text_with_named(
+        &self,
+        line: &Line,
+        local_code: &str,
+        named_substitutions: &BevyYarnNamedSubstitutions,
+    ) -> (Option<String>, Cow<'_, str>) {
+        self.get_final_text_with_policy(
+            line,
+            local_code,
+            named_substitutions,
+            &YarnMissingStringPolicy::default(),
+        )
+    }
+
+    /// Gets the final substituted and formatted text, completing named substitutions and
+    /// using the given policy for the text of lines missing from the table
+    pub fn get_final_text_with_policy(
+        &self,
+        line: &Line,
+        local_code: &str,
+        named_substitutions: &BevyYarnNamedSubstitutions,
+        missing_string_policy: &YarnMissingStringPolicy,
+    ) -> (Option<String>, Cow<'_, str>) {
+        let (character, initial) = self.find_string_in_table(&line.id, missing_string_policy);
         let subbed_text = Self::perform_variable_substitutions(initial, &line.substitutions);
-        (character, expand_format_functions(&subbed_text, local_code))
+        let subbed_text = Self::perform_named_substitutions(subbed_text, named_substitutions);
+
+        // format functions such as `[plural ...]` are always in square brackets
+        if subbed_text.contains('[') {
+            (
+                character,
+                Cow::Owned(expand_format_functions(&subbed_text, local_code)),
+            )
+        } else {
+            (character, subbed_text)
+        }
+    }
+
+    /// Gets the final substituted and formatted text like
+    /// [BevyYarnStringTable::get_final_text_with_policy], sharing the string table's text
+    /// rather than copying it if the line has no substitutions or format functions
+    pub(crate) fn get_shared_final_text(
+        &self,
+        line: &Line,
+        local_code: &str,
+        named_substitutions: &BevyYarnNamedSubstitutions,
+        missing_string_policy: &YarnMissingStringPolicy,
+    ) -> (Option<String>, Arc<str>) {
+        let (character, text) = self.get_final_text_with_policy(
+            line,
+            local_code,
+            named_substitutions,
+            missing_string_policy,
+        );
+
+        let text = match (text, self.lines.get(line.id.as_str())) {
+            // the text is only borrowed if it is the table's text unchanged
+            (Cow::Borrowed(text), Some(table_line)) if std::ptr::eq(text, &*table_line.text) => {
+                table_line.text.clone()
+            }
+            (text, _) => text.into(),
+        };
+
+        (character, text)
     }
 }
 
+/// A resource containing values for named substitutions in lines, such as `{player_name}`
+/// or `{gold}`. These are often preferred by localisation teams over positional substitutions
+/// such as `{0}` as word order changes between languages. Positional substitutions are
+/// applied first.
+#[derive(Debug, Default, Clone, Resource)]
+pub struct BevyYarnNamedSubstitutions(pub HashMap<String, String>);
+
+/// A resource that controls the text shown for lines that are missing from the string table,
+/// for instance showing a loud placeholder in QA builds and an empty string when shipping.
+/// Defaults to the placeholder `<missing_string: {id}>`.
+#[derive(Debug, Clone, Resource)]
+pub enum YarnMissingStringPolicy {
+    /// Shows the given text, with `{id}` replaced by the line id
+    Placeholder(String),
+    /// Shows an empty string
+    Empty,
+    /// Shows the line id
+    LineId,
+    /// Panics, for catching missing lines in tests
+    Panic,
+}
+
+impl Default for YarnMissingStringPolicy {
+    fn default() -> Self {
+        Self::Placeholder("<missing_string: {id}>".into())
+    }
+}
+
+impl YarnMissingStringPolicy {
+    /// Gets the text to show for the missing line with the given id
+    fn missing_text<'a>(&self, id: &str) -> Cow<'a, str> {
+        match self {
+            Self::Placeholder(placeholder) => Cow::Owned(placeholder.replace("{id}", id)),
+            Self::Empty => Cow::Borrowed(""),
+            Self::LineId => Cow::Owned(id.to_owned()),
+            Self::Panic => panic!("Line id {id} missing from string table"),
+        }
+    }
+}
+
+/// What happens when the dialogue reaches a line that is missing from the string table,
+/// set with [crate::YarnPluginBuilder::with_missing_line_policy]. Choices with missing lines
+/// always use [MissingLinePolicy::Placeholder], as skipping a choice would change the
+/// options that can be selected.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MissingLinePolicy {
+    /// Says the line with the text from the [YarnMissingStringPolicy]
+    #[default]
+    Placeholder,
+    /// Continues the dialogue without saying the line
+    SkipLine,
+    /// Raises a [crate::prelude::BevyYarnError::MissingLine] error event and stops stepping
+    /// the dialogue at the missing line, without saying it
+    Error,
+}
+
 /// A custom loader for BevyYarnProgram assets.
 #[derive(Default)]
-pub struct BevyYarnStringTableAssetLoader;
+pub struct BevyYarnStringTableAssetLoader {
+    pub(crate) diagnostics: YarnLoaderDiagnostics,
+}
 
 impl AssetLoader for BevyYarnStringTableAssetLoader {
     fn load<'a>(
@@ -153,13 +1070,9 @@ impl AssetLoader for BevyYarnStringTableAssetLoader {
         load_context: &'a mut bevy::asset::LoadContext,
     ) -> bevy::utils::BoxedFuture<'a, Result<(), bevy::asset::Error>> {
         Box::pin(async move {
-            let string_table =
-                HashMap::from_iter(Reader::from_reader(bytes).deserialize().map(|result| {
-                    let res: LineInfo = result.unwrap();
-                    (res.id.clone(), res)
-                }));
-
-            load_context.set_default_asset(LoadedAsset::new(BevyYarnStringTable(string_table)));
+            let string_table = BevyYarnStringTable::from_csv(bytes)
+                .map_err(|e| self.diagnostics.table_error(load_context.path(), &e))?;
+            load_context.set_default_asset(LoadedAsset::new(string_table));
 
             Ok(())
         })
@@ -170,25 +1083,193 @@ impl AssetLoader for BevyYarnStringTableAssetLoader {
     }
 }
 
+/// Helpers for building string tables in tests, with the `test-utils` feature. These are
+/// for test fixtures only, and shouldn't be used in production code where tables are loaded
+/// from files.
+#[cfg(any(test, feature = "test-utils"))]
+impl BevyYarnStringTable {
+    /// Adds or replaces the line with the given id and text, returning the line the virtual
+    /// machine raises for it with the given substitutions. The line can be turned into a
+    /// [crate::prelude::BevyYarnLine] with a [crate::prelude::YarnTranslationContext]:
+    ///
+    /// ```ignore
+    /// let mut string_table = BevyYarnStringTable::default();
+    /// let line = string_table.insert_test_line("line:1", "Nice to meet you, {0}", vec!["Sam".into()]);
+    /// assert_eq!(&*context.line(&line).formatted_text, "Nice to meet you, Sam");
+    /// ```
+    pub fn insert_test_line(
+        &mut self,
+        id: impl Into<String>,
+        text: impl Into<String>,
+        substitutions: Vec<String>,
+    ) -> Line {
+        let id = id.into();
+        let text = text.into();
+        let header = ["id", "text", "file", "node", "lineNumber"];
+        let table = Self::from_csv(&test_csv(
+            &header,
+            &[id.as_str(), text.as_str(), "", "", "0"],
+        ))
+        .expect("test lines are valid csv");
+        self.merge(table);
+
+        Line { id, substitutions }
+    }
+}
+
+/// Writes a header and a single record as csv, so test entries are parsed the same way as
+/// tables loaded from files
+#[cfg(any(test, feature = "test-utils"))]
+fn test_csv(header: &[&str], record: &[&str]) -> Vec<u8> {
+    let mut writer = csv::WriterBuilder::new()
+        .flexible(true)
+        .from_writer(Vec::new());
+    for record in [header, record] {
+        writer
+            .write_record(record)
+            .expect("writing csv to memory doesn't fail");
+    }
+    writer
+        .into_inner()
+        .expect("writing csv to memory doesn't fail")
+}
+
 /// A resource to contain the metadata table
 #[derive(Default, Debug, TypeUuid, TypePath)]
 #[uuid = "42073437-7c2b-4526-859c-f1b059881c67"]
-pub struct BevyYarnMetadataTable(pub HashMap<String, MetadataInfo>);
+pub struct BevyYarnMetadataTable(pub HashMap<LineId, MetadataInfo>);
 
 impl BevyYarnMetadataTable {
+    /// Parses a metadata table from the contents of a metadata CSV file, see [decode_table]
+    /// for the supported encodings
+    pub fn from_csv(bytes: &[u8]) -> Result<Self, csv::Error> {
+        ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(decode_table(bytes).as_ref())
+            .deserialize()
+            .map(|result| result.map(|info: MetadataInfo| (info.id.clone().into(), info)))
+            .collect::<Result<HashMap<_, _>, _>>()
+            .map(Self)
+    }
+
+    /// Adds or replaces the metadata of the line with the given id, with tags written
+    /// without the leading `#`, such as `mood:happy`. This is for test fixtures only, with
+    /// the `test-utils` feature, and shouldn't be used in production code.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn insert_test_metadata(&mut self, id: impl Into<String>, tags: Vec<String>) {
+        let id = id.into();
+        let mut record = vec![id.as_str(), "", "0"];
+        record.extend(tags.iter().map(String::as_str));
+
+        let header = ["id", "node", "lineNumber", "tags"];
+        let table =
+            Self::from_csv(&test_csv(&header, &record)).expect("test metadata is valid csv");
+        self.0.extend(table.0);
+    }
+
     /// Gets the tags associated with a given line, if any
     pub fn get_tags_for_line(&self, line: &Line) -> Vec<String> {
         self.0
-            .get(&line.id)
+            .get(line.id.as_str())
             .map(|metadata_info| &metadata_info.tags)
             .cloned()
             .unwrap_or_default()
     }
+
+    /// Gets the tags associated with a given line, split into free-form tags such as `#sad`
+    /// and a map of tags in the form `#key:value`, such as `#file:intro.yarn`, by key. See
+    /// [BevyYarnMetadataTable::get_tags_for_line] for all of the tags in a single list.
+    pub fn get_parsed_tags_for_line(&self, line: &Line) -> (Vec<String>, HashMap<String, String>) {
+        let mut tags = Vec::new();
+        let mut structured_tags = HashMap::new();
+
+        for tag in self.parse_tags_for_line(line) {
+            match tag {
+                YarnTag::Flag(tag) => tags.push(tag),
+                YarnTag::KeyValue { key, value } => {
+                    structured_tags.insert(key, value);
+                }
+            }
+        }
+
+        (tags, structured_tags)
+    }
+
+    /// Gets the tags associated with a given line parsed into [YarnTag]s, in the order they
+    /// appear in the metadata table. See [BevyYarnMetadataTable::get_tags_for_line] for the
+    /// raw tags.
+    pub fn parse_tags_for_line(&self, line: &Line) -> Vec<YarnTag> {
+        self.0
+            .get(line.id.as_str())
+            .into_iter()
+            .flat_map(|metadata_info| metadata_info.tags.iter())
+            .map(|tag| YarnTag::parse(tag))
+            .collect()
+    }
+
+    /// Gets the IDs of the lines in the given node, sorted by ID, for instance to start
+    /// streaming voice over for a node before its lines are said. The metadata table only
+    /// contains lines that have metadata such as tags, so lines without any are not included.
+    pub fn get_line_ids_in_node(&self, node_name: &str) -> Vec<&str> {
+        let mut line_ids = self
+            .0
+            .values()
+            .filter(|metadata_info| metadata_info.node == node_name)
+            .map(|metadata_info| metadata_info.id.as_str())
+            .collect::<Vec<_>>();
+        line_ids.sort_unstable();
+        line_ids
+    }
+}
+
+/// A tag on a line, parsed from the raw tags in a [BevyYarnMetadataTable]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum YarnTag {
+    /// A free-form tag such as `#sad`, without the leading `#`
+    Flag(String),
+    /// A tag in the form `#key:value`, such as `#emotion:happy`
+    KeyValue {
+        /// The part of the tag before the first `:`, without the leading `#`
+        key: String,
+        /// The part of the tag after the first `:`
+        value: String,
+    },
+}
+
+impl YarnTag {
+    /// Parses a raw tag, with or without its leading `#`. Tags containing a `:` are split
+    /// into a key and value on the first `:`, other tags are flags.
+    ///
+    /// ```
+    /// use bevy_mod_yarn::prelude::YarnTag;
+    ///
+    /// assert_eq!(
+    ///     YarnTag::parse("#emotion:happy"),
+    ///     YarnTag::KeyValue {
+    ///         key: "emotion".into(),
+    ///         value: "happy".into(),
+    ///     }
+    /// );
+    /// assert_eq!(YarnTag::parse("#sad"), YarnTag::Flag("sad".into()));
+    /// ```
+    pub fn parse(tag: &str) -> Self {
+        let tag = tag.strip_prefix('#').unwrap_or(tag);
+
+        match tag.split_once(':') {
+            Some((key, value)) if !key.is_empty() => Self::KeyValue {
+                key: key.to_owned(),
+                value: value.to_owned(),
+            },
+            _ => Self::Flag(tag.to_owned()),
+        }
+    }
 }
 
 /// A custom loader for BevyYarnProgram assets.
 #[derive(Default)]
-pub struct BevyYarnMetadataTableAssetLoader;
+pub struct BevyYarnMetadataTableAssetLoader {
+    pub(crate) diagnostics: YarnLoaderDiagnostics,
+}
 
 impl AssetLoader for BevyYarnMetadataTableAssetLoader {
     fn load<'a>(
@@ -197,22 +1278,10 @@ impl AssetLoader for BevyYarnMetadataTableAssetLoader {
         load_context: &'a mut bevy::asset::LoadContext,
     ) -> bevy::utils::BoxedFuture<'a, Result<(), bevy::asset::Error>> {
         Box::pin(async move {
-            let metadata_table = HashMap::from_iter(
-                ReaderBuilder::new()
-                    .flexible(true)
-                    .from_reader(bytes)
-                    .deserialize()
-                    .map(|result| {
-                        if result.is_err() {
-                            warn!("[{:?}] {result:?}\n", load_context.path());
-                        }
-
-                        let res: MetadataInfo = result.unwrap();
-                        (res.id.clone(), res)
-                    }),
-            );
+            let metadata_table = BevyYarnMetadataTable::from_csv(bytes)
+                .map_err(|e| self.diagnostics.table_error(load_context.path(), &e))?;
 
-            load_context.set_default_asset(LoadedAsset::new(BevyYarnMetadataTable(metadata_table)));
+            load_context.set_default_asset(LoadedAsset::new(metadata_table));
 
             Ok(())
         })
@@ -222,3 +1291,334 @@ impl AssetLoader for BevyYarnMetadataTableAssetLoader {
         &["metadata.csv"]
     }
 }
+
+/// A small set of string table entries that are patched into a loaded string table when
+/// the patch asset is loaded or changes, to avoid reloading the whole table when only
+/// a few lines change during hot reloading.
+///
+/// Patches are JSON files containing an array of string table entries, with the same
+/// fields as the lines CSV file. The patch for `story.lines.csv` must be named
+/// `story.patch.json` and be located in the same directory. With the `ysc-table-names`
+/// feature, the patch for `story-Lines.csv` is named `story-Lines.patch.json`. Patches are
+/// not loaded automatically, load the patch with the asset server and keep the handle.
+#[derive(Debug, TypeUuid, TypePath)]
+#[uuid = "8baecaba-f995-4dab-b52c-20e1aaea99d9"]
+pub struct StringTablePatch {
+    /// The entries to add or replace in the string table
+    pub lines: Vec<LineInfo>,
+
+    /// A handle for the string table this patch applies to
+    pub string_table: Handle<BevyYarnStringTable>,
+}
+
+/// A custom loader for StringTablePatch assets.
+#[derive(Default)]
+pub struct StringTablePatchAssetLoader;
+
+impl AssetLoader for StringTablePatchAssetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut bevy::asset::LoadContext,
+    ) -> bevy::utils::BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let lines: Vec<LineInfo> = serde_json::from_slice(bytes)?;
+
+            let name = load_context
+                .path()
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".patch.json"))
+                .ok_or_else(|| {
+                    bevy::asset::Error::msg(format!(
+                        "[{:?}] String table patches must be named <name>.patch.json",
+                        load_context.path()
+                    ))
+                })?;
+
+            // The patch for `story-Lines.csv` is named `story-Lines.patch.json`, and the
+            // table is loaded as the `lines` asset of `story.yarnc`
+            #[cfg(feature = "ysc-table-names")]
+            let table_path = name.strip_suffix("-Lines").map(|stem| {
+                AssetPath::new(
+                    load_context.path().with_file_name(format!("{stem}.yarnc")),
+                    Some("lines".into()),
+                )
+            });
+            #[cfg(not(feature = "ysc-table-names"))]
+            let table_path = None;
+
+            // The patch for `story.lines.csv` is named `story.patch.json`
+            let table_path = table_path.unwrap_or_else(|| {
+                AssetPath::new(
+                    load_context
+                        .path()
+                        .with_file_name(format!("{name}.lines.csv")),
+                    None,
+                )
+            });
+
+            let string_table = load_context.get_handle(table_path);
+            load_context.set_default_asset(LoadedAsset::new(StringTablePatch {
+                lines,
+                string_table,
+            }));
+
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["patch.json"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LOCALE;
+
+    #[test]
+    fn decodes_v2_programs() {
+        let (program, format_version) = decode_program(
+            include_bytes!("../assets/kitchen_sink.yarnc"),
+            Path::new("kitchen_sink.yarnc"),
+        )
+        .expect("kitchen sink example is a v2 program");
+
+        assert_eq!(format_version, SUPPORTED_PROGRAM_VERSION);
+        assert!(program.nodes.contains_key("Start"));
+    }
+
+    #[test]
+    fn rejects_v3_programs() {
+        // field 4, the language version, as a varint of 3
+        let bytes = [0x20, 0x03];
+
+        let error = decode_program(&bytes, Path::new("v3.yarnc"))
+            .expect_err("v3 programs aren't supported");
+
+        assert!(error
+            .to_string()
+            .ends_with("Expected Yarn Spinner v2.x program, got v3.x"));
+    }
+
+    #[test]
+    fn decode_errors_name_the_program() {
+        let error = decode_program(b"garbage", Path::new("dialogue/broken.yarnc"))
+            .expect_err("garbage isn't a program");
+
+        assert!(error
+            .to_string()
+            .starts_with("Failed to decode Yarn program from 'dialogue/broken.yarnc': "));
+    }
+
+    #[test]
+    fn table_errors_name_the_table_and_row() {
+        let csv = "id,text,file,node,lineNumber\nline:a,Hello,a.yarn,Start,1\nline:b\n";
+        let error = BevyYarnStringTable::from_csv(csv.as_bytes()).expect_err("row 3 is short");
+
+        assert!(
+            describe_table_error(Path::new("dialogue/broken.lines.csv"), &error)
+                .starts_with("Failed to parse table 'dialogue/broken.lines.csv' at row 3: ")
+        );
+    }
+
+    fn named(substitutions: &[(&str, &str)]) -> BevyYarnNamedSubstitutions {
+        BevyYarnNamedSubstitutions(
+            substitutions
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn applies_named_and_positional_substitutions() {
+        let mut table = BevyYarnStringTable::default();
+        let line = table.insert_test_line(
+            "line:1",
+            "Guard: {player_name}, you owe {0} gold to {1}",
+            vec!["12".into(), "{lender}".into()],
+        );
+
+        let (character, text) = table.get_final_text_with_named(
+            &line,
+            LOCALE,
+            &named(&[("player_name", "Sam"), ("lender", "the bank")]),
+        );
+
+        assert_eq!(character.as_deref(), Some("Guard"));
+        // positional substitutions are applied first, so their values can be named
+        assert_eq!(text, "Sam, you owe 12 gold to the bank");
+    }
+
+    #[test]
+    fn missing_named_substitutions_are_empty() {
+        let mut table = BevyYarnStringTable::default();
+        let line = table.insert_test_line("line:1", "Hello {player_name}!", Vec::new());
+
+        let (_, text) = table.get_final_text_with_named(&line, LOCALE, &named(&[]));
+
+        assert_eq!(text, "Hello !");
+    }
+
+    #[test]
+    fn positional_placeholders_are_not_named() {
+        let mut table = BevyYarnStringTable::default();
+        let line = table.insert_test_line("line:1", "{0} and {name}", Vec::new());
+
+        let (_, text) = table.get_final_text_with_named(&line, LOCALE, &named(&[("0", "zero")]));
+
+        assert_eq!(text, "{0} and ");
+    }
+
+    #[test]
+    fn static_lines_borrow_the_table_text() {
+        let mut table = BevyYarnStringTable::default();
+        let line = table.insert_test_line("line:1", "Guard: Halt!", Vec::new());
+
+        let (_, text) = table.get_final_text(&line, LOCALE);
+        assert!(matches!(text, Cow::Borrowed("Halt!")));
+
+        let policy = YarnMissingStringPolicy::default();
+        let named = BevyYarnNamedSubstitutions::default();
+        let (_, first) = table.get_shared_final_text(&line, LOCALE, &named, &policy);
+        let (_, second) = table.get_shared_final_text(&line, LOCALE, &named, &policy);
+        assert!(Arc::ptr_eq(&first, &second));
+    }
+
+    #[test]
+    fn substituted_and_formatted_lines_own_their_text() {
+        let mut table = BevyYarnStringTable::default();
+        let substituted = table.insert_test_line("line:1", "Hello {0}", vec!["Sam".into()]);
+        let formatted = table.insert_test_line(
+            "line:2",
+            "[plural value=2 one=\"% apple\" other=\"% apples\"/]",
+            Vec::new(),
+        );
+
+        let (_, text) = table.get_final_text(&substituted, LOCALE);
+        assert!(matches!(text, Cow::Owned(ref text) if text == "Hello Sam"));
+
+        let (_, text) = table.get_final_text(&formatted, LOCALE);
+        assert!(matches!(text, Cow::Owned(_)));
+    }
+
+    const TABLE: &str = "id,text,file,node,lineNumber
+line:1,Guard: Halt!,test.yarn,Start,1
+line:2,The guard looks you up and down.,test.yarn,Start,2
+line:3,Guard: Papers please,test.yarn,Start,3
+";
+
+    #[test]
+    fn string_tables_look_up_and_iterate_lines() {
+        let table = BevyYarnStringTable::from_csv(TABLE.as_bytes()).expect("table is valid");
+
+        assert_eq!(table.len(), 3);
+        assert!(!table.is_empty());
+        assert_eq!(table.raw_text("line:1"), Some("Guard: Halt!"));
+        assert_eq!(
+            table.line_info("line:2").map(|info| info.id.as_str()),
+            Some("line:2")
+        );
+        assert!(table.line_info("line:4").is_none());
+
+        let mut ids = table.iter().map(|(id, _)| id.as_str()).collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, ["line:1", "line:2", "line:3"]);
+
+        assert_eq!(
+            table.character_name_set(),
+            HashSet::from(["Guard".to_string()])
+        );
+        assert_eq!(table.line_count_for_character("Guard"), 2);
+    }
+
+    #[test]
+    fn merged_string_tables_replace_and_add_lines() {
+        let mut table = BevyYarnStringTable::from_csv(TABLE.as_bytes()).expect("table is valid");
+        let other = BevyYarnStringTable::from_csv(
+            "id,text,file,node,lineNumber
+line:3,Captain: Papers please,test.yarn,Start,3
+line:4,Guard: Move along,test.yarn,Start,4
+"
+            .as_bytes(),
+        )
+        .expect("table is valid");
+
+        table.merge(other);
+
+        assert_eq!(table.len(), 4);
+        assert_eq!(table.raw_text("line:3"), Some("Captain: Papers please"));
+        assert_eq!(table.raw_text("line:4"), Some("Guard: Move along"));
+        assert_eq!(table.line_count_for_character("Guard"), 2);
+        assert_eq!(table.line_count_for_character("Captain"), 1);
+
+        let (character, text) = table.get_final_text(
+            &Line {
+                id: "line:3".into(),
+                substitutions: Vec::new(),
+            },
+            LOCALE,
+        );
+        assert_eq!(character.as_deref(), Some("Captain"));
+        assert_eq!(text, "Papers please");
+    }
+
+    #[test]
+    fn metadata_tables_list_the_lines_in_a_node() {
+        let table = BevyYarnMetadataTable::from_csv(
+            "id,node,lineNumber,tags
+line:3,Start,3,mood:happy
+line:1,Start,1,lastline
+line:2,Shop,2,mood:sad
+"
+            .as_bytes(),
+        )
+        .expect("table is valid");
+
+        assert_eq!(table.get_line_ids_in_node("Start"), ["line:1", "line:3"]);
+        assert_eq!(table.get_line_ids_in_node("Shop"), ["line:2"]);
+        assert!(table.get_line_ids_in_node("End").is_empty());
+    }
+
+    #[test]
+    fn string_tables_list_their_characters() {
+        let table = BevyYarnStringTable::from_csv(
+            "id,text,file,node,lineNumber
+line:1,Guard: Halt!,test.yarn,Start,1
+line:2,Captain: Let them through,test.yarn,Start,2
+line:3,Guard: Yes sir,test.yarn,Start,3
+line:4,Thief: Thanks,test.yarn,Start,4
+line:5,The gate opens.,test.yarn,Start,5
+line:6,Guard: Next!,test.yarn,Start,6
+"
+            .as_bytes(),
+        )
+        .expect("table is valid");
+
+        let characters = table.character_name_set();
+        assert_eq!(characters.len(), 3);
+        assert_eq!(
+            characters,
+            HashSet::from(["Guard".into(), "Captain".into(), "Thief".into()])
+        );
+
+        assert_eq!(table.line_count_for_character("Guard"), 3);
+        assert_eq!(table.line_count_for_character("Captain"), 1);
+        assert_eq!(table.line_count_for_character("Thief"), 1);
+        assert_eq!(table.line_count_for_character("Narrator"), 0);
+    }
+
+    #[test]
+    fn extracts_the_character_from_a_line() {
+        let (character, text) = BevyYarnStringTable::extract_character("Guard: Halt!".into());
+        assert_eq!(character.as_deref(), Some("Guard"));
+        assert_eq!(text, "Halt!");
+
+        let (character, text) = BevyYarnStringTable::extract_character("The gate opens.".into());
+        assert_eq!(character, None);
+        assert_eq!(text, "The gate opens.");
+    }
+}
@@ -0,0 +1,150 @@
+//! Barks are short, fire-and-forget lines such as an NPC remarking on the weather. A bark
+//! plays a single node from a program, advancing through its lines on its own with the
+//! timing from [crate::prelude::YarnTimingConfig], and despawns its entity when it completes.
+//!
+//! Barks raise [BevyYarnBarkEvent]s rather than [crate::prelude::BevyYarnEvent]s, so the
+//! main dialogue UI doesn't show them, and aren't stepped by
+//! [crate::prelude::BevyYarnStepDialogueEvent]s or the default input handlers.
+
+use bevy::prelude::*;
+use chapter::VirtualMachine;
+
+use crate::{
+    assets::BevyYarnProgram,
+    playback::program_path,
+    prelude::{BevyYarnLine, YarnGlobalPause, YarnNodePlaybackRules},
+    BevyYarnDialogueEngine,
+};
+
+/// Spawn this component to play a node from a program once as a bark. The entity is
+/// despawned when the bark completes, so barks are usually spawned on their own entity.
+/// Barks shouldn't offer choices, any that do are logged and the bark ends there. Barks
+/// whose node can't be played under its [crate::prelude::NodePlaybackRule] are despawned
+/// without playing.
+///
+/// ```ignore
+/// commands.spawn(YarnBark::new(asset_server.load("npcs.yarnc"), "WeatherBark"));
+/// ```
+#[derive(Component, Debug, Clone)]
+pub struct YarnBark {
+    /// The program to play the bark from
+    pub program: Handle<BevyYarnProgram>,
+    /// The name of the node to play
+    pub node: String,
+}
+
+impl YarnBark {
+    /// Creates a bark that plays the given node from the program
+    pub fn new<N: Into<String>>(program: Handle<BevyYarnProgram>, node: N) -> Self {
+        Self {
+            program,
+            node: node.into(),
+        }
+    }
+}
+
+/// Events raised by barks, see the [module documentation](self)
+#[derive(Event, Debug, Clone, PartialEq)]
+pub enum BevyYarnBarkEvent {
+    /// The bark on the given entity said a line. The bark advances by itself after
+    /// [BevyYarnLine::estimated_duration].
+    Line {
+        /// The entity with the [YarnBark]
+        entity: Entity,
+        /// The line to show
+        line: BevyYarnLine,
+    },
+    /// The bark on the given entity completed, and its entity is about to be despawned
+    Finished(Entity),
+}
+
+/// Counts down until a bark shows its next line
+#[derive(Component, Debug, Default)]
+pub(crate) struct BarkTimer(Option<Timer>);
+
+impl YarnBark {
+    /// Starts a dialogue engine for each bark once its program has loaded
+    pub(crate) fn start_barks(
+        mut commands: Commands,
+        asset_server: Res<AssetServer>,
+        programs: Res<Assets<BevyYarnProgram>>,
+        mut playback_rules: ResMut<YarnNodePlaybackRules>,
+        barks: Query<(Entity, &YarnBark), Without<BevyYarnDialogueEngine>>,
+    ) {
+        for (entity, bark) in barks.iter() {
+            let Some(program) = programs.get(&bark.program) else {
+                continue;
+            };
+
+            let program_path = program_path(&asset_server, &bark.program);
+            if !playback_rules.can_play(&program_path, &bark.node) {
+                debug!("Skipping bark {}, it can't be played again yet", bark.node);
+                commands.entity(entity).despawn_recursive();
+                continue;
+            }
+
+            let mut vm = VirtualMachine::new(program.program.clone());
+            if let Err(e) = vm.set_node(&bark.node) {
+                warn!("Unable to play bark {}: {e:?}", bark.node);
+                commands.entity(entity).despawn_recursive();
+                continue;
+            }
+
+            let mut engine = BevyYarnDialogueEngine::new(
+                format!("bark {}", bark.node),
+                &bark.node,
+                vm,
+                bark.program.clone(),
+                program.string_table.clone(),
+                program.metadata_table.clone(),
+            );
+            engine.initial_values = program.initial_values();
+            engine.is_bark = true;
+            engine.step_deferred = true;
+            // mark the node straight away so another bark of it this frame is skipped
+            playback_rules.mark_played(&program_path, &bark.node);
+
+            commands
+                .entity(entity)
+                .insert((engine, BarkTimer::default()));
+        }
+    }
+
+    /// Steps each bark once its current line has been shown for long enough, and despawns
+    /// completed barks
+    pub(crate) fn advance_barks(
+        mut commands: Commands,
+        time: Res<Time>,
+        global_pause: Res<YarnGlobalPause>,
+        mut bark_events: EventReader<BevyYarnBarkEvent>,
+        mut barks: Query<(Entity, &mut BevyYarnDialogueEngine, &mut BarkTimer), With<YarnBark>>,
+    ) {
+        for event in bark_events.iter() {
+            if let BevyYarnBarkEvent::Line { entity, line } = event {
+                if let Ok((_, _, mut timer)) = barks.get_mut(*entity) {
+                    timer.0 = Some(Timer::new(line.estimated_duration, TimerMode::Once));
+                }
+            }
+        }
+
+        for (entity, mut engine, mut timer) in barks.iter_mut() {
+            if engine.is_complete {
+                commands.entity(entity).despawn_recursive();
+                continue;
+            }
+
+            if global_pause.0 || engine.is_priority_paused() {
+                continue;
+            }
+
+            let Some(ref mut line_timer) = timer.0 else {
+                continue;
+            };
+
+            if line_timer.tick(time.delta()).finished() {
+                timer.0 = None;
+                engine.step_deferred = true;
+            }
+        }
+    }
+}
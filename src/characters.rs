@@ -0,0 +1,123 @@
+//! Finding the entities that speak lines, for instance to point a camera at the speaker or
+//! show their portrait. Add a [YarnCharacter] with the character name used in the yarn
+//! files to each speaking entity:
+//!
+//! ```ignore
+//! commands.spawn((SpriteBundle::default(), YarnCharacter::new("Guard")));
+//! ```
+//!
+//! The [YarnCharacterRegistry] maps the names to their entities, and the
+//! [crate::prelude::BevyYarnLine::speaker_entity] of each line said is set from it. The
+//! first time a character with no registered entity speaks, a [BevyYarnMissingSpeakerEvent]
+//! is sent. Cameras and speech bubbles can follow the speaker with the
+//! [BevyYarnSpeakerChangedEvent].
+
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+
+use crate::ids::LineId;
+
+/// Registers the entity it is added to as the speaker of the lines said by the character
+/// with the given name, see [crate::prelude::BevyYarnLine::character]. If more than one
+/// entity has the same name, the most recently added is used.
+#[derive(Component, Debug, Default, Clone, PartialEq, Eq, Hash, Reflect)]
+#[reflect(Component, Default)]
+pub struct YarnCharacter(pub String);
+
+impl YarnCharacter {
+    /// Creates a character with the given name
+    pub fn new<N: Into<String>>(name: N) -> Self {
+        Self(name.into())
+    }
+}
+
+/// An event sent the first time a line is said by a character with no registered
+/// [YarnCharacter] entity, so missing hookups are easy to spot
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct BevyYarnMissingSpeakerEvent {
+    /// The name of the character
+    pub character: String,
+    /// The line the character said
+    pub line_id: LineId,
+}
+
+/// An event sent when a dialogue says a line with a different speaker to its previous
+/// line, for instance to re-frame a camera or move a speech bubble. This is sent once per
+/// change rather than for every line, including when a narration line with no
+/// [crate::prelude::BevyYarnLine::character] follows a character's line. The first line
+/// after the dialogue starts, moves to another node or ends always sends this event.
+/// Barks don't send this event.
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct BevyYarnSpeakerChangedEvent {
+    /// The entity of the dialogue engine, or [Entity::PLACEHOLDER] for a resource engine
+    pub engine: Entity,
+    /// The entity registered for the new speaker in the [YarnCharacterRegistry], if any
+    pub speaker: Option<Entity>,
+    /// The name of the new speaker, or `None` for narration
+    pub character: Option<String>,
+}
+
+/// A resource mapping character names to the entities with the matching [YarnCharacter],
+/// kept in sync as the components are added, changed and removed
+#[derive(Resource, Debug, Default)]
+pub struct YarnCharacterRegistry {
+    entities: HashMap<String, Entity>,
+    reported_missing: HashSet<String>,
+}
+
+impl YarnCharacterRegistry {
+    /// The entity registered for the character with the given name, if any
+    pub fn get(&self, character: &str) -> Option<Entity> {
+        self.entities.get(character).copied()
+    }
+
+    /// The registered character names and their entities, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Entity)> + '_ {
+        self.entities
+            .iter()
+            .map(|(character, entity)| (character.as_str(), *entity))
+    }
+
+    /// The number of registered characters
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    /// Whether no characters are registered
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// The entity registered for the character, returning `true` the first time a
+    /// character with no registered entity is looked up so it is only reported once
+    pub(crate) fn resolve(&mut self, character: &str) -> (Option<Entity>, bool) {
+        match self.get(character) {
+            Some(entity) => (Some(entity), false),
+            None => (None, self.reported_missing.insert(character.to_owned())),
+        }
+    }
+
+    /// Registers the added and changed [YarnCharacter]s and removes the entities whose
+    /// component was removed or that were despawned
+    pub(crate) fn sync(
+        mut registry: ResMut<YarnCharacterRegistry>,
+        mut removed: RemovedComponents<YarnCharacter>,
+        changed: Query<(Entity, &YarnCharacter), Changed<YarnCharacter>>,
+    ) {
+        for entity in removed.iter() {
+            registry
+                .entities
+                .retain(|_, registered| *registered != entity);
+        }
+
+        for (entity, character) in changed.iter() {
+            // the entity's previous name if it was renamed
+            registry
+                .entities
+                .retain(|_, registered| *registered != entity);
+            registry.entities.insert(character.0.clone(), entity);
+            registry.reported_missing.remove(&character.0);
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//! Lightweight snapshots of a dialogue for save systems, see
+//! [crate::BevyYarnDialogueEngine::export_checkpoint].
+
+use std::collections::HashMap;
+
+use chapter::YarnValue;
+
+/// The state needed to resume a dialogue later: the node it is running, its variables and
+/// how many times it has visited each node. Unlike the engine itself, this holds no asset
+/// handles, so it can be saved with the `serde` feature and imported into a fresh engine
+/// for the same program with [crate::BevyYarnDialogueEngine::import_checkpoint]:
+///
+/// ```ignore
+/// let checkpoint = engine.export_checkpoint();
+/// save_file.dialogue = serde_json::to_string(&checkpoint)?;
+///
+/// // after loading the game and the dialogue
+/// engine.import_checkpoint(serde_json::from_str(&save_file.dialogue)?)?;
+/// ```
+///
+/// Dialogues resume from the start of the checkpoint's node, as the virtual machine can't
+/// be restored part way through a node.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DialogueCheckpoint {
+    /// The node the dialogue was running
+    pub node_name: String,
+    /// The variables in the virtual machine's variable storage. With the `serde` feature,
+    /// these are saved as plain booleans, numbers and strings.
+    #[cfg_attr(feature = "serde", serde(with = "crate::remote::serde_yarn_variables"))]
+    pub variables: HashMap<String, YarnValue>,
+    /// The number of times the dialogue has visited each node, see
+    /// [crate::BevyYarnDialogueEngine::visit_count]
+    pub visited_nodes: HashMap<String, u32>,
+}
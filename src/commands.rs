@@ -2,12 +2,13 @@
 //! Used to process Yarn Spinner commands using pre-registered
 //! [CommandHandlerFn] command handlers.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use bevy::{
-    ecs::system::Command,
-    prelude::{warn, App, Mut, Resource, World},
+    ecs::system::{Command, CommandQueue},
+    prelude::{warn, App, Commands, Entity, Mut, Name, Resource, World},
 };
+use regex::Regex;
 
 /// Represents a "command handler", which is a way for Bevy apps to register
 /// functions that are called in response to commands parsed from the Yarn file.
@@ -19,34 +20,240 @@ use bevy::{
 /// to raise that with the bevy application.
 pub type CommandHandlerFn = fn(&mut World, Vec<String>);
 
+/// A command handler that also receives the entity targeted by the command, if any.
+/// Commands target an entity either by passing a leading `@entity_name` argument, which
+/// is matched against the bevy [Name] of an entity, or with [BevyYarnCommand::with_target].
+/// For instance `<<despawn_enemy @enemy_42>>`. The `@entity_name` argument is left out of
+/// the arguments these handlers receive.
+pub type EntityCommandHandlerFn = fn(Option<Entity>, &mut World, Vec<String>);
+
+/// A command handler that queues changes with [Commands] rather than changing the [World]
+/// directly. These are called as soon as the command is reached in the dialogue rather
+/// than through an exclusive [World] command, so a burst of commands that only need to
+/// queue a few changes doesn't hold up the frame. Use a [CommandHandlerFn] for handlers
+/// that need to read the world.
+pub type DeferredCommandHandlerFn = fn(&mut Commands, Vec<String>);
+
+/// A type that provides a set of command handlers, so they can be registered together with
+/// [crate::YarnPluginBuilder::add_yarn_commands_from_provider]. This is usually implemented
+/// with the [crate::yarn_command_provider] macro, which names each command after its
+/// handler function so the command names can't be mistyped.
+pub trait YarnCommandProvider {
+    /// The commands to register, as pairs of the command name and its handler
+    fn commands() -> Vec<(&'static str, CommandHandlerFn)>;
+}
+
+/// Implements [YarnCommandProvider] for a type from a list of its associated functions,
+/// each registered as a command with the same name as the function. The functions must
+/// have the signature of a [CommandHandlerFn].
+///
+/// ```ignore
+/// struct StageCommands;
+///
+/// impl StageCommands {
+///     fn set_background(world: &mut World, args: Vec<String>) { /* ... */ }
+///     fn play_sound(world: &mut World, args: Vec<String>) { /* ... */ }
+/// }
+///
+/// // registers the `<<set_background>>` and `<<play_sound>>` commands
+/// yarn_command_provider!(StageCommands { set_background, play_sound });
+/// ```
+#[macro_export]
+macro_rules! yarn_command_provider {
+    ($provider:ty { $($command:ident),* $(,)? }) => {
+        impl $crate::commands::YarnCommandProvider for $provider {
+            fn commands() -> Vec<(&'static str, $crate::commands::CommandHandlerFn)> {
+                vec![$((
+                    stringify!($command),
+                    <$provider>::$command as $crate::commands::CommandHandlerFn,
+                )),*]
+            }
+        }
+    };
+}
+
+/// A registered handler for a yarn command
+#[derive(Clone, Copy)]
+pub(crate) enum CommandHandler {
+    /// A handler that only receives the command arguments
+    Args(CommandHandlerFn),
+    /// A handler that receives the targeted entity and the command arguments
+    Entity(EntityCommandHandlerFn),
+    /// A handler that queues changes with [Commands]
+    Deferred(DeferredCommandHandlerFn),
+}
+
+/// A resource with the handlers registered with the [crate::YarnPluginBuilder], used by
+/// [crate::YarnPlugin::step_dialogue_vm] to dispatch commands
+#[derive(Default, Resource)]
+pub struct CommandHandlers(pub(crate) HashMap<String, CommandHandler>);
+
+/// The names of the [crate::BUILTIN_COMMANDS] that have not been disabled with
+/// [crate::YarnPluginBuilder::without_builtin_command]
 #[derive(Default, Resource)]
-pub(crate) struct CommandHandlers(pub(crate) HashMap<String, CommandHandlerFn>);
+pub struct BuiltinCommands(pub(crate) HashSet<String>);
+
+impl BuiltinCommands {
+    /// Whether the built in command with the given name is enabled
+    pub(crate) fn is_enabled(&self, command_name: &str) -> bool {
+        self.0.contains(command_name)
+    }
+}
+
+/// A resource that enables a check when each dialogue is loaded for commands used in the
+/// yarn file that have no registered handler, which are often typos. A single warning is
+/// logged listing all unhandled commands. Insert this resource to opt in to the check.
+///
+/// Commands that are intentionally handled through [crate::prelude::BevyYarnEvent::Command]
+/// events rather than a registered handler can be added to the allowlist.
+#[derive(Debug, Default, Clone, Resource)]
+pub struct YarnCommandLint {
+    /// Names of commands that are expected to have no registered handler
+    pub allowlist: HashSet<String>,
+}
+
+impl YarnCommandLint {
+    /// Adds a command to the allowlist. Returns the lint.
+    pub fn allow<N: Into<String>>(mut self, command_name: N) -> Self {
+        self.allowlist.insert(command_name.into());
+        self
+    }
+}
 
 /// Represents a custom command from within the Yarn file, usually expressed as
 ///
 /// ```yarn
 /// <<my_command arg1 arg2 argN>>
 /// ```
-#[derive(Debug, Clone)]
+///
+/// Commands are hashed on their [BevyYarnCommand::command_name] only.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BevyYarnCommand {
     /// The name of the command
     pub command_name: String,
 
-    /// The arguments provided to the command
+    /// The arguments provided to the command, including any leading `@entity_name`
+    /// argument
     pub args: Vec<String>,
 
     /// Whether the command has already been handled by a pre-registered command
     pub handled: bool,
+
+    /// The entity targeted by this command, if any
+    pub target_entity: Option<Entity>,
+
+    /// The name of the entity targeted by this command, parsed from a leading `@entity_name`
+    /// argument. This is resolved to the [BevyYarnCommand::target_entity] when the command
+    /// is applied to an [EntityCommandHandlerFn] if no target entity was set.
+    pub target_name: Option<String>,
+}
+
+impl std::hash::Hash for BevyYarnCommand {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.command_name.hash(state);
+    }
+}
+
+impl BevyYarnCommand {
+    /// Parses the command name and args from a command's text, splitting on spaces
+    /// except where the args are quoted
+    pub(crate) fn parse(cmd_text: &str) -> Self {
+        let command_parser = Regex::new(r#"(("[^"]+")|\S+)+"#).expect("parse regex");
+
+        // parse the command name and args
+        let (command_name, args) = command_parser
+            .find_iter(cmd_text)
+            .map(|cap| cap.as_str().to_owned().replace('"', ""))
+            .enumerate()
+            .fold(
+                (String::new(), Vec::<String>::new()),
+                |mut acc, (index, item)| {
+                    if index == 0 {
+                        (item, acc.1)
+                    } else {
+                        acc.1.push(item);
+                        acc
+                    }
+                },
+            );
+
+        // a leading `@name` argument targets a named entity
+        let target_name = args
+            .first()
+            .and_then(|arg| arg.strip_prefix('@'))
+            .filter(|name| !name.is_empty())
+            .map(str::to_owned);
+
+        Self {
+            command_name,
+            args,
+            handled: false,
+            target_entity: None,
+            target_name,
+        }
+    }
+
+    /// Sets the entity targeted by this command. Returns the command.
+    pub fn with_target(mut self, entity: Entity) -> Self {
+        self.target_entity = Some(entity);
+        self
+    }
+
+    /// The arguments without the leading `@entity_name` argument, if there is one
+    fn args_without_target(mut self) -> Vec<String> {
+        if self.target_name.is_some() {
+            self.args.remove(0);
+        }
+        self.args
+    }
+
+    /// Finds the targeted entity, either from the target entity or by finding
+    /// an entity with a [Name] matching the target name.
+    fn resolve_target(&self, world: &mut World) -> Option<Entity> {
+        if self.target_entity.is_some() {
+            return self.target_entity;
+        }
+
+        let target_name = self.target_name.as_ref()?;
+        let target = world
+            .query::<(Entity, &Name)>()
+            .iter(world)
+            .find(|(_, name)| name.as_str() == target_name)
+            .map(|(entity, _)| entity);
+
+        if target.is_none() {
+            warn!(
+                "Command {} targets entity @{target_name} but no entity has that name",
+                self.command_name
+            );
+        }
+
+        target
+    }
 }
 
 impl Command for BevyYarnCommand {
     // This approach is inspired by https://github.com/Semihazah/bevy_yarn_spinner
     fn apply(self, world: &mut World) {
-        world.resource_scope(|world, command_registry: Mut<CommandHandlers>| {
-            if let Some(handler) = command_registry.0.get(&self.command_name) {
-                handler(world, self.args);
-            }
-        });
+        world.resource_scope(
+            |world, command_registry: Mut<CommandHandlers>| match command_registry
+                .0
+                .get(&self.command_name)
+            {
+                Some(CommandHandler::Args(handler)) => handler(world, self.args),
+                Some(CommandHandler::Entity(handler)) => {
+                    let target = self.resolve_target(world);
+                    handler(target, world, self.args_without_target());
+                }
+                Some(CommandHandler::Deferred(handler)) => {
+                    let mut queue = CommandQueue::default();
+                    handler(&mut Commands::new(&mut queue, world), self.args);
+                    queue.apply(world);
+                }
+                None => {}
+            },
+        );
     }
 }
 
@@ -60,6 +267,23 @@ pub trait AddBevyCommandHandlerExt {
         command_name: N,
         handler: CommandHandlerFn,
     ) -> &mut Self;
+
+    /// Add a command that receives the targeted entity to the [CommandHandlers] for this app.
+    /// If the command already exists, the existing handler is replaced.
+    fn add_yarn_entity_command<N: Into<String>>(
+        &mut self,
+        command_name: N,
+        handler: EntityCommandHandlerFn,
+    ) -> &mut Self;
+
+    /// Add a command that queues changes with [Commands] to the [CommandHandlers] for this
+    /// app, see [DeferredCommandHandlerFn]. If the command already exists, the existing
+    /// handler is replaced.
+    fn add_yarn_deferred_command<N: Into<String>>(
+        &mut self,
+        command_name: N,
+        handler: DeferredCommandHandlerFn,
+    ) -> &mut Self;
 }
 
 impl AddBevyCommandHandlerExt for World {
@@ -68,13 +292,25 @@ impl AddBevyCommandHandlerExt for World {
         command_name: N,
         handler: CommandHandlerFn,
     ) -> &mut Self {
-        match self.get_resource_mut::<CommandHandlers>() {
-            Some(mut handlers) => {
-                handlers.0.insert(command_name.into(), handler);
-            },
-            None => warn!("Attempted to add YarnCommand, but no CommandHandlers present. Was the YarnPlugin added?"),
-        };
+        insert_command_handler(self, command_name.into(), CommandHandler::Args(handler));
+        self
+    }
 
+    fn add_yarn_entity_command<N: Into<String>>(
+        &mut self,
+        command_name: N,
+        handler: EntityCommandHandlerFn,
+    ) -> &mut Self {
+        insert_command_handler(self, command_name.into(), CommandHandler::Entity(handler));
+        self
+    }
+
+    fn add_yarn_deferred_command<N: Into<String>>(
+        &mut self,
+        command_name: N,
+        handler: DeferredCommandHandlerFn,
+    ) -> &mut Self {
+        insert_command_handler(self, command_name.into(), CommandHandler::Deferred(handler));
         self
     }
 }
@@ -88,4 +324,138 @@ impl AddBevyCommandHandlerExt for App {
         let _ = self.world.add_yarn_command(command_name, handler);
         self
     }
+
+    fn add_yarn_entity_command<N: Into<String>>(
+        &mut self,
+        command_name: N,
+        handler: EntityCommandHandlerFn,
+    ) -> &mut Self {
+        let _ = self.world.add_yarn_entity_command(command_name, handler);
+        self
+    }
+
+    fn add_yarn_deferred_command<N: Into<String>>(
+        &mut self,
+        command_name: N,
+        handler: DeferredCommandHandlerFn,
+    ) -> &mut Self {
+        let _ = self.world.add_yarn_deferred_command(command_name, handler);
+        self
+    }
+}
+
+fn insert_command_handler(world: &mut World, command_name: String, handler: CommandHandler) {
+    match world.get_resource_mut::<CommandHandlers>() {
+        Some(mut handlers) => {
+            handlers.0.insert(command_name, handler);
+        },
+        None => warn!("Attempted to add YarnCommand, but no CommandHandlers present. Was the YarnPlugin added?"),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The target and arguments the test handler was last called with
+    #[derive(Resource, Default)]
+    struct Targeted(Option<(Option<Entity>, Vec<String>)>);
+
+    fn record_target(target: Option<Entity>, world: &mut World, args: Vec<String>) {
+        world.resource_mut::<Targeted>().0 = Some((target, args));
+    }
+
+    fn world_with_entity_command() -> World {
+        let mut world = World::new();
+        world.init_resource::<CommandHandlers>();
+        world.init_resource::<Targeted>();
+        world.add_yarn_entity_command("despawn_enemy", record_target);
+        world
+    }
+
+    #[test]
+    fn parses_a_leading_target_name() {
+        let command = BevyYarnCommand::parse("despawn_enemy @enemy_42 \"slowly now\"");
+
+        assert_eq!(command.command_name, "despawn_enemy");
+        assert_eq!(command.target_name.as_deref(), Some("enemy_42"));
+        assert_eq!(
+            command.args,
+            vec!["@enemy_42".to_string(), "slowly now".into()]
+        );
+        assert_eq!(command.target_entity, None);
+    }
+
+    /// The arguments the plain test handler was last called with
+    #[derive(Resource, Default)]
+    struct Said(Vec<String>);
+
+    fn record_args(world: &mut World, args: Vec<String>) {
+        world.resource_mut::<Said>().0 = args;
+    }
+
+    #[test]
+    fn plain_commands_keep_a_leading_target_argument() {
+        let mut world = World::new();
+        world.init_resource::<CommandHandlers>();
+        world.init_resource::<Said>();
+        world.add_yarn_command("say", record_args);
+
+        BevyYarnCommand::parse("say @Bob hi").apply(&mut world);
+
+        assert_eq!(
+            world.resource::<Said>().0,
+            vec!["@Bob".to_string(), "hi".into()]
+        );
+    }
+
+    #[test]
+    fn only_a_leading_argument_is_a_target() {
+        let command = BevyYarnCommand::parse("point_at north @enemy_42");
+        assert_eq!(command.target_name, None);
+        assert_eq!(command.args, vec!["north".to_string(), "@enemy_42".into()]);
+
+        let command = BevyYarnCommand::parse("say @");
+        assert_eq!(command.target_name, None);
+        assert_eq!(command.args, vec!["@".to_string()]);
+    }
+
+    #[test]
+    fn entity_commands_receive_the_named_entity() {
+        let mut world = world_with_entity_command();
+        world.spawn(Name::new("enemy_41"));
+        let enemy = world.spawn(Name::new("enemy_42")).id();
+
+        BevyYarnCommand::parse("despawn_enemy @enemy_42 now").apply(&mut world);
+
+        assert_eq!(
+            world.resource::<Targeted>().0,
+            Some((Some(enemy), vec!["now".to_string()]))
+        );
+    }
+
+    #[test]
+    fn entity_commands_receive_the_set_target() {
+        let mut world = world_with_entity_command();
+        world.spawn(Name::new("enemy_42"));
+        let target = world.spawn_empty().id();
+
+        BevyYarnCommand::parse("despawn_enemy @enemy_42")
+            .with_target(target)
+            .apply(&mut world);
+
+        assert_eq!(
+            world.resource::<Targeted>().0,
+            Some((Some(target), Vec::new()))
+        );
+    }
+
+    #[test]
+    fn entity_commands_receive_no_target_for_unknown_names() {
+        let mut world = world_with_entity_command();
+
+        BevyYarnCommand::parse("despawn_enemy @nobody").apply(&mut world);
+
+        assert_eq!(world.resource::<Targeted>().0, Some((None, Vec::new())));
+    }
 }
@@ -0,0 +1,307 @@
+//! Filtering lines by their tags, for instance hiding `#mature` or `#spoiler` lines while
+//! streaming.
+//!
+//! The [ContentFilter] resource lists the blocked line tags and the [ContentFilterPolicy]
+//! for each. Lines are filtered before their [crate::prelude::BevyYarnEvent::Say] or
+//! [crate::prelude::BevyYarnEvent::Choices] event is sent:
+//!
+//! ```yarn
+//! Guard: You'll never get past me, you little... #mature #alt:line:guard_clean
+//! ```
+//!
+//! ```ignore
+//! commands.insert_resource(
+//!     ContentFilter::default()
+//!         .with_rule("mature", ContentFilterPolicy::ReplaceWithAlt)
+//!         .with_rule("spoiler", ContentFilterPolicy::Skip),
+//! );
+//! ```
+//!
+//! Choices can't be skipped without changing the option indices the dialogue expects, so
+//! [ContentFilterPolicy::Skip] censors choices instead. Censoring happens before any
+//! [crate::YarnPluginBuilder::with_line_middleware] runs.
+
+use bevy::prelude::*;
+use chapter::Line;
+
+use crate::{assets::BevyYarnMetadataTable, events::BevyYarnEvent};
+
+/// The line tag giving the line id of the alternate line for
+/// [ContentFilterPolicy::ReplaceWithAlt], for instance `#alt:line:guard_clean`
+pub const ALT_TAG: &str = "alt";
+
+/// What happens to a line with a blocked tag, see [ContentFilter]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContentFilterPolicy {
+    /// The line with the id in its `#alt:<line id>` tag is said instead, see [ALT_TAG].
+    /// Lines without an alternate line are censored.
+    ReplaceWithAlt,
+    /// The line isn't said, and the dialogue continues to the next line. Choices are
+    /// censored instead.
+    Skip,
+    /// The line is said with its text replaced by [ContentFilter::censored_text]
+    Censor,
+}
+
+/// A resource listing the line tags that are blocked and what happens to lines with them,
+/// see the [module documentation](self). No tags are blocked by default.
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct ContentFilter {
+    /// Whether lines are filtered, for instance while a streamer mode setting is on
+    pub enabled: bool,
+    /// The text that censored lines are said with, defaulting to `[censored]`
+    pub censored_text: String,
+    rules: Vec<(String, ContentFilterPolicy)>,
+}
+
+impl Default for ContentFilter {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            censored_text: "[censored]".to_owned(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl ContentFilter {
+    /// Blocks lines with the given tag, written without the leading `#`, replacing any
+    /// existing policy for the tag
+    pub fn set_rule<T: Into<String>>(&mut self, tag: T, policy: ContentFilterPolicy) {
+        let tag = tag.into();
+        match self.rules.iter_mut().find(|(blocked, _)| *blocked == tag) {
+            Some((_, existing)) => *existing = policy,
+            None => self.rules.push((tag, policy)),
+        }
+    }
+
+    /// Blocks lines with the given tag, see [ContentFilter::set_rule]. Returns the filter
+    pub fn with_rule<T: Into<String>>(mut self, tag: T, policy: ContentFilterPolicy) -> Self {
+        self.set_rule(tag, policy);
+        self
+    }
+
+    /// Stops blocking lines with the given tag
+    pub fn remove_rule(&mut self, tag: &str) {
+        self.rules.retain(|(blocked, _)| blocked != tag);
+    }
+
+    /// The policy for a line with the given free-form tags, from the first blocked tag in
+    /// the order the rules were added. Returns `None` if no tags are blocked or the filter
+    /// isn't [ContentFilter::enabled].
+    pub fn policy_for(&self, tags: &[String]) -> Option<ContentFilterPolicy> {
+        if !self.enabled {
+            return None;
+        }
+
+        self.rules
+            .iter()
+            .find(|(blocked, _)| tags.contains(blocked))
+            .map(|(_, policy)| *policy)
+    }
+
+    /// Filters a line from the virtual machine, returning the line to say in its place or
+    /// `None` if it is skipped. Choices are never skipped. Censoring is applied once the
+    /// line's text has been looked up, by [ContentFilter::censor].
+    pub(crate) fn filter_line(
+        &self,
+        line: &Line,
+        metadata_table: &BevyYarnMetadataTable,
+        is_choice: bool,
+    ) -> Option<Line> {
+        let (tags, structured_tags) = metadata_table.get_parsed_tags_for_line(line);
+
+        match self.policy_for(&tags) {
+            Some(ContentFilterPolicy::Skip) if !is_choice => None,
+            Some(ContentFilterPolicy::ReplaceWithAlt) => match structured_tags.get(ALT_TAG) {
+                Some(alt_id) => Some(Line {
+                    id: alt_id.clone(),
+                    substitutions: line.substitutions.clone(),
+                }),
+                None => Some(line.clone()),
+            },
+            _ => Some(line.clone()),
+        }
+    }
+
+    /// Replaces the text of the censored lines in a [BevyYarnEvent::Say] or
+    /// [BevyYarnEvent::Choices] event with [ContentFilter::censored_text]
+    pub(crate) fn censor(&self, event: &mut BevyYarnEvent) {
+        if !self.enabled || self.rules.is_empty() {
+            return;
+        }
+
+        let (lines, is_choice) = match event {
+            BevyYarnEvent::Say(line) => (vec![line], false),
+            BevyYarnEvent::Choices { choices, .. } => (
+                choices
+                    .iter_mut()
+                    .map(|choice| &mut choice.formatted_line)
+                    .collect(),
+                true,
+            ),
+            _ => return,
+        };
+
+        for line in lines {
+            let censored = match self.policy_for(&line.tags) {
+                Some(ContentFilterPolicy::Censor) => true,
+                Some(ContentFilterPolicy::Skip) => is_choice,
+                // lines with an alternate were replaced when the dialogue was stepped
+                Some(ContentFilterPolicy::ReplaceWithAlt) => {
+                    !line.structured_tags.contains_key(ALT_TAG)
+                }
+                None => false,
+            };
+
+            if censored {
+                line.formatted_text = self.censored_text.as_str().into();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::{BevyYarnChoice, BevyYarnLine};
+
+    fn line(id: &str) -> Line {
+        Line {
+            id: id.into(),
+            substitutions: vec!["Sam".into()],
+        }
+    }
+
+    /// A line said with the text and free-form tags its id has in the metadata table
+    fn said(metadata_table: &BevyYarnMetadataTable, line: Line) -> BevyYarnLine {
+        let (tags, structured_tags) = metadata_table.get_parsed_tags_for_line(&line);
+        let mut said = BevyYarnLine::new(line, "Some text");
+        said.tags = tags;
+        said.structured_tags = structured_tags;
+        said
+    }
+
+    fn choices(line: BevyYarnLine) -> BevyYarnEvent {
+        BevyYarnEvent::Choices {
+            engine_entity: Entity::PLACEHOLDER,
+            choices: vec![BevyYarnChoice {
+                line_id: line.line.id.clone().into(),
+                option_index: 0,
+                destination_node: "End".into(),
+                destination_title: "End".into(),
+                was_previously_selected: false,
+                formatted_line: line,
+            }],
+            is_replay: false,
+        }
+    }
+
+    fn censored_text(event: &BevyYarnEvent) -> &str {
+        match event {
+            BevyYarnEvent::Say(line) => &line.formatted_text,
+            BevyYarnEvent::Choices { choices, .. } => &choices[0].formatted_line.formatted_text,
+            _ => unreachable!(),
+        }
+    }
+
+    /// A metadata table with a line for each policy, and a mature line with no alternate
+    fn fixture() -> (ContentFilter, BevyYarnMetadataTable) {
+        let filter = ContentFilter::default()
+            .with_rule("mature", ContentFilterPolicy::ReplaceWithAlt)
+            .with_rule("spoiler", ContentFilterPolicy::Skip)
+            .with_rule("gory", ContentFilterPolicy::Censor);
+
+        let mut metadata_table = BevyYarnMetadataTable::default();
+        metadata_table.insert_test_metadata(
+            "line:mature",
+            vec!["mature".into(), "alt:line:clean".into()],
+        );
+        metadata_table.insert_test_metadata("line:mature_no_alt", vec!["mature".into()]);
+        metadata_table.insert_test_metadata("line:spoiler", vec!["spoiler".into()]);
+        metadata_table.insert_test_metadata("line:gory", vec!["gory".into()]);
+
+        (filter, metadata_table)
+    }
+
+    #[test]
+    fn lines_are_replaced_with_their_alternate() {
+        let (filter, metadata_table) = fixture();
+
+        let replaced = filter.filter_line(&line("line:mature"), &metadata_table, false);
+
+        assert_eq!(
+            replaced.as_ref().map(|line| line.id.as_str()),
+            Some("line:clean")
+        );
+        assert_eq!(
+            replaced.map(|line| line.substitutions),
+            Some(vec!["Sam".to_string()])
+        );
+    }
+
+    #[test]
+    fn lines_without_an_alternate_are_censored() {
+        let (filter, metadata_table) = fixture();
+
+        let kept = filter
+            .filter_line(&line("line:mature_no_alt"), &metadata_table, false)
+            .expect("lines without an alternate are kept");
+        let mut event = BevyYarnEvent::Say(said(&metadata_table, kept));
+        filter.censor(&mut event);
+
+        assert_eq!(censored_text(&event), "[censored]");
+    }
+
+    #[test]
+    fn lines_are_skipped_but_choices_are_censored() {
+        let (filter, metadata_table) = fixture();
+
+        assert!(filter
+            .filter_line(&line("line:spoiler"), &metadata_table, false)
+            .is_none());
+
+        let kept = filter
+            .filter_line(&line("line:spoiler"), &metadata_table, true)
+            .expect("choices are never skipped");
+        let mut event = choices(said(&metadata_table, kept));
+        filter.censor(&mut event);
+
+        assert_eq!(censored_text(&event), "[censored]");
+    }
+
+    #[test]
+    fn lines_are_censored() {
+        let (filter, metadata_table) = fixture();
+
+        for is_choice in [false, true] {
+            let kept = filter
+                .filter_line(&line("line:gory"), &metadata_table, is_choice)
+                .expect("censored lines are kept");
+            let said = said(&metadata_table, kept);
+            let mut event = if is_choice {
+                choices(said)
+            } else {
+                BevyYarnEvent::Say(said)
+            };
+            filter.censor(&mut event);
+
+            assert_eq!(censored_text(&event), "[censored]");
+        }
+    }
+
+    #[test]
+    fn disabled_filters_keep_every_line() {
+        let (mut filter, metadata_table) = fixture();
+        filter.enabled = false;
+
+        for id in ["line:mature", "line:spoiler", "line:gory"] {
+            let kept = filter.filter_line(&line(id), &metadata_table, false);
+            assert_eq!(kept.map(|line| line.id), Some(id.to_string()));
+
+            let mut event = BevyYarnEvent::Say(said(&metadata_table, line(id)));
+            filter.censor(&mut event);
+            assert_eq!(censored_text(&event), "Some text");
+        }
+    }
+}
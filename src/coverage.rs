@@ -0,0 +1,152 @@
+//! Tracking which lines and nodes playtesters have seen, to find dialogue content that is
+//! never reached.
+
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use crate::{
+    assets::{BevyYarnProgram, BevyYarnStringTable},
+    bark::BevyYarnBarkEvent,
+    events::BevyYarnEvent,
+    ids::LineId,
+    BevyYarnDialogueEngine,
+};
+
+/// A resource that accumulates the lines said and nodes entered by every dialogue,
+/// including barks, while it exists. Insert it to start tracking coverage:
+///
+/// ```ignore
+/// app.init_resource::<DialogueCoverage>();
+///
+/// // at the end of the session
+/// let report = coverage.report(&program, &string_table);
+/// info!("Seen {:.0}% of lines", report.line_percentage());
+/// ```
+///
+/// The lines of choices count as seen when the choices are offered. Lines and choices
+/// skipped by the [crate::prelude::YarnEventMask] aren't tracked. With the `serde` feature
+/// coverage can be saved after each session and combined with [DialogueCoverage::merge].
+#[derive(Resource, Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DialogueCoverage {
+    /// The ids of the lines that have been said or offered as choices
+    pub lines: HashSet<LineId>,
+    /// The names of the nodes that have been entered
+    pub nodes: HashSet<String>,
+}
+
+impl DialogueCoverage {
+    /// Adds the lines and nodes seen in another session
+    pub fn merge(&mut self, other: &DialogueCoverage) {
+        self.lines.extend(other.lines.iter().cloned());
+        self.nodes.extend(other.nodes.iter().cloned());
+    }
+
+    /// Compares the coverage with the nodes of a program and the lines of a string table
+    pub fn report(
+        &self,
+        program: &BevyYarnProgram,
+        string_table: &BevyYarnStringTable,
+    ) -> CoverageReport {
+        let mut unseen_nodes = program
+            .program
+            .nodes
+            .keys()
+            .filter(|node| !self.nodes.contains(*node))
+            .cloned()
+            .collect::<Vec<_>>();
+        unseen_nodes.sort_unstable();
+
+        let mut unseen_lines = string_table
+            .0
+            .iter()
+            .map(|(line_id, _)| line_id)
+            .filter(|line_id| !self.lines.contains(*line_id))
+            .cloned()
+            .collect::<Vec<_>>();
+        unseen_lines.sort_unstable();
+
+        CoverageReport {
+            total_nodes: program.program.nodes.len(),
+            total_lines: string_table.len(),
+            unseen_nodes,
+            unseen_lines,
+        }
+    }
+
+    /// Adds the lines raised in events, and the nodes visited by engines that changed
+    pub(crate) fn record(
+        mut coverage: ResMut<DialogueCoverage>,
+        mut yarn_events: EventReader<BevyYarnEvent>,
+        mut bark_events: EventReader<BevyYarnBarkEvent>,
+        resource_engine: Option<Res<BevyYarnDialogueEngine>>,
+        engines: Query<&BevyYarnDialogueEngine, Changed<BevyYarnDialogueEngine>>,
+    ) {
+        for event in yarn_events.iter() {
+            match event {
+                BevyYarnEvent::Say(line) => {
+                    coverage.lines.insert(LineId(line.line.id.clone()));
+                }
+                BevyYarnEvent::Choices { choices, .. } => {
+                    coverage
+                        .lines
+                        .extend(choices.iter().map(|choice| choice.line_id.clone()));
+                }
+                _ => {}
+            }
+        }
+
+        for event in bark_events.iter() {
+            if let BevyYarnBarkEvent::Line { line, .. } = event {
+                coverage.lines.insert(LineId(line.line.id.clone()));
+            }
+        }
+
+        let resource_engine = resource_engine.filter(|engine| engine.is_changed());
+        for engine in engines.iter().chain(resource_engine.as_deref()) {
+            for node in engine.visited_nodes.keys() {
+                if !coverage.nodes.contains(node) {
+                    coverage.nodes.insert(node.clone());
+                }
+            }
+        }
+    }
+}
+
+/// The nodes and lines of a program that haven't been seen, from
+/// [DialogueCoverage::report]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CoverageReport {
+    /// The number of nodes in the program
+    pub total_nodes: usize,
+    /// The number of lines in the string table
+    pub total_lines: usize,
+    /// The names of the nodes that haven't been entered, sorted
+    pub unseen_nodes: Vec<String>,
+    /// The ids of the lines that haven't been seen, sorted
+    pub unseen_lines: Vec<LineId>,
+}
+
+impl CoverageReport {
+    /// The percentage of the program's nodes that have been entered, or 100 if there are
+    /// no nodes
+    pub fn node_percentage(&self) -> f32 {
+        Self::percentage(self.total_nodes - self.unseen_nodes.len(), self.total_nodes)
+    }
+
+    /// The percentage of the string table's lines that have been seen, or 100 if there are
+    /// no lines
+    pub fn line_percentage(&self) -> f32 {
+        Self::percentage(self.total_lines - self.unseen_lines.len(), self.total_lines)
+    }
+
+    fn percentage(seen: usize, total: usize) -> f32 {
+        if total == 0 {
+            return 100.0;
+        }
+
+        seen as f32 / total as f32 * 100.0
+    }
+}
@@ -1,8 +1,24 @@
 //! A component representing a new yarn data file to load into the engine
 
-use bevy::prelude::Component;
+use std::{collections::HashMap, ops::Range, sync::Arc, time::Duration};
+
+use bevy::prelude::{
+    warn, Bundle, Commands, Component, Entity, GlobalTransform, ReflectComponent, ReflectDefault,
+    ReflectResource, Resource, Transform, World,
+};
+use bevy::reflect::Reflect;
 use chapter::Line;
 
+use crate::{
+    ids::{LineId, NodeName},
+    BevyYarnDialogueEngine,
+};
+
+/// A function that is called when a dialogue finishes, with the entity of the
+/// dialogue engine that completed, or [Entity::PLACEHOLDER] for a resource engine started
+/// with [YarnDialogue]. Registered per dialogue with [YarnData::with_on_complete].
+pub type DialogueCompleteFn = fn(&mut World, Entity);
+
 /// A component that is added to trigger loading a yarn engine.  The entity that this component
 /// is added has the yharnam "Virtual Machine" added to it and this component is removed.
 ///
@@ -11,35 +27,687 @@ use chapter::Line;
 /// the following three files should be present in the same directory:
 ///
 /// 1. mystory.yarnc
-/// 2. mystory-Lines.csv
-/// 3. mystory-Metadata.csv
+/// 2. mystory.lines.csv
+/// 3. mystory.metadata.csv
+///
+/// With the `ysc-table-names` feature, the tables are loaded from the file names written by
+/// the Yarn Spinner Console, `mystory-Lines.csv` and `mystory-Metadata.csv`.
 ///
-#[derive(Component)]
+/// [YarnData] is registered for reflection, so dialogues can be assigned to entities in bevy
+/// scenes. The `on_complete` function can't be stored in a scene and is always `None`:
+///
+/// ```ron
+/// "bevy_mod_yarn::data::YarnData": (
+///     yarnc_path: "dialogue/npc.yarnc",
+///     auto_start: false,
+/// ),
+/// ```
+///
+/// [YarnData] can also be inserted as a resource, which is loaded into a
+/// [BevyYarnDialogueEngine] resource, see [YarnDialogue].
+#[derive(Component, Resource, Reflect)]
+#[reflect(Component, Resource, Default)]
 pub struct YarnData {
     /// The path to load the yarnc file from from
     pub yarnc_path: String,
+
+    /// Whether the dialogue is stepped as soon as it has loaded. Only this dialogue is
+    /// stepped, so other running dialogues don't advance. If this is false, the dialogue
+    /// waits after the [crate::prelude::BevyYarnEvent::DialogueReady] event is raised until
+    /// it is advanced with [BevyYarnDialogueEngine::try_advance].
+    /// [crate::prelude::BevyYarnStepDialogueEvent]s don't start it, so other dialogues can
+    /// be stepped meanwhile.
+    pub auto_start: bool,
+
+    /// An optional function that is called when this dialogue completes
+    #[reflect(ignore)]
+    pub on_complete: Option<DialogueCompleteFn>,
+
+    /// Whether this data is kept once the dialogue engine has loaded, rather than removed.
+    /// Kept data reloads the dialogue from the start whenever it is changed, for instance
+    /// by setting [YarnData::yarnc_path] or calling [YarnData::reload].
+    pub keep_after_load: bool,
+
+    /// Whether kept data has been loaded into a dialogue engine since it last changed
+    #[reflect(ignore)]
+    pub(crate) loaded: bool,
+}
+
+/// Creates a [YarnData] with an empty path that starts automatically, for spawning from
+/// scenes. The path should be set before the data is loaded.
+impl Default for YarnData {
+    fn default() -> Self {
+        Self::new("")
+    }
+}
+
+impl YarnData {
+    /// Creates a new [YarnData] that loads the yarnc file at the given path, and starts
+    /// the dialogue as soon as it has loaded
+    pub fn new<P: Into<String>>(yarnc_path: P) -> Self {
+        Self {
+            yarnc_path: yarnc_path.into(),
+            auto_start: true,
+            on_complete: None,
+            keep_after_load: false,
+            loaded: false,
+        }
+    }
+
+    /// Stops the dialogue from starting automatically once it has loaded. Returns the data.
+    pub fn without_auto_start(mut self) -> Self {
+        self.auto_start = false;
+        self
+    }
+
+    /// Keeps the data once the dialogue engine has loaded, so that the dialogue can be
+    /// reloaded, see [YarnData::keep_after_load]. Returns the data.
+    pub fn with_keep_after_load(mut self) -> Self {
+        self.keep_after_load = true;
+        self
+    }
+
+    /// Requests that kept data is loaded again, replacing the dialogue engine with one that
+    /// starts from the beginning. This has no effect unless [YarnData::keep_after_load] is set,
+    /// as the data is removed once it has loaded.
+    pub fn reload(&mut self) {
+        self.loaded = false;
+    }
+
+    /// Sets a function to call when this dialogue completes. Returns the data.
+    pub fn with_on_complete(mut self, on_complete: DialogueCompleteFn) -> Self {
+        self.on_complete = Some(on_complete);
+        self
+    }
+}
+
+/// A bundle for spawning a dialogue engine entity, which loads the dialogue from its
+/// [YarnData] as usual and can be positioned in the world, for instance for speech bubbles:
+///
+/// ```ignore
+/// commands.spawn(BevyYarnDialogueEngineBundle {
+///     data: YarnData::new("npc.yarnc"),
+///     transform: Transform::from_xyz(10.0, 0.0, 0.0),
+///     ..default()
+/// });
+/// ```
+///
+/// [BevyYarnDialogueEngineBundle::headless] creates the dialogue from the contents of
+/// the compiled files instead, skipping the asset server.
+#[derive(Bundle, Default)]
+pub struct BevyYarnDialogueEngineBundle {
+    /// The dialogue to load
+    pub data: YarnData,
+    /// The compiled dialogue files for headless bundles, empty for dialogues loaded from
+    /// [BevyYarnDialogueEngineBundle::data]
+    pub headless: YarnHeadlessData,
+    /// The position of the dialogue engine entity
+    pub transform: Transform,
+    /// The global position of the dialogue engine entity
+    pub global_transform: GlobalTransform,
+}
+
+impl BevyYarnDialogueEngineBundle {
+    /// Creates a bundle for a dialogue from the contents of a yarnc file and its lines and
+    /// metadata CSV files, which starts as soon as it has been spawned. The files are
+    /// decoded by the [crate::YarnPlugin] without the asset server, and an error is logged
+    /// if they are invalid.
+    ///
+    /// ```ignore
+    /// commands.spawn(BevyYarnDialogueEngineBundle::headless(
+    ///     include_bytes!("../assets/minimal.yarnc"),
+    ///     include_bytes!("../assets/minimal.lines.csv"),
+    ///     include_bytes!("../assets/minimal.metadata.csv"),
+    /// ));
+    /// ```
+    pub fn headless(yarnc: &[u8], lines_csv: &[u8], metadata_csv: &[u8]) -> Self {
+        Self {
+            headless: YarnHeadlessData {
+                yarnc: yarnc.to_vec(),
+                lines_csv: lines_csv.to_vec(),
+                metadata_csv: metadata_csv.to_vec(),
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// The contents of the compiled dialogue files for a
+/// [BevyYarnDialogueEngineBundle::headless] dialogue. This is removed once the dialogue
+/// engine has been created. Empty data is ignored.
+#[derive(Component, Debug, Clone, Default)]
+pub struct YarnHeadlessData {
+    /// The contents of the yarnc file
+    pub yarnc: Vec<u8>,
+    /// The contents of the lines CSV file
+    pub lines_csv: Vec<u8>,
+    /// The contents of the metadata CSV file
+    pub metadata_csv: Vec<u8>,
+}
+
+impl YarnHeadlessData {
+    /// Whether there are no compiled files, so the dialogue is loaded from its [YarnData]
+    pub fn is_empty(&self) -> bool {
+        self.yarnc.is_empty()
+    }
+}
+
+/// Runs a single global conversation, for games that only ever have one conversation at a
+/// time. Rather than spawning an entity with a [YarnData] component, [YarnDialogue::start]
+/// inserts a [YarnData] resource which is loaded into a [BevyYarnDialogueEngine] resource:
+///
+/// ```ignore
+/// fn start_intro(mut commands: Commands) {
+///     YarnDialogue::start(&mut commands, "dialogue/intro.yarnc");
+/// }
+///
+/// fn show_choices(engine: Option<Res<BevyYarnDialogueEngine>>) {
+///     if let Some(engine) = engine {
+///         info!("{} choices", engine.num_choices);
+///     }
+/// }
+/// ```
+///
+/// The resource engine is stepped by [crate::prelude::BevyYarnStepDialogueEvent]s along with
+/// any engine components, and raises the usual [crate::prelude::BevyYarnEvent]s. Events that
+/// refer to an engine entity use [Entity::PLACEHOLDER] for the resource engine. The
+/// [crate::prelude::DialogueDebugInfo] and [crate::prelude::DialogueTrace] components are
+/// not available in this mode.
+pub struct YarnDialogue;
+
+impl YarnDialogue {
+    /// Starts the conversation in the yarnc file at the given path, replacing any existing
+    /// resource conversation
+    pub fn start<P: Into<String>>(commands: &mut Commands, yarnc_path: P) {
+        Self::start_with(commands, YarnData::new(yarnc_path));
+    }
+
+    /// Starts the conversation described by the given [YarnData], replacing any existing
+    /// resource conversation
+    pub fn start_with(commands: &mut Commands, data: YarnData) {
+        commands.remove_resource::<BevyYarnDialogueEngine>();
+        commands.insert_resource(data);
+    }
+
+    /// Stops the resource conversation, raising an aborted
+    /// [crate::prelude::BevyYarnEvent::EndConversation] if it hadn't completed
+    pub fn stop(commands: &mut Commands) {
+        commands.remove_resource::<BevyYarnDialogueEngine>();
+        commands.remove_resource::<YarnData>();
+    }
 }
 
 /// Represents a choice that can be made, including some metadata
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Reflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[reflect(from_reflect = false)]
 pub struct BevyYarnChoice {
     /// The line ID for this choice
-    pub line_id: String,
+    pub line_id: LineId,
+    /// The index of this choice in the virtual machine's options, to select with
+    /// [crate::BevyYarnDialogueEngine::select_option]. The virtual machine leaves out
+    /// unavailable options, so this is also the position of this choice in its
+    /// [crate::prelude::BevyYarnEvent::Choices] event.
+    pub option_index: usize,
     /// The destination node that this choice navigates to
-    pub destination_node: String,
+    pub destination_node: NodeName,
+    /// A human friendly title for [BevyYarnChoice::destination_node], for previewing where
+    /// the choice leads. This is the node's `title` header, or the node name if it has none,
+    /// see [crate::prelude::BevyYarnProgram::node_title].
+    pub destination_title: String,
+    /// Whether the dialogue has already visited [BevyYarnChoice::destination_node], for
+    /// instance by selecting this choice before, see
+    /// [crate::BevyYarnDialogueEngine::has_visited]. This lets UIs show choices that revisit
+    /// content differently from choices that lead somewhere new.
+    pub was_previously_selected: bool,
     /// The line to display for this choice
     pub formatted_line: BevyYarnLine,
 }
 
-/// Represents a line that that can be said, including some metadata
-#[derive(Debug, Clone)]
+/// Represents a line that that can be said, including some metadata. Lines are hashed on
+/// their line id only, to avoid hashing the formatted text. The line metadata, text and
+/// substitutions are shared, so cloning a line only copies its tags. Lines are built by the
+/// plugin, or with [BevyYarnLine::new] for tests and custom line sources.
+#[derive(Debug, Clone, Reflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[reflect(from_reflect = false)]
+#[non_exhaustive]
 pub struct BevyYarnLine {
     /// The line metadata from the Yarn engine
-    pub line: Line,
-    /// The formatted text, including any substitutions and with formatting functions expanded
-    pub formatted_text: String,
+    #[reflect(ignore)]
+    #[cfg_attr(feature = "serde", serde(with = "crate::remote::serde_line"))]
+    pub line: Arc<Line>,
+    /// The formatted text, including any substitutions and with formatting functions expanded.
+    /// Lines without substitutions or format functions share the text of their string
+    /// table entry rather than copying it.
+    #[reflect(ignore)]
+    pub formatted_text: Arc<str>,
+    /// The text of the line's string table entry before substitutions are completed and
+    /// format functions are expanded, or empty if the line is missing from the string table
+    #[reflect(ignore)]
+    pub raw_text: Arc<str>,
     /// If the line is prefixed with "<characer name>: ", this is trimmed from the text and available here.
     pub character: Option<String>,
-    /// A list of tags associated with this line
+    /// Who the line is spoken by, telling narration and stage directions apart from
+    /// character lines, see [YarnActorConfig]. This is `None` for lines with no character
+    /// that aren't tagged as narration or stage directions.
+    pub actor_type: Option<YarnActorType>,
+    /// A list of the free-form tags associated with this line, such as `#sad`. Tags in the
+    /// form `#key:value` are in [BevyYarnLine::structured_tags] instead.
     pub tags: Vec<String>,
+    /// The tags in the form `#key:value` associated with this line, such as `#file:intro.yarn`,
+    /// by key
+    pub structured_tags: HashMap<String, String>,
+    /// The locale of the string table the line's text was taken from, see
+    /// [crate::prelude::YarnLocaleChain]
+    pub resolved_locale: String,
+    /// How long the line should stay on screen for, for auto-advance and subtitle timing.
+    /// This is estimated from the length of the text by the [YarnTimingConfig], unless the
+    /// line has a `#duration:<seconds>` tag.
+    pub estimated_duration: Duration,
+    /// The emotion of the line from its `#emotion:<emotion>` tag, for instance for choosing
+    /// a portrait or shaking the text box. See [YarnLineTagKeys] for the tags read.
+    pub emotion: Option<String>,
+    /// The voice over clip of the line from its `#voice:<clip>` tag. See [YarnLineTagKeys]
+    /// for the tags read.
+    pub voice: Option<String>,
+    /// The entity registered for the [BevyYarnLine::character] in the
+    /// [crate::prelude::YarnCharacterRegistry] when the line was said, if any. This is
+    /// `None` for choices, and for lines that haven't been sent yet.
+    pub speaker_entity: Option<Entity>,
+    /// Whether the line is being re-sent by [crate::BevyYarnDialogueEngine::replay_history]
+    /// rather than said for the first time
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub is_replay: bool,
+}
+
+impl PartialEq for BevyYarnLine {
+    fn eq(&self, other: &Self) -> bool {
+        self.line.id == other.line.id
+            && self.line.substitutions == other.line.substitutions
+            && self.formatted_text == other.formatted_text
+            && self.raw_text == other.raw_text
+            && self.character == other.character
+            && self.actor_type == other.actor_type
+            && self.tags == other.tags
+            && self.structured_tags == other.structured_tags
+            && self.resolved_locale == other.resolved_locale
+            && self.emotion == other.emotion
+            && self.voice == other.voice
+            && self.is_replay == other.is_replay
+    }
+}
+
+impl Eq for BevyYarnLine {}
+
+impl std::hash::Hash for BevyYarnLine {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.line.id.hash(state);
+    }
+}
+
+impl BevyYarnLine {
+    /// Creates a line with the given text and no character, tags or speaker, for instance to
+    /// say a line that isn't in a string table or to build fixtures in tests. The raw text is
+    /// the same as the formatted text, the locale is [crate::LOCALE] and the duration is
+    /// estimated with the default [YarnTimingConfig]. The other fields can be set afterwards:
+    ///
+    /// ```ignore
+    /// let mut line = BevyYarnLine::new(Line { id: "line:1".into(), substitutions: vec![] }, "Halt!");
+    /// line.character = Some("Guard".into());
+    /// ```
+    pub fn new(line: Line, formatted_text: impl Into<Arc<str>>) -> Self {
+        let formatted_text = formatted_text.into();
+
+        Self {
+            line: Arc::new(line),
+            raw_text: formatted_text.clone(),
+            estimated_duration: YarnTimingConfig::default()
+                .estimate(&formatted_text, &HashMap::new()),
+            formatted_text,
+            character: None,
+            actor_type: None,
+            tags: Vec::new(),
+            structured_tags: HashMap::new(),
+            resolved_locale: crate::LOCALE.to_owned(),
+            emotion: None,
+            voice: None,
+            speaker_entity: None,
+            is_replay: false,
+        }
+    }
+
+    /// The values substituted into the line's text, in order. These are read from
+    /// [BevyYarnLine::line], which is shared, so cloning a line doesn't copy them.
+    pub fn substitutions(&self) -> &[String] {
+        &self.line.substitutions
+    }
+
+    /// Whether the formatted text contains any markup tags, such as `[b]bold[/b]` or
+    /// `[wave size=2]`. Brackets escaped with `\`, and brackets that don't contain a tag name
+    /// such as `[0]` or `[ ]`, are not markup.
+    pub fn contains_markup(&self) -> bool {
+        markup_tags(&self.formatted_text).next().is_some()
+    }
+
+    /// Lists the distinct names of the markup tags in the formatted text, in the order they
+    /// first appear. Opening and closing tags have the same name, so `[b]bold[/b]` gives
+    /// `["b"]`. The `[/]` tag that closes all open tags has no name so isn't listed.
+    pub fn markup_tag_names(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+
+        for (_, name) in markup_tags(&self.formatted_text) {
+            if !name.is_empty() && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+
+        names
+    }
+
+    /// The formatted text with its markup tags removed and escaped brackets unescaped, for
+    /// showing the line somewhere markup isn't supported such as subtitles
+    pub fn plain_text(&self) -> String {
+        strip_markup(&self.formatted_text)
+            .replace("\\[", "[")
+            .replace("\\]", "]")
+    }
+}
+
+/// The text with its markup tags removed, leaving escaped brackets escaped
+fn strip_markup(text: &str) -> String {
+    let mut plain_text = String::with_capacity(text.len());
+    let mut end_of_tag = 0;
+    for (range, _) in markup_tags(text) {
+        plain_text.push_str(&text[end_of_tag..range.start]);
+        end_of_tag = range.end;
+    }
+    plain_text.push_str(&text[end_of_tag..]);
+    plain_text
+}
+
+/// Finds the first unescaped occurrence of the character in the text
+fn find_unescaped(text: &str, target: char) -> Option<usize> {
+    let mut escaped = false;
+
+    text.char_indices().find_map(|(index, c)| {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == target {
+            return Some(index);
+        }
+        None
+    })
+}
+
+/// Iterates the byte ranges and names of the markup tags in the text, giving an empty name
+/// for `[/]`
+fn markup_tags(text: &str) -> impl Iterator<Item = (Range<usize>, &str)> {
+    let mut offset = 0;
+
+    std::iter::from_fn(move || loop {
+        let rest = &text[offset..];
+        let open = find_unescaped(rest, '[')?;
+        let after_open = &rest[open + 1..];
+        let Some(close) = find_unescaped(after_open, ']') else {
+            offset = text.len();
+            return None;
+        };
+
+        let inner = &after_open[..close];
+        let body = inner.strip_prefix('/').unwrap_or(inner).trim_start();
+        let name_len = body
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(body.len());
+        let (name, attributes) = body.split_at(name_len);
+
+        let is_close_all = inner.starts_with('/') && body.trim_end().is_empty();
+        let is_tag = name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+            && (attributes.is_empty() || attributes.starts_with([' ', '=', '/']))
+            && find_unescaped(inner, '[').is_none();
+
+        let start = offset + open;
+        if is_close_all || is_tag {
+            offset = start + close + 2;
+            return Some((start..offset, name));
+        }
+
+        // literal brackets, such as `[0]`, so look for a tag after the opening bracket
+        offset = start + 1;
+    })
+}
+
+/// A resource with the reading speed used to estimate [BevyYarnLine::estimated_duration].
+/// Each line stays on screen for the base time, plus time for each character and word of
+/// its text. Markup tags don't count towards the length of the text.
+#[derive(Debug, Clone, Resource)]
+pub struct YarnTimingConfig {
+    /// The time every line stays on screen for, in seconds
+    pub base_seconds: f32,
+    /// The additional time for each character of the line, in seconds
+    pub seconds_per_character: f32,
+    /// The additional time for each word of the line, in seconds
+    pub seconds_per_word: f32,
+}
+
+impl Default for YarnTimingConfig {
+    fn default() -> Self {
+        Self {
+            base_seconds: 1.0,
+            seconds_per_character: 0.05,
+            seconds_per_word: 0.0,
+        }
+    }
+}
+
+impl YarnTimingConfig {
+    /// The structured tag that overrides the estimated duration of a line, in seconds, for
+    /// instance `#duration:3.5`
+    pub const DURATION_TAG: &'static str = "duration";
+
+    /// Estimates how long a line with the given text and structured tags should stay on
+    /// screen, see [BevyYarnLine::estimated_duration]
+    pub fn estimate(&self, text: &str, structured_tags: &HashMap<String, String>) -> Duration {
+        if let Some(duration) = structured_tags.get(Self::DURATION_TAG) {
+            match duration.parse::<f32>() {
+                Ok(seconds) if seconds.is_finite() && seconds >= 0.0 => {
+                    return Duration::from_secs_f32(seconds)
+                }
+                _ => warn!(
+                    "Invalid #{}:{duration} tag, estimating instead",
+                    Self::DURATION_TAG
+                ),
+            }
+        }
+
+        let plain_text = strip_markup(text);
+        let seconds = self.base_seconds
+            + self.seconds_per_character * plain_text.chars().count() as f32
+            + self.seconds_per_word * plain_text.split_whitespace().count() as f32;
+
+        Duration::from_secs_f32(seconds.max(0.0))
+    }
+}
+
+/// Who a line is spoken by, see [BevyYarnLine::actor_type]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Reflect)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum YarnActorType {
+    /// A character with the given name, from the line's [BevyYarnLine::character]
+    Character(String),
+    /// Narration, with one of the [YarnActorConfig::narrator_prefixes] or
+    /// [YarnActorConfig::narrator_tags]
+    Narrator,
+    /// A stage direction, such as `stage: The lights dim`
+    StageDirection,
+}
+
+/// A resource with the character prefixes and line tags that mark lines as narration or
+/// stage directions rather than character lines, see [BevyYarnLine::actor_type]. Tags are
+/// checked before prefixes, and prefixes are compared ignoring case. Lines with one of the
+/// prefixes keep it as their [BevyYarnLine::character]. Lines with no character that have
+/// none of the tags have no actor type.
+///
+/// ```yarn
+/// Narrator: The guard eyes you suspiciously.
+/// The lights dim. #stage
+/// ```
+#[derive(Debug, Clone, Resource)]
+pub struct YarnActorConfig {
+    /// The character names of narration lines, `narrator` by default
+    pub narrator_prefixes: Vec<String>,
+    /// The character names of stage directions, `stage` by default
+    pub stage_direction_prefixes: Vec<String>,
+    /// The free-form tags of narration lines, `#narrator` by default
+    pub narrator_tags: Vec<String>,
+    /// The free-form tags of stage directions, `#stage` by default
+    pub stage_direction_tags: Vec<String>,
+}
+
+impl Default for YarnActorConfig {
+    fn default() -> Self {
+        Self {
+            narrator_prefixes: vec!["narrator".into()],
+            stage_direction_prefixes: vec!["stage".into()],
+            narrator_tags: vec!["narrator".into()],
+            stage_direction_tags: vec!["stage".into()],
+        }
+    }
+}
+
+impl YarnActorConfig {
+    /// The actor type of a line with the given character and free-form tags, or `None` if
+    /// the line has no character and none of the tags
+    pub fn actor_type(&self, character: Option<&str>, tags: &[String]) -> Option<YarnActorType> {
+        let has_tag = |tags_for_type: &[String]| tags.iter().any(|tag| tags_for_type.contains(tag));
+        let has_prefix = |prefixes: &[String]| {
+            character.is_some_and(|character| {
+                prefixes
+                    .iter()
+                    .any(|prefix| prefix.eq_ignore_ascii_case(character))
+            })
+        };
+
+        if has_tag(&self.stage_direction_tags) {
+            Some(YarnActorType::StageDirection)
+        } else if has_tag(&self.narrator_tags) {
+            Some(YarnActorType::Narrator)
+        } else if has_prefix(&self.stage_direction_prefixes) {
+            Some(YarnActorType::StageDirection)
+        } else if has_prefix(&self.narrator_prefixes) {
+            Some(YarnActorType::Narrator)
+        } else {
+            character.map(|character| YarnActorType::Character(character.to_owned()))
+        }
+    }
+}
+
+/// A resource with the structured tag keys read into the well known fields of a
+/// [BevyYarnLine], such as [BevyYarnLine::emotion]. Each field is read from the first of
+/// its keys that the line has a tag for, so keys can be added for projects that use
+/// different names, for instance `#mood:angry`. The tags also stay in
+/// [BevyYarnLine::structured_tags].
+#[derive(Debug, Clone, Resource)]
+pub struct YarnLineTagKeys {
+    /// The keys read into [BevyYarnLine::emotion], `emotion` by default
+    pub emotion: Vec<String>,
+    /// The keys read into [BevyYarnLine::voice], `voice` by default
+    pub voice: Vec<String>,
+}
+
+impl Default for YarnLineTagKeys {
+    fn default() -> Self {
+        Self {
+            emotion: vec!["emotion".into()],
+            voice: vec!["voice".into()],
+        }
+    }
+}
+
+impl YarnLineTagKeys {
+    /// Finds the value of the first of the given keys in the structured tags
+    pub(crate) fn find(
+        keys: &[String],
+        structured_tags: &HashMap<String, String>,
+    ) -> Option<String> {
+        keys.iter()
+            .find_map(|key| structured_tags.get(key))
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(text: &str) -> BevyYarnLine {
+        let line = Line {
+            id: "line:1".into(),
+            substitutions: Vec::new(),
+        };
+        BevyYarnLine::new(line, text)
+    }
+
+    #[test]
+    fn finds_markup_tag_names() {
+        let line = line("[b]Bold[/b], [wave size=2]wavy[/wave] and [b]bold again[/b][pause=500/]");
+
+        assert!(line.contains_markup());
+        assert_eq!(line.markup_tag_names(), ["b", "wave", "pause"]);
+        assert_eq!(line.plain_text(), "Bold, wavy and bold again");
+    }
+
+    #[test]
+    fn close_all_tags_are_markup_without_a_name() {
+        let line = line("[b][i]Loud[/]");
+
+        assert!(line.contains_markup());
+        assert_eq!(line.markup_tag_names(), ["b", "i"]);
+        assert!(self::line("[/]").contains_markup());
+        assert!(self::line("[/]").markup_tag_names().is_empty());
+    }
+
+    #[test]
+    fn escaped_and_literal_brackets_are_not_markup() {
+        for text in [
+            "Press \\[space\\] to continue",
+            "You scored [0] points",
+            "An empty [ ] box",
+            "An unclosed [bracket",
+            "A stray ] bracket",
+        ] {
+            let line = line(text);
+            assert!(!line.contains_markup(), "{text} has no markup");
+            assert!(line.markup_tag_names().is_empty());
+        }
+
+        assert_eq!(
+            line("Press \\[space\\] to [b]continue[/b]").plain_text(),
+            "Press [space] to continue"
+        );
+    }
+
+    #[test]
+    fn cloned_lines_share_their_substitutions_and_text() {
+        let substitutions = (0..10).map(|index| index.to_string()).collect::<Vec<_>>();
+        let original = BevyYarnLine::new(
+            Line {
+                id: "line:1".into(),
+                substitutions: substitutions.clone(),
+            },
+            "0 1 2 3 4 5 6 7 8 9",
+        );
+
+        let clone = original.clone();
+
+        assert_eq!(clone.substitutions(), substitutions);
+        assert!(Arc::ptr_eq(&original.line, &clone.line));
+        assert!(std::ptr::eq(
+            original.substitutions(),
+            clone.substitutions()
+        ));
+        assert!(Arc::ptr_eq(&original.formatted_text, &clone.formatted_text));
+    }
 }
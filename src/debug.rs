@@ -0,0 +1,572 @@
+//! Debugging support for dialogues. The [DialogueDebugInfo] component is a snapshot of an
+//! engine's state that is updated every frame for engines that have the component, for use
+//! in custom inspectors. With the `debug-plugin` feature, the [YarnDebugPlugin] adds the
+//! component to every engine and shows a simple text overlay. With the `debug-overlay`
+//! feature, the [BevyYarnDebugOverlayPlugin] shows the same information in egui panels.
+//!
+//! For bug reports, the [DialogueTrace] component records everything the virtual machine
+//! did into a bounded buffer that can be exported as JSON. Insert the [YarnTraceAll]
+//! resource to add a trace to every engine, for instance during playtests.
+//!
+//! Lines that dialogues reach but that are missing from their string table are recorded in
+//! the [YarnMissingLines] resource.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use bevy::{asset::HandleId, prelude::*};
+use chapter::SuspendReason;
+
+#[cfg(feature = "debug-plugin")]
+use crate::prelude::{BevyYarnStepDialogueEvent, NodeName, YarnSystemSet};
+use crate::{assets::BevyYarnStringTable, BevyYarnDialogueEngine};
+
+/// The number of recent suspend reasons kept in [DialogueDebugInfo::recent_suspend_reasons]
+pub const DEBUG_INFO_RECENT_REASONS: usize = 8;
+
+/// A snapshot of a dialogue engine's state, updated every frame. Insert this component on
+/// an entity with a [BevyYarnDialogueEngine] to start gathering debug information for it.
+#[derive(Component, Debug, Default, Clone)]
+pub struct DialogueDebugInfo {
+    /// The name of the engine, see [BevyYarnDialogueEngine::engine_name]
+    pub engine_name: String,
+    /// The node the engine is currently running, if known
+    pub current_node: Option<String>,
+    /// The number of choices currently available
+    pub num_choices: usize,
+    /// Whether the dialogue is complete
+    pub is_complete: bool,
+    /// The number of sub-conversations that are running
+    pub call_depth: usize,
+    /// The last error raised by the virtual machine, if any
+    pub last_error: Option<String>,
+    /// Descriptions of the most recent suspend reasons raised by the virtual machine, oldest first
+    pub recent_suspend_reasons: VecDeque<String>,
+    /// The name and value of every variable stored in the virtual machine, sorted by name
+    pub variables: Vec<(String, String)>,
+}
+
+impl DialogueDebugInfo {
+    /// Records a suspend reason raised by the virtual machine, keeping only the most recent
+    pub(crate) fn record_suspend_reason(&mut self, reason: &SuspendReason) {
+        let description = describe_suspend_reason(reason);
+
+        if self.recent_suspend_reasons.len() == DEBUG_INFO_RECENT_REASONS {
+            self.recent_suspend_reasons.pop_front();
+        }
+        self.recent_suspend_reasons.push_back(description);
+    }
+
+    /// Updates the snapshot from the given engine
+    pub(crate) fn update(&mut self, engine: &BevyYarnDialogueEngine) {
+        self.engine_name.clone_from(&engine.engine_name);
+        self.current_node.clone_from(&engine.current_node);
+        self.num_choices = engine.num_choices;
+        self.is_complete = engine.is_complete;
+        self.call_depth = engine.call_depth();
+        self.last_error.clone_from(&engine.last_error);
+
+        self.variables = engine
+            .vm
+            .variable_storage
+            .iter()
+            .map(|(name, value)| (name.clone(), format!("{value:?}")))
+            .collect();
+        self.variables.sort();
+    }
+
+    /// Adds [DialogueDebugInfo] to every dialogue engine that doesn't have it yet
+    #[cfg(any(feature = "debug-plugin", feature = "debug-overlay"))]
+    pub(crate) fn attach_all(
+        mut commands: Commands,
+        engines: Query<Entity, (With<BevyYarnDialogueEngine>, Without<DialogueDebugInfo>)>,
+    ) {
+        for entity in engines.iter() {
+            commands.entity(entity).insert(DialogueDebugInfo::default());
+        }
+    }
+
+    /// Updates the debug info for all engines that have the [DialogueDebugInfo] component
+    pub(crate) fn update_all(
+        mut engines: Query<(&BevyYarnDialogueEngine, &mut DialogueDebugInfo)>,
+    ) {
+        for (engine, mut debug_info) in engines.iter_mut() {
+            debug_info.update(engine);
+        }
+    }
+}
+
+/// Describes a suspend reason in a single line
+pub(crate) fn describe_suspend_reason(reason: &SuspendReason) -> String {
+    match reason {
+        SuspendReason::Nop => "Nop".to_owned(),
+        SuspendReason::Line(line) => format!("Line {}", line.id),
+        SuspendReason::Options(options) => format!("Options ({})", options.len()),
+        SuspendReason::Command(cmd_text) => format!("Command {cmd_text}"),
+        SuspendReason::NodeChange { start, end } => format!("NodeChange {start} -> {end}"),
+        SuspendReason::DialogueComplete(last_node) => format!("DialogueComplete {last_node}"),
+        SuspendReason::InvalidOption(option) => format!("InvalidOption {option}"),
+        // suspend reasons added by newer versions of the virtual machine
+        #[allow(unreachable_patterns)]
+        _ => "Unknown".to_owned(),
+    }
+}
+
+/// The number of entries a [DialogueTrace] keeps by default
+pub const DEFAULT_TRACE_CAPACITY: usize = 512;
+
+/// A single step of the virtual machine recorded by a [DialogueTrace]
+#[derive(Debug, Clone)]
+pub struct DialogueTraceEntry {
+    /// The number of the [crate::prelude::BevyYarnStepDialogueEvent] that triggered this
+    /// step, counted from when the app started
+    pub step: u64,
+    /// The time since startup in seconds when the step happened
+    pub time: f64,
+    /// The name of the engine that was running, see [BevyYarnDialogueEngine::engine_name]
+    pub engine_name: String,
+    /// The node the engine was in before the step, if known
+    pub node: Option<String>,
+    /// A description of the suspend reason, or of the error raised by the virtual machine
+    pub reason: String,
+    /// The id of the line that was said, if any
+    pub line_id: Option<String>,
+    /// The text of the command that was run, if any
+    pub command: Option<String>,
+}
+
+/// Records every suspend reason raised by the engine on the same entity into a bounded
+/// buffer, dropping the oldest entries when it is full. Engines without this component
+/// aren't traced, use [YarnTraceAll] to add it to every engine.
+#[derive(Component, Debug, Clone)]
+pub struct DialogueTrace {
+    entries: VecDeque<DialogueTraceEntry>,
+    capacity: usize,
+}
+
+impl Default for DialogueTrace {
+    fn default() -> Self {
+        Self::new(DEFAULT_TRACE_CAPACITY)
+    }
+}
+
+impl DialogueTrace {
+    /// Creates a trace that keeps up to `capacity` entries
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity.min(DEFAULT_TRACE_CAPACITY)),
+            capacity,
+        }
+    }
+
+    /// The recorded entries, oldest first
+    pub fn entries(&self) -> impl Iterator<Item = &DialogueTraceEntry> {
+        self.entries.iter()
+    }
+
+    /// Removes all recorded entries
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Exports the recorded entries as a JSON array, for attaching to bug reports
+    pub fn export_json(&self) -> String {
+        serde_json::Value::Array(
+            self.entries
+                .iter()
+                .map(|entry| {
+                    serde_json::json!({
+                        "step": entry.step,
+                        "time": entry.time,
+                        "engine_name": entry.engine_name,
+                        "node": entry.node,
+                        "reason": entry.reason,
+                        "line_id": entry.line_id,
+                        "command": entry.command,
+                    })
+                })
+                .collect(),
+        )
+        .to_string()
+    }
+
+    fn push(&mut self, entry: DialogueTraceEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Records a suspend reason raised by the given engine
+    pub(crate) fn record_suspend_reason(
+        &mut self,
+        step: u64,
+        time: f64,
+        engine: &BevyYarnDialogueEngine,
+        reason: &SuspendReason,
+    ) {
+        self.push(DialogueTraceEntry {
+            step,
+            time,
+            engine_name: engine.engine_name.clone(),
+            node: engine.current_node.clone(),
+            reason: describe_suspend_reason(reason),
+            line_id: match reason {
+                SuspendReason::Line(line) => Some(line.id.clone()),
+                _ => None,
+            },
+            command: match reason {
+                SuspendReason::Command(cmd_text) => Some(cmd_text.clone()),
+                _ => None,
+            },
+        });
+    }
+
+    /// Records an error raised by the given engine
+    pub(crate) fn record_error(
+        &mut self,
+        step: u64,
+        time: f64,
+        engine: &BevyYarnDialogueEngine,
+        error: String,
+    ) {
+        self.push(DialogueTraceEntry {
+            step,
+            time,
+            engine_name: engine.engine_name.clone(),
+            node: engine.current_node.clone(),
+            reason: format!("Error {error}"),
+            line_id: None,
+            command: None,
+        });
+    }
+
+    /// Adds a [DialogueTrace] to every dialogue engine that doesn't have one yet
+    pub(crate) fn attach_all(
+        mut commands: Commands,
+        engines: Query<Entity, (With<BevyYarnDialogueEngine>, Without<DialogueTrace>)>,
+    ) {
+        for entity in engines.iter() {
+            commands.entity(entity).insert(DialogueTrace::default());
+        }
+    }
+}
+
+/// A resource recording the ids of lines that dialogues reached but that were missing
+/// from their string table, by string table. Each missing line is only warned about the
+/// first time it is reached, so a missing line in a looping node doesn't bury other logs.
+#[derive(Resource, Debug, Default, Clone)]
+pub struct YarnMissingLines {
+    lines: HashMap<HandleId, HashSet<String>>,
+    unreported: Vec<(String, Option<String>)>,
+}
+
+impl YarnMissingLines {
+    /// Records that a line is missing from the given string table, warning if it hasn't
+    /// been recorded before. Newly missing lines are also recorded in the
+    /// [crate::prelude::YarnDiagnostics] with the node the dialogue was running.
+    pub(crate) fn record(
+        &mut self,
+        string_table: &Handle<BevyYarnStringTable>,
+        line_id: &str,
+        node: Option<&str>,
+    ) {
+        let lines = self.lines.entry(string_table.id()).or_default();
+
+        if !lines.contains(line_id) {
+            warn!("Line id {line_id} missing from string table. Skipping");
+            lines.insert(line_id.to_owned());
+            self.unreported
+                .push((line_id.to_owned(), node.map(str::to_owned)));
+        }
+    }
+
+    /// Takes the lines newly recorded as missing since this was last called, with the node
+    /// the dialogue was running
+    pub(crate) fn take_unreported(
+        &mut self,
+    ) -> impl Iterator<Item = (String, Option<String>)> + '_ {
+        self.unreported.drain(..)
+    }
+
+    /// The number of distinct missing lines recorded across all string tables
+    pub fn count(&self) -> usize {
+        self.lines.values().map(HashSet::len).sum()
+    }
+
+    /// Whether no missing lines have been recorded
+    pub fn is_empty(&self) -> bool {
+        self.count() == 0
+    }
+
+    /// The ids of the lines recorded as missing from the given string table, sorted
+    pub fn missing_from(&self, string_table: &Handle<BevyYarnStringTable>) -> Vec<&str> {
+        let mut lines = self
+            .lines
+            .get(&string_table.id())
+            .map(|lines| lines.iter().map(String::as_str).collect::<Vec<_>>())
+            .unwrap_or_default();
+        lines.sort_unstable();
+        lines
+    }
+
+    /// Forgets the recorded lines, so each missing line is warned about again
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+}
+
+/// When this resource is present, a [DialogueTrace] is added to every dialogue engine
+#[derive(Resource, Debug, Default, Clone)]
+pub struct YarnTraceAll;
+
+/// An event that can be sent to control a dialogue engine while debugging
+#[derive(Event, Debug, Clone)]
+pub enum YarnDebugAction {
+    /// Steps all dialogues forward
+    Step,
+    /// Moves the given engine to the start of the named node and steps the dialogue
+    JumpToNode {
+        /// The entity with the engine to move
+        engine: Entity,
+        /// The name of the node to move to
+        node: NodeName,
+    },
+}
+
+/// Adds [DialogueDebugInfo] to every dialogue engine, handles [YarnDebugAction] events and
+/// shows a text overlay listing every engine's debug info. `F3` toggles the overlay and
+/// `F4` steps all dialogues.
+#[cfg(feature = "debug-plugin")]
+#[derive(Default)]
+pub struct YarnDebugPlugin;
+
+#[cfg(feature = "debug-plugin")]
+impl Plugin for YarnDebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<YarnDebugAction>()
+            .add_systems(Startup, Self::spawn_overlay)
+            .add_systems(
+                Update,
+                (
+                    DialogueDebugInfo::attach_all,
+                    (Self::handle_input, Self::handle_actions)
+                        .chain()
+                        .in_set(YarnSystemSet::Input),
+                    Self::update_overlay.after(DialogueDebugInfo::update_all),
+                ),
+            );
+    }
+}
+
+/// Marks the text entity that shows the debug overlay
+#[cfg(feature = "debug-plugin")]
+#[derive(Component)]
+pub struct YarnDebugOverlayText;
+
+#[cfg(feature = "debug-plugin")]
+impl YarnDebugPlugin {
+    fn spawn_overlay(mut commands: Commands) {
+        commands.spawn((
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font_size: 14.,
+                    color: Color::YELLOW,
+                    ..default()
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(5.),
+                right: Val::Px(5.),
+                ..default()
+            }),
+            YarnDebugOverlayText,
+        ));
+    }
+
+    fn handle_input(
+        keys: Res<Input<KeyCode>>,
+        mut actions: EventWriter<YarnDebugAction>,
+        mut overlays: Query<&mut Visibility, With<YarnDebugOverlayText>>,
+    ) {
+        if keys.just_pressed(KeyCode::F3) {
+            for mut visibility in overlays.iter_mut() {
+                *visibility = match *visibility {
+                    Visibility::Hidden => Visibility::Inherited,
+                    _ => Visibility::Hidden,
+                };
+            }
+        }
+
+        if keys.just_pressed(KeyCode::F4) {
+            actions.send(YarnDebugAction::Step);
+        }
+    }
+
+    fn handle_actions(
+        mut actions: EventReader<YarnDebugAction>,
+        mut event_sender: EventWriter<BevyYarnStepDialogueEvent>,
+        mut engines: Query<&mut BevyYarnDialogueEngine>,
+    ) {
+        for action in actions.iter() {
+            match action {
+                YarnDebugAction::Step => {
+                    info!("Debug stepping dialogues");
+                }
+                YarnDebugAction::JumpToNode { engine, node } => {
+                    let Ok(mut yarn_engine) = engines.get_mut(*engine) else {
+                        warn!("Unable to jump to node {node}, {engine:?} has no dialogue engine");
+                        continue;
+                    };
+
+                    if let Err(e) = yarn_engine.vm.set_node(node.as_str()) {
+                        warn!("Unable to jump to node {node}: {e:?}");
+                        continue;
+                    }
+
+                    info!("Debug jumping {} to node {node}", yarn_engine.engine_name);
+                    yarn_engine.set_current_node(node.to_string());
+                    yarn_engine.num_choices = 0;
+                    yarn_engine.is_complete = false;
+                }
+            }
+
+            event_sender.send(BevyYarnStepDialogueEvent);
+        }
+    }
+
+    fn update_overlay(
+        debug_infos: Query<&DialogueDebugInfo>,
+        mut overlays: Query<&mut Text, With<YarnDebugOverlayText>>,
+    ) {
+        let text = debug_infos
+            .iter()
+            .map(|info| {
+                format!(
+                    "{}\n  node: {}\n  choices: {}, complete: {}, depth: {}\n{}{}{}",
+                    info.engine_name,
+                    info.current_node.as_deref().unwrap_or("<unknown>"),
+                    info.num_choices,
+                    info.is_complete,
+                    info.call_depth,
+                    info.last_error
+                        .as_ref()
+                        .map(|e| format!("  error: {e}\n"))
+                        .unwrap_or_default(),
+                    info.recent_suspend_reasons
+                        .iter()
+                        .map(|reason| format!("  > {reason}\n"))
+                        .collect::<String>(),
+                    info.variables
+                        .iter()
+                        .map(|(name, value)| format!("  {name} = {value}\n"))
+                        .collect::<String>(),
+                )
+            })
+            .collect::<String>();
+
+        for mut overlay in overlays.iter_mut() {
+            if let Some(section) = overlay.sections.first_mut() {
+                section.value.clone_from(&text);
+            }
+        }
+    }
+}
+
+/// The number of recent events shown for each engine by [BevyYarnDebugOverlayPlugin]
+#[cfg(feature = "debug-overlay")]
+pub const DEBUG_OVERLAY_RECENT_EVENTS: usize = 5;
+
+/// Draws a small semi-transparent [bevy_egui] panel for each dialogue engine, titled with
+/// its [BevyYarnDialogueEngine::engine_name] and listing its current node, choices,
+/// completion state and most recent events. `F1` toggles the panels. Adds the
+/// [bevy_egui::EguiPlugin] if it hasn't already been added.
+#[cfg(feature = "debug-overlay")]
+#[derive(Default)]
+pub struct BevyYarnDebugOverlayPlugin;
+
+/// Whether the panels drawn by [BevyYarnDebugOverlayPlugin] are visible
+#[cfg(feature = "debug-overlay")]
+#[derive(Resource, Debug, Clone)]
+pub struct BevyYarnDebugOverlayVisible(pub bool);
+
+#[cfg(feature = "debug-overlay")]
+impl Default for BevyYarnDebugOverlayVisible {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+
+#[cfg(feature = "debug-overlay")]
+impl Plugin for BevyYarnDebugOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<bevy_egui::EguiPlugin>() {
+            app.add_plugins(bevy_egui::EguiPlugin);
+        }
+
+        app.init_resource::<BevyYarnDebugOverlayVisible>()
+            .add_systems(
+                Update,
+                (
+                    DialogueDebugInfo::attach_all,
+                    Self::toggle_overlay,
+                    Self::draw_overlay,
+                ),
+            );
+    }
+}
+
+#[cfg(feature = "debug-overlay")]
+impl BevyYarnDebugOverlayPlugin {
+    fn toggle_overlay(keys: Res<Input<KeyCode>>, mut visible: ResMut<BevyYarnDebugOverlayVisible>) {
+        if keys.just_pressed(KeyCode::F1) {
+            visible.0 = !visible.0;
+        }
+    }
+
+    fn draw_overlay(
+        mut contexts: bevy_egui::EguiContexts,
+        visible: Res<BevyYarnDebugOverlayVisible>,
+        debug_infos: Query<(Entity, &DialogueDebugInfo)>,
+    ) {
+        use bevy_egui::egui;
+
+        if !visible.0 {
+            return;
+        }
+
+        let frame = egui::Frame::window(&contexts.ctx_mut().style())
+            .fill(egui::Color32::from_black_alpha(160));
+
+        for (position, (entity, info)) in debug_infos.iter().enumerate() {
+            egui::Window::new(info.engine_name.as_str())
+                .id(egui::Id::new(("bevy_mod_yarn_debug_overlay", entity)))
+                .default_pos([8., 8. + 160. * position as f32])
+                .frame(frame)
+                .resizable(false)
+                .show(contexts.ctx_mut(), |ui| {
+                    ui.label(format!("{entity:?}"));
+                    ui.label(format!(
+                        "node: {}",
+                        info.current_node.as_deref().unwrap_or("<unknown>")
+                    ));
+                    ui.label(format!("num_choices: {}", info.num_choices));
+                    ui.label(format!("is_complete: {}", info.is_complete));
+
+                    ui.separator();
+                    let skip = info
+                        .recent_suspend_reasons
+                        .len()
+                        .saturating_sub(DEBUG_OVERLAY_RECENT_EVENTS);
+                    for reason in info.recent_suspend_reasons.iter().skip(skip) {
+                        ui.label(reason.as_str());
+                    }
+                });
+        }
+    }
+}
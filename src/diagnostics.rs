@@ -0,0 +1,257 @@
+//! Content issues found while loading and running dialogue, collected so they can be
+//! checked programmatically. Lines missing from their string table, commands with no
+//! registered handler, string or metadata tables that can't be parsed and characters with
+//! no registered entity are still logged, and are also recorded in the [YarnDiagnostics]
+//! resource:
+//!
+//! ```ignore
+//! fn fail_on_content_errors(diagnostics: Res<YarnDiagnostics>) {
+//!     for issue in diagnostics.issues() {
+//!         eprintln!("{:?} at {}s: {}", issue.severity, issue.time, issue.message);
+//!     }
+//!
+//!     assert!(!diagnostics.has_errors());
+//! }
+//! ```
+//!
+//! Unhandled commands are only recorded when a [crate::commands::YarnCommandLint] resource
+//! is present, as commands are often handled by reading the events instead.
+
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    assets::describe_table_error,
+    debug::YarnMissingLines,
+    ids::{LineId, NodeName},
+};
+
+/// How serious a [YarnDiagnostic] is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum YarnDiagnosticSeverity {
+    /// The dialogue can run, but may not show what was written
+    Warning,
+    /// Content couldn't be loaded
+    Error,
+}
+
+/// The kind of issue a [YarnDiagnostic] records
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum YarnDiagnosticKind {
+    /// A dialogue reached a line that is missing from its string table, see
+    /// [YarnMissingLines]
+    MissingLine,
+    /// A program uses a command with no registered handler
+    UnhandledCommand,
+    /// A string or metadata table couldn't be parsed
+    TableParseError,
+    /// A line was said by a character with no registered
+    /// [crate::prelude::YarnCharacter] entity
+    MissingSpeaker,
+}
+
+/// An issue recorded in the [YarnDiagnostics]
+#[derive(Debug, Clone, PartialEq)]
+pub struct YarnDiagnostic {
+    /// How serious the issue is
+    pub severity: YarnDiagnosticSeverity,
+    /// The kind of issue
+    pub kind: YarnDiagnosticKind,
+    /// A description of the issue, the same as the message that was logged
+    pub message: String,
+    /// The line the issue is with, if any
+    pub line_id: Option<LineId>,
+    /// The node the dialogue was running when the issue was found, if any
+    pub node: Option<NodeName>,
+    /// The path of the asset the issue is with, if known
+    pub path: Option<String>,
+    /// The elapsed app time in seconds of the frame the issue was recorded in. Table parse
+    /// errors are recorded in the first frame after their table fails to load.
+    pub time: f64,
+}
+
+impl YarnDiagnostic {
+    /// Creates an issue with no context, which is timestamped when it is recorded
+    pub(crate) fn new<M: Into<String>>(
+        severity: YarnDiagnosticSeverity,
+        kind: YarnDiagnosticKind,
+        message: M,
+    ) -> Self {
+        Self {
+            severity,
+            kind,
+            message: message.into(),
+            line_id: None,
+            node: None,
+            path: None,
+            time: 0.0,
+        }
+    }
+
+    /// Sets the line the issue is with
+    pub(crate) fn with_line_id(mut self, line_id: &str) -> Self {
+        self.line_id = Some(line_id.into());
+        self
+    }
+
+    /// Sets the node the dialogue was running, if known
+    pub(crate) fn with_node(mut self, node: Option<&str>) -> Self {
+        self.node = node.map(NodeName::from);
+        self
+    }
+
+    /// Sets the path of the asset the issue is with
+    pub(crate) fn with_path(mut self, path: &Path) -> Self {
+        self.path = Some(path.to_string_lossy().into_owned());
+        self
+    }
+}
+
+/// Issues recorded by the asset loaders, which load on other threads, until they are
+/// collected into the [YarnDiagnostics]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct YarnLoaderDiagnostics(Arc<Mutex<Vec<YarnDiagnostic>>>);
+
+impl YarnLoaderDiagnostics {
+    /// Logs and records that the table at the given path couldn't be parsed, returning the
+    /// error for the loader with the path and row of the error
+    pub(crate) fn table_error(&self, path: &Path, error: &csv::Error) -> bevy::asset::Error {
+        let message = describe_table_error(path, error);
+        error!("{message}");
+
+        if let Ok(mut issues) = self.0.lock() {
+            issues.push(
+                YarnDiagnostic::new(
+                    YarnDiagnosticSeverity::Error,
+                    YarnDiagnosticKind::TableParseError,
+                    message.clone(),
+                )
+                .with_path(path),
+            );
+        }
+
+        bevy::asset::Error::msg(message)
+    }
+}
+
+/// A resource with the content issues found while loading and running dialogue, oldest
+/// first, see the [module documentation](self)
+#[derive(Resource, Debug, Default)]
+pub struct YarnDiagnostics {
+    issues: Vec<YarnDiagnostic>,
+    pending: Vec<YarnDiagnostic>,
+    pub(crate) loader_diagnostics: YarnLoaderDiagnostics,
+}
+
+impl YarnDiagnostics {
+    /// The recorded issues, oldest first
+    pub fn issues(&self) -> &[YarnDiagnostic] {
+        &self.issues
+    }
+
+    /// The recorded issues of the given kind, oldest first
+    pub fn issues_of_kind(
+        &self,
+        kind: YarnDiagnosticKind,
+    ) -> impl Iterator<Item = &YarnDiagnostic> + '_ {
+        self.issues.iter().filter(move |issue| issue.kind == kind)
+    }
+
+    /// The number of recorded issues with the given severity
+    pub fn count(&self, severity: YarnDiagnosticSeverity) -> usize {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == severity)
+            .count()
+    }
+
+    /// Whether any [YarnDiagnosticSeverity::Error] issues have been recorded
+    pub fn has_errors(&self) -> bool {
+        self.count(YarnDiagnosticSeverity::Error) > 0
+    }
+
+    /// Whether no issues have been recorded
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Forgets the recorded issues. Missing lines are only recorded again once
+    /// [YarnMissingLines::clear] is called.
+    pub fn clear(&mut self) {
+        self.issues.clear();
+    }
+
+    /// Records an issue, which is timestamped once the dialogues have been stepped
+    pub(crate) fn push(&mut self, issue: YarnDiagnostic) {
+        self.pending.push(issue);
+    }
+
+    /// Timestamps and records the issues found this frame, including the lines first
+    /// recorded as missing and the tables that failed to load
+    pub(crate) fn collect(
+        mut diagnostics: ResMut<YarnDiagnostics>,
+        mut missing_lines: ResMut<YarnMissingLines>,
+        time: Res<Time>,
+    ) {
+        let diagnostics = diagnostics.as_mut();
+        let loaded = diagnostics
+            .loader_diagnostics
+            .0
+            .lock()
+            .map(|mut issues| std::mem::take(&mut *issues))
+            .unwrap_or_default();
+        let missing = missing_lines.take_unreported().map(|(line_id, node)| {
+            YarnDiagnostic::new(
+                YarnDiagnosticSeverity::Warning,
+                YarnDiagnosticKind::MissingLine,
+                format!("Line id {line_id} missing from string table. Skipping"),
+            )
+            .with_line_id(&line_id)
+            .with_node(node.as_deref())
+        });
+
+        let now = time.elapsed_seconds_f64();
+        let issues = loaded
+            .into_iter()
+            .chain(diagnostics.pending.drain(..))
+            .chain(missing)
+            .map(|issue| YarnDiagnostic { time: now, ..issue });
+
+        diagnostics.issues.extend(issues);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::BevyYarnStringTable;
+
+    #[test]
+    fn loader_table_errors_are_collected_with_their_path() {
+        let error = BevyYarnStringTable::from_csv(b"id,text,file,node,lineNumber\nline:a\n")
+            .expect_err("row 2 is short");
+        let mut app = App::new();
+        app.insert_resource(Time::default())
+            .init_resource::<YarnDiagnostics>()
+            .init_resource::<YarnMissingLines>()
+            .add_systems(Update, YarnDiagnostics::collect);
+
+        let loader_error = app
+            .world
+            .resource::<YarnDiagnostics>()
+            .loader_diagnostics
+            .table_error(Path::new("broken.lines.csv"), &error);
+        app.update();
+
+        let diagnostics = app.world.resource::<YarnDiagnostics>();
+        let issue = &diagnostics.issues()[0];
+        assert_eq!(issue.kind, YarnDiagnosticKind::TableParseError);
+        assert_eq!(issue.path.as_deref(), Some("broken.lines.csv"));
+        assert_eq!(issue.message, loader_error.to_string());
+        assert!(diagnostics.has_errors());
+    }
+}
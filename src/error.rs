@@ -0,0 +1,52 @@
+//! Errors raised by the dialogue engine
+
+use std::fmt::Display;
+
+use crate::ids::LineId;
+
+/// The reasons an operation on a [crate::BevyYarnDialogueEngine] can fail, also raised
+/// while stepping dialogue with [crate::prelude::BevyYarnEvent::Error]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BevyYarnError {
+    /// The JSON could not be parsed, with the parser's error message
+    InvalidJson(String),
+    /// The JSON was valid but is not an object mapping variable names to values
+    NotAnObject,
+    /// The virtual machine suspended for a reason that this version of the plugin doesn't
+    /// know how to handle, with a description of the reason. See
+    /// [crate::YarnPluginBuilder::with_unknown_suspend_reason_handler].
+    UnknownSuspendReason(String),
+    /// The dialogue reached a line that is missing from the string table, with the
+    /// [crate::prelude::MissingLinePolicy::Error] policy
+    MissingLine(LineId),
+    /// The node couldn't be started, for instance because it isn't in the program, with the
+    /// virtual machine's error message
+    InvalidNode(String),
+    /// The yarnc file or tables called with the [crate::CALL_COMMAND] failed to load, with
+    /// the path of the yarnc file
+    CallFailed(String),
+}
+
+impl Display for BevyYarnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidJson(e) => write!(f, "failed to parse JSON: {e}"),
+            Self::NotAnObject => write!(f, "expected a JSON object of variable names to values"),
+            Self::UnknownSuspendReason(reason) => write!(f, "unknown suspend reason {reason}"),
+            Self::MissingLine(line_id) => {
+                write!(f, "line {line_id} is missing from the string table")
+            }
+            Self::InvalidNode(e) => write!(f, "failed to start node: {e}"),
+            Self::CallFailed(path) => write!(f, "failed to load called program {path}"),
+        }
+    }
+}
+
+impl std::error::Error for BevyYarnError {}
+
+impl From<serde_json::Error> for BevyYarnError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::InvalidJson(value.to_string())
+    }
+}
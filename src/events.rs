@@ -1,9 +1,15 @@
 //! Events that are used to inject data from the Yarn state machine
 //! into the bevy ECS.
 
-use bevy::prelude::Event;
+use std::hash::{Hash, Hasher};
 
-use crate::prelude::{BevyYarnChoice, BevyYarnCommand, BevyYarnLine};
+use bevy::prelude::{Entity, Event, Resource};
+use bitflags::bitflags;
+use chapter::YarnValue;
+
+use crate::prelude::{
+    BevyYarnChoice, BevyYarnCommand, BevyYarnError, BevyYarnLine, LineId, NodeName,
+};
 
 /// An event that is raised when the dialogue should step forward
 #[derive(Event)]
@@ -11,14 +17,250 @@ pub struct BevyYarnStepDialogueEvent;
 
 /// Events that can be raised by the YarnEngine for processing
 /// within bevy (usually by client code)
-#[derive(Clone, Debug, Event)]
+///
+/// Events can be stored in a [std::collections::HashSet] to deduplicate them. Lines are
+/// hashed on their line id, commands on their name and [BevyYarnEvent::VariableSet] on
+/// the variable name.
+#[derive(Clone, Debug, PartialEq, Event)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BevyYarnEvent {
     /// Say a line
     Say(BevyYarnLine),
     /// Offer some choices
-    Choices(Vec<BevyYarnChoice>),
+    Choices {
+        /// The entity of the dialogue engine offering the choices, which the selection should
+        /// be sent to with a [crate::prelude::SelectChoiceCommand]. This is
+        /// [Entity::PLACEHOLDER] for a resource engine started with
+        /// [crate::prelude::YarnDialogue].
+        engine_entity: Entity,
+        /// The choices, in the order they should be shown
+        choices: Vec<BevyYarnChoice>,
+        /// Whether the choices are being re-sent by
+        /// [crate::BevyYarnDialogueEngine::replay_history] rather than offered for the first
+        /// time
+        #[cfg_attr(feature = "serde", serde(default))]
+        is_replay: bool,
+    },
     /// Run a command
     Command(BevyYarnCommand),
     /// End the conversation
-    EndConversation,
+    EndConversation {
+        /// The entity of the dialogue engine whose conversation ended, so listeners waiting
+        /// on a particular dialogue can tell it apart from others. This is
+        /// [Entity::PLACEHOLDER] for a resource engine started with
+        /// [crate::prelude::YarnDialogue].
+        engine_entity: Entity,
+        /// Whether the conversation ended because its engine was removed or despawned before
+        /// the dialogue completed, rather than by reaching the end of the dialogue
+        aborted: bool,
+    },
+    /// The dialogue engine on the given entity has loaded and is ready to be stepped. The
+    /// entity is [Entity::PLACEHOLDER] for a resource engine started with
+    /// [crate::prelude::YarnDialogue].
+    DialogueReady(Entity),
+    /// A variable was set while stepping the dialogue, for instance by `<<set $score = $score + 10>>`.
+    ///
+    /// The virtual machine doesn't report set statements separately, so these are found by
+    /// comparing the variable storage before and after each step. Setting a variable to the
+    /// value it already holds doesn't raise an event. Only sent if [YarnEventMask::VARIABLE_SET]
+    /// is enabled, which it isn't by default.
+    VariableSet {
+        /// The name of the variable, including the leading `$`
+        name: String,
+        /// The new value of the variable
+        #[cfg_attr(feature = "serde", serde(with = "crate::remote::serde_yarn_value"))]
+        value: YarnValue,
+    },
+    /// A choice was selected and the dialogue was stepped to follow it, for instance for
+    /// analytics on which branches players take
+    ChoiceSelected {
+        /// The line ID of the selected choice, see [BevyYarnChoice::line_id]
+        line_id: LineId,
+        /// The node the selected choice navigates to, see [BevyYarnChoice::destination_node]
+        destination_node: NodeName,
+    },
+    /// The dialogue couldn't be stepped, for instance because the virtual machine suspended
+    /// for a reason the plugin doesn't know how to handle
+    Error(BevyYarnError),
+    /// A step completed the reveal of the last line rather than advancing the dialogue, so
+    /// typewriters should show the whole line. Only sent if the plugin is built with
+    /// [crate::YarnPluginBuilder::with_line_reveal].
+    LineRevealComplete,
+    /// The next line is said by a different character than the last line of the dialogue,
+    /// for instance to swap portraits. Sent before the [BevyYarnEvent::Say] of the line, see
+    /// [crate::BevyYarnDialogueEngine::last_character]. Barks don't raise this event.
+    CharacterChanged {
+        /// The character of the last line, or `None` if it had no character or this is the
+        /// first line of the dialogue
+        from: Option<String>,
+        /// The character of the next line, or `None` if it has no character
+        to: Option<String>,
+    },
+}
+
+impl BevyYarnEvent {
+    /// Whether this is a line or choices re-sent by
+    /// [crate::BevyYarnDialogueEngine::replay_history]. Systems that should only react to a
+    /// line or choice once, such as analytics, can skip these.
+    pub fn is_replay(&self) -> bool {
+        match self {
+            Self::Say(line) => line.is_replay,
+            Self::Choices { is_replay, .. } => *is_replay,
+            _ => false,
+        }
+    }
+
+    /// This event marked as replayed, see [BevyYarnEvent::is_replay]
+    pub(crate) fn into_replay(mut self) -> Self {
+        match &mut self {
+            Self::Say(line) => line.is_replay = true,
+            Self::Choices { is_replay, .. } => *is_replay = true,
+            _ => {}
+        }
+
+        self
+    }
+}
+
+/// Yarn numbers are floats, so a [BevyYarnEvent::VariableSet] holding `NaN` is not equal
+/// to itself. Yarn Spinner doesn't produce `NaN`, so this is treated as an equivalence.
+impl Eq for BevyYarnEvent {}
+
+impl Hash for BevyYarnEvent {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+
+        match self {
+            Self::Say(line) => line.hash(state),
+            Self::Choices {
+                engine_entity,
+                choices,
+                ..
+            } => {
+                engine_entity.hash(state);
+                choices.hash(state);
+            }
+            Self::Command(command) => command.hash(state),
+            Self::EndConversation {
+                engine_entity,
+                aborted,
+            } => {
+                engine_entity.hash(state);
+                aborted.hash(state);
+            }
+            Self::DialogueReady(entity) => entity.hash(state),
+            Self::VariableSet { name, .. } => name.hash(state),
+            Self::ChoiceSelected {
+                line_id,
+                destination_node,
+            } => {
+                line_id.hash(state);
+                destination_node.hash(state);
+            }
+            Self::Error(error) => error.hash(state),
+            Self::LineRevealComplete => {}
+            Self::CharacterChanged { from, to } => {
+                from.hash(state);
+                to.hash(state);
+            }
+        }
+    }
+}
+
+bitflags! {
+    /// A resource that controls which [BevyYarnEvent]s are built and sent by the plugin.
+    /// Events that are not in the mask are skipped entirely, which avoids building lines,
+    /// tags and so on for events that the app never reads. Defaults to all events enabled
+    /// apart from [YarnEventMask::VARIABLE_SET], which copies the variable storage every step.
+    ///
+    /// Note that pre-registered command handlers still run if [YarnEventMask::COMMAND]
+    /// is not set, only the [BevyYarnEvent::Command] event is skipped.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Resource)]
+    pub struct YarnEventMask: u16 {
+        /// Send [BevyYarnEvent::Say] events
+        const SAY = 1 << 0;
+        /// Send [BevyYarnEvent::Choices] events
+        const CHOICES = 1 << 1;
+        /// Send [BevyYarnEvent::Command] events
+        const COMMAND = 1 << 2;
+        /// Send [BevyYarnEvent::EndConversation] events
+        const END_CONVERSATION = 1 << 3;
+        /// Send [BevyYarnEvent::DialogueReady] events
+        const DIALOGUE_READY = 1 << 4;
+        /// Send [BevyYarnEvent::VariableSet] events
+        const VARIABLE_SET = 1 << 5;
+        /// Send [BevyYarnEvent::ChoiceSelected] events
+        const CHOICE_SELECTED = 1 << 6;
+        /// Send [BevyYarnEvent::Error] events
+        const ERROR = 1 << 7;
+        /// Send [BevyYarnEvent::LineRevealComplete] events
+        const LINE_REVEAL_COMPLETE = 1 << 8;
+        /// Send [BevyYarnEvent::CharacterChanged] events
+        const CHARACTER_CHANGED = 1 << 9;
+    }
+}
+
+impl Default for YarnEventMask {
+    fn default() -> Self {
+        Self::all().difference(Self::VARIABLE_SET)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use chapter::Line;
+
+    use super::*;
+
+    fn say(id: &str, text: &str) -> BevyYarnEvent {
+        let line = Line {
+            id: id.into(),
+            substitutions: Vec::new(),
+        };
+        BevyYarnEvent::Say(BevyYarnLine::new(line, text))
+    }
+
+    #[test]
+    fn equal_events_are_deduplicated() {
+        let events = HashSet::from([
+            say("line:1", "Halt!"),
+            say("line:1", "Halt!"),
+            BevyYarnEvent::Command(BevyYarnCommand::parse("wave north")),
+            BevyYarnEvent::Command(BevyYarnCommand::parse("wave north")),
+            BevyYarnEvent::EndConversation {
+                engine_entity: Entity::PLACEHOLDER,
+                aborted: false,
+            },
+            BevyYarnEvent::EndConversation {
+                engine_entity: Entity::PLACEHOLDER,
+                aborted: false,
+            },
+        ]);
+
+        assert_eq!(events.len(), 3);
+    }
+
+    #[test]
+    fn events_with_the_same_hash_are_distinct() {
+        let mut events = HashSet::new();
+
+        // lines are hashed on their id and commands on their name only
+        assert!(events.insert(say("line:1", "Halt!")));
+        assert!(events.insert(say("line:1", "Halt, who goes there?")));
+        assert!(events.insert(BevyYarnEvent::Command(BevyYarnCommand::parse("wave north"))));
+        assert!(events.insert(BevyYarnEvent::Command(BevyYarnCommand::parse("wave south"))));
+        assert!(!events.insert(say("line:1", "Halt!")));
+
+        assert_eq!(events.len(), 4);
+    }
+
+    #[test]
+    fn replayed_events_are_distinct_from_live_events() {
+        let live = say("line:1", "Halt!");
+        let events = HashSet::from([live.clone(), live.into_replay()]);
+
+        assert_eq!(events.len(), 2);
+    }
 }
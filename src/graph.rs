@@ -0,0 +1,434 @@
+//! Static analysis of the node graph of a compiled yarn program, found by walking
+//! the compiled instructions for jumps and option destinations.
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    fmt::Write,
+};
+
+use chapter::{instruction::OpCode, operand::Value, Instruction, Node, Program};
+
+use crate::assets::BevyYarnStringTable;
+
+/// The maximum number of characters of option text to show on an edge
+const MAX_EDGE_LABEL_LEN: usize = 32;
+
+/// A jump from one node to another
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct NodeEdge {
+    /// The node the jump is from
+    pub from: String,
+    /// The node the jump is to
+    pub to: String,
+    /// The line id of the option that leads to this jump, if any
+    pub option_line_id: Option<String>,
+}
+
+/// A report of content issues found by statically analysing a program's node graph,
+/// see [crate::prelude::BevyYarnProgram::content_report]. All lists are sorted.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ContentReport {
+    /// Nodes that can't be reached from the start node. Nodes reached only by jumps computed
+    /// at runtime are included, as these can't be resolved statically.
+    pub unreachable_nodes: Vec<String>,
+    /// Nodes that don't jump to any other node, so the dialogue ends when they finish.
+    /// This is expected for the final nodes of a dialogue, so isn't treated as an issue.
+    pub nodes_with_no_exit: Vec<String>,
+    /// Jumps to nodes that don't exist in the program, as `(from, to)` node names.
+    /// These fail at runtime.
+    pub referenced_missing_nodes: Vec<(String, String)>,
+}
+
+impl ContentReport {
+    /// Returns true if the report has no unreachable nodes and no jumps to missing nodes
+    pub fn is_clean(&self) -> bool {
+        self.unreachable_nodes.is_empty() && self.referenced_missing_nodes.is_empty()
+    }
+}
+
+/// Where the instructions following an option label lead
+enum Destination {
+    /// A jump to a node with a statically known name, at the given instruction index
+    Node(usize, String),
+    /// A jump to a node whose name is computed at runtime
+    Dynamic,
+    /// The node stops or shows more options without jumping
+    None,
+}
+
+/// The nodes of a program and the jumps between them
+pub(crate) struct NodeGraph {
+    /// The names of all nodes in the program
+    pub nodes: HashSet<String>,
+    /// Every statically resolved jump between nodes
+    pub edges: Vec<NodeEdge>,
+    /// The names of nodes that contain jumps to nodes computed at runtime
+    pub dynamic_jumps: HashSet<String>,
+}
+
+fn opcode(instruction: &Instruction) -> Option<OpCode> {
+    OpCode::try_from(instruction.opcode).ok()
+}
+
+fn string_operand(instruction: &Instruction, index: usize) -> Option<&str> {
+    match instruction.operands.get(index)?.value.as_ref()? {
+        Value::StringValue(value) => Some(value),
+        _ => None,
+    }
+}
+
+/// Finds the destination of a `RunNode` instruction. The node name is either an operand,
+/// or pushed by the previous instruction. Anything else is computed at runtime.
+fn run_node_target(node: &Node, index: usize) -> Option<String> {
+    if let Some(target) = string_operand(&node.instructions[index], 0) {
+        return Some(target.to_owned());
+    }
+
+    let previous = node.instructions.get(index.checked_sub(1)?)?;
+    match opcode(previous) {
+        Some(OpCode::PushString) => string_operand(previous, 0).map(str::to_owned),
+        _ => None,
+    }
+}
+
+/// Follows the instructions from the given index until they jump to another node,
+/// or stop. Labelled jumps within the node are followed.
+fn find_destination(node: &Node, start: usize) -> Destination {
+    let mut index = start;
+    let mut visited = HashSet::new();
+
+    while let Some(instruction) = node.instructions.get(index) {
+        if !visited.insert(index) {
+            return Destination::None;
+        }
+
+        match opcode(instruction) {
+            Some(OpCode::RunNode) => {
+                return match run_node_target(node, index) {
+                    Some(target) => Destination::Node(index, target),
+                    None => Destination::Dynamic,
+                }
+            }
+            Some(OpCode::JumpTo) => {
+                match string_operand(instruction, 0).and_then(|label| node.labels.get(label)) {
+                    Some(label_index) => index = *label_index as usize,
+                    None => return Destination::None,
+                }
+            }
+            Some(OpCode::Stop) | Some(OpCode::ShowOptions) => return Destination::None,
+            _ => index += 1,
+        }
+    }
+
+    Destination::None
+}
+
+impl NodeGraph {
+    /// Builds the graph by walking the instructions of every node in the program
+    pub fn new(program: &Program) -> Self {
+        let mut graph = Self {
+            nodes: program.nodes.keys().cloned().collect(),
+            edges: Vec::new(),
+            dynamic_jumps: HashSet::new(),
+        };
+
+        let mut node_names = program.nodes.keys().collect::<Vec<_>>();
+        node_names.sort();
+
+        for name in node_names {
+            let node = &program.nodes[name];
+            let mut option_jumps = HashSet::new();
+
+            // first find jumps that follow an option, so the edges can be labelled
+            for instruction in node.instructions.iter() {
+                if opcode(instruction) != Some(OpCode::AddOption) {
+                    continue;
+                }
+
+                let label_index = string_operand(instruction, 1)
+                    .and_then(|label| node.labels.get(label))
+                    .map(|index| *index as usize);
+
+                let Some(label_index) = label_index else {
+                    continue;
+                };
+
+                match find_destination(node, label_index) {
+                    Destination::Node(index, target) => {
+                        option_jumps.insert(index);
+                        graph.edges.push(NodeEdge {
+                            from: name.clone(),
+                            to: target,
+                            option_line_id: string_operand(instruction, 0).map(str::to_owned),
+                        });
+                    }
+                    Destination::Dynamic => {
+                        graph.dynamic_jumps.insert(name.clone());
+                    }
+                    Destination::None => {}
+                }
+            }
+
+            // then add every other jump
+            for (index, instruction) in node.instructions.iter().enumerate() {
+                if opcode(instruction) != Some(OpCode::RunNode) || option_jumps.contains(&index) {
+                    continue;
+                }
+
+                match run_node_target(node, index) {
+                    Some(target) => graph.edges.push(NodeEdge {
+                        from: name.clone(),
+                        to: target,
+                        option_line_id: None,
+                    }),
+                    None => {
+                        graph.dynamic_jumps.insert(name.clone());
+                    }
+                }
+            }
+        }
+
+        graph
+    }
+
+    /// Finds all nodes that can be statically reached from the given start node
+    pub fn reachable_from(&self, start: &str) -> HashSet<String> {
+        let mut reachable = HashSet::new();
+        let mut queue = VecDeque::new();
+
+        if self.nodes.contains(start) {
+            reachable.insert(start.to_owned());
+            queue.push_back(start);
+        }
+
+        while let Some(current) = queue.pop_front() {
+            for edge in self.edges.iter().filter(|edge| edge.from == current) {
+                if self.nodes.contains(&edge.to) && reachable.insert(edge.to.clone()) {
+                    queue.push_back(&edge.to);
+                }
+            }
+        }
+
+        reachable
+    }
+
+    /// Builds a report of unreachable nodes, nodes with no exit and jumps to missing nodes
+    pub fn content_report(&self, start_node: &str) -> ContentReport {
+        let reachable = self.reachable_from(start_node);
+
+        let mut report = ContentReport {
+            unreachable_nodes: self
+                .nodes
+                .iter()
+                .filter(|node| !reachable.contains(*node))
+                .cloned()
+                .collect(),
+            nodes_with_no_exit: self
+                .nodes
+                .iter()
+                .filter(|node| {
+                    !self.dynamic_jumps.contains(*node)
+                        && !self.edges.iter().any(|edge| &edge.from == *node)
+                })
+                .cloned()
+                .collect(),
+            referenced_missing_nodes: self
+                .edges
+                .iter()
+                .filter(|edge| !self.nodes.contains(&edge.to))
+                .map(|edge| (edge.from.clone(), edge.to.clone()))
+                .collect(),
+        };
+
+        report.unreachable_nodes.sort();
+        report.nodes_with_no_exit.sort();
+        report.referenced_missing_nodes.sort();
+        report.referenced_missing_nodes.dedup();
+        report
+    }
+
+    /// Finds the indices of edges that jump back to a node that is already being visited
+    /// when walking the graph depth first from the start node, i.e. edges that form cycles
+    fn back_edges(&self, start: &str) -> HashSet<usize> {
+        let mut back_edges = HashSet::new();
+        let mut finished = HashSet::new();
+        let mut on_stack = HashSet::new();
+        let mut stack = vec![(start, 0)];
+        on_stack.insert(start);
+
+        let outgoing = |node: &str| {
+            self.edges
+                .iter()
+                .enumerate()
+                .filter(|(_, edge)| edge.from == node)
+                .map(|(index, _)| index)
+                .collect::<Vec<_>>()
+        };
+
+        while let Some((node, next_edge)) = stack.pop() {
+            let edges = outgoing(node);
+
+            match edges.get(next_edge) {
+                Some(edge_index) => {
+                    stack.push((node, next_edge + 1));
+                    let target = self.edges[*edge_index].to.as_str();
+
+                    if on_stack.contains(target) {
+                        back_edges.insert(*edge_index);
+                    } else if !finished.contains(target) && self.nodes.contains(target) {
+                        on_stack.insert(target);
+                        stack.push((target, 0));
+                    }
+                }
+                None => {
+                    on_stack.remove(node);
+                    finished.insert(node);
+                }
+            }
+        }
+
+        back_edges
+    }
+
+    /// Writes the graph in Graphviz DOT format. Nodes that can't be reached from the
+    /// `Start` node are grey, jumps to nodes that don't exist are red, jumps that form
+    /// cycles are dashed and nodes with jumps computed at runtime are annotated.
+    pub fn to_dot(&self, string_table: Option<&BevyYarnStringTable>) -> String {
+        let reachable = self.reachable_from("Start");
+        let back_edges = self.back_edges("Start");
+
+        let mut dot = String::from("digraph yarn {\n    node [shape=box];\n");
+
+        let mut nodes = self.nodes.iter().collect::<Vec<_>>();
+        nodes.sort();
+
+        for node in nodes {
+            let mut attributes = vec![format!(
+                "label=\"{}{}\"",
+                escape(node),
+                if self.dynamic_jumps.contains(node) {
+                    "\\n(has runtime jumps)"
+                } else {
+                    ""
+                }
+            )];
+
+            if !reachable.contains(node) {
+                attributes.push("color=grey fontcolor=grey".into());
+            }
+
+            let _ = writeln!(dot, "    \"{}\" [{}];", escape(node), attributes.join(" "));
+        }
+
+        let mut missing = self
+            .edges
+            .iter()
+            .map(|edge| &edge.to)
+            .filter(|to| !self.nodes.contains(*to))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        missing.sort();
+
+        for node in missing {
+            let _ = writeln!(
+                dot,
+                "    \"{}\" [label=\"{}\\n(missing)\" color=red fontcolor=red];",
+                escape(node),
+                escape(node)
+            );
+        }
+
+        let labels: HashMap<&str, String> = self
+            .edges
+            .iter()
+            .filter_map(|edge| edge.option_line_id.as_deref())
+            .map(|line_id| {
+                let text = string_table
+                    .and_then(|table| table.line_info(line_id))
+                    .map(|info| info.text.as_str())
+                    .unwrap_or(line_id);
+                (line_id, truncate(text))
+            })
+            .collect();
+
+        for (index, edge) in self.edges.iter().enumerate() {
+            let mut attributes = Vec::new();
+
+            if let Some(label) = edge.option_line_id.as_deref().and_then(|id| labels.get(id)) {
+                attributes.push(format!("label=\"{}\"", escape(label)));
+            }
+
+            if back_edges.contains(&index) {
+                attributes.push("style=dashed".into());
+            }
+
+            if !self.nodes.contains(&edge.to) {
+                attributes.push("color=red".into());
+            }
+
+            let _ = writeln!(
+                dot,
+                "    \"{}\" -> \"{}\" [{}];",
+                escape(&edge.from),
+                escape(&edge.to),
+                attributes.join(" ")
+            );
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Finds the names of all commands run by the program. Commands whose name is built from an
+/// expression (and so can't be determined without running the program) are skipped.
+pub(crate) fn command_names(program: &Program) -> BTreeSet<String> {
+    program
+        .nodes
+        .values()
+        .flat_map(|node| node.instructions.iter())
+        .filter(|instruction| opcode(instruction) == Some(OpCode::RunCommand))
+        .filter_map(|instruction| string_operand(instruction, 0))
+        .filter_map(|command_text| command_text.split_whitespace().next())
+        .filter(|name| !name.contains('{'))
+        .map(str::to_owned)
+        .collect()
+}
+
+/// The prefixes of the operator functions that Yarn Spinner compiles expressions to, such
+/// as `Number.Add`, which are always provided by the virtual machine
+const OPERATOR_FUNCTION_PREFIXES: &[&str] = &["Number.", "String.", "Bool.", "Enum."];
+
+/// Finds the names of all functions called by the program, apart from the operator
+/// functions that expressions are compiled to
+pub(crate) fn function_names(program: &Program) -> BTreeSet<String> {
+    program
+        .nodes
+        .values()
+        .flat_map(|node| node.instructions.iter())
+        .filter(|instruction| opcode(instruction) == Some(OpCode::CallFunc))
+        .filter_map(|instruction| string_operand(instruction, 0))
+        .filter(|name| {
+            !OPERATOR_FUNCTION_PREFIXES
+                .iter()
+                .any(|prefix| name.starts_with(prefix))
+        })
+        .map(str::to_owned)
+        .collect()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn truncate(text: &str) -> String {
+    if text.chars().count() > MAX_EDGE_LABEL_LEN {
+        format!(
+            "{}...",
+            text.chars().take(MAX_EDGE_LABEL_LEN).collect::<String>()
+        )
+    } else {
+        text.to_owned()
+    }
+}
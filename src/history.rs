@@ -0,0 +1,331 @@
+//! A record of the dialogue that has been shown to the player, which can be
+//! exported as a plain text transcript for QA and narrative review, or as SRT or WebVTT
+//! subtitles for recorded playthroughs.
+
+use std::{collections::VecDeque, fmt::Write, time::Duration};
+
+use bevy::prelude::{Entity, Resource};
+
+use crate::{
+    data::{BevyYarnChoice, BevyYarnLine},
+    events::BevyYarnEvent,
+};
+
+/// A single entry in the [DialogueHistory]
+#[derive(Debug, Clone)]
+pub enum DialogueHistoryEntry {
+    /// A line was said
+    Line {
+        /// The name of the engine that said the line
+        engine_name: String,
+        /// The elapsed app time in seconds when the line was said
+        time: f64,
+        /// The line that was said
+        line: BevyYarnLine,
+    },
+    /// Some choices were offered
+    Choices {
+        /// The name of the engine that offered the choices
+        engine_name: String,
+        /// The elapsed app time in seconds when the choices were offered
+        time: f64,
+        /// The entity of the engine that offered the choices, see
+        /// [BevyYarnEvent::Choices]
+        engine_entity: Entity,
+        /// The choices that were offered
+        choices: Vec<BevyYarnChoice>,
+        /// The index of the choice that was picked, if one has been picked yet
+        selected: Option<usize>,
+    },
+    /// The dialogue moved to a new node
+    NodeChange {
+        /// The name of the engine that changed node
+        engine_name: String,
+        /// The elapsed app time in seconds when the node changed
+        time: f64,
+        /// The name of the node that the dialogue moved to
+        node: String,
+    },
+}
+
+impl DialogueHistoryEntry {
+    /// The name of the engine that raised this entry
+    pub fn engine_name(&self) -> &str {
+        match self {
+            Self::Line { engine_name, .. }
+            | Self::Choices { engine_name, .. }
+            | Self::NodeChange { engine_name, .. } => engine_name,
+        }
+    }
+
+    /// The event that was raised when this entry was recorded, if any
+    pub fn to_event(&self) -> Option<BevyYarnEvent> {
+        match self {
+            Self::Line { line, .. } => Some(BevyYarnEvent::Say(line.clone())),
+            Self::Choices {
+                engine_entity,
+                choices,
+                ..
+            } => Some(BevyYarnEvent::Choices {
+                engine_entity: *engine_entity,
+                choices: choices.clone(),
+                is_replay: false,
+            }),
+            Self::NodeChange { .. } => None,
+        }
+    }
+
+    /// The elapsed app time in seconds when this entry was recorded
+    pub fn time(&self) -> f64 {
+        match self {
+            Self::Line { time, .. }
+            | Self::Choices { time, .. }
+            | Self::NodeChange { time, .. } => *time,
+        }
+    }
+}
+
+/// The file format written by [DialogueHistory::to_subtitles]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SubtitleFormat {
+    /// SubRip `.srt` subtitles
+    Srt,
+    /// WebVTT `.vtt` subtitles, with `<` and `&` in the lines escaped
+    WebVtt,
+}
+
+/// Where the start and end times of the subtitles written by
+/// [DialogueHistory::to_subtitles] come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SubtitleTiming {
+    /// The elapsed app time each line was said at, measured from the first entry in the
+    /// history, for subtitling a recording of the playthrough. Each line ends when the next
+    /// line or set of choices is shown, and the last line ends after its
+    /// [BevyYarnLine::estimated_duration].
+    Recorded,
+    /// The [BevyYarnLine::estimated_duration] of each line, with each line starting as the
+    /// previous one ends, for subtitling dialogue that plays back at reading speed
+    Estimated,
+}
+
+/// The number of entries a [DialogueHistory] keeps by default
+pub const DEFAULT_HISTORY_CAPACITY: usize = 1000;
+
+/// A resource that records every line, set of choices and node change raised by
+/// the yarn engines, in the order they occurred. Lines and choices that are skipped
+/// by the [crate::prelude::YarnEventMask] are not recorded.
+///
+/// Only the most recent [DialogueHistory::max_entries] entries are kept, so long sessions
+/// don't keep growing the history. Insert a history with a larger capacity to keep more,
+/// for instance to export the transcript of a whole session:
+///
+/// ```ignore
+/// app.insert_resource(DialogueHistory::with_max_entries(usize::MAX));
+/// ```
+#[derive(Debug, Resource)]
+pub struct DialogueHistory {
+    /// The recorded entries, oldest first
+    pub entries: VecDeque<DialogueHistoryEntry>,
+    /// The maximum number of entries kept. Once the history is full, the oldest entry is
+    /// removed as each new entry is recorded. Defaults to [DEFAULT_HISTORY_CAPACITY].
+    pub max_entries: usize,
+}
+
+impl Default for DialogueHistory {
+    fn default() -> Self {
+        Self::with_max_entries(DEFAULT_HISTORY_CAPACITY)
+    }
+}
+
+impl DialogueHistory {
+    /// Creates an empty history that keeps at most the given number of entries
+    pub fn with_max_entries(max_entries: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            max_entries,
+        }
+    }
+
+    /// Removes all entries from the history
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Records an entry, removing the oldest entries if the history is full
+    pub(crate) fn push(&mut self, entry: DialogueHistoryEntry) {
+        if self.max_entries == 0 {
+            return;
+        }
+
+        while self.entries.len() >= self.max_entries {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Marks the most recent set of choices offered by the given engine as having the
+    /// choice with the given option index selected.
+    pub(crate) fn record_selection(&mut self, engine_name: &str, option_index: usize) {
+        let last_choices = self.entries.iter_mut().rev().find(|entry| {
+            matches!(entry, DialogueHistoryEntry::Choices { .. })
+                && entry.engine_name() == engine_name
+        });
+
+        if let Some(DialogueHistoryEntry::Choices {
+            choices, selected, ..
+        }) = last_choices
+        {
+            *selected = choices
+                .iter()
+                .position(|choice| choice.option_index == option_index);
+        }
+    }
+
+    /// Builds a plain text transcript of the history. Lines are written as
+    /// `Character: line`, choices are written as `> option` with the picked option
+    /// marked as `> [picked] option`. Engine and node changes are written as `#` comments.
+    /// If `timestamps` is true each line is prefixed with the elapsed app time.
+    pub fn to_transcript(&self, timestamps: bool) -> String {
+        let mut transcript = String::new();
+        let mut current_engine: Option<&str> = None;
+
+        for entry in self.entries.iter() {
+            if current_engine != Some(entry.engine_name()) {
+                current_engine = Some(entry.engine_name());
+                let _ = writeln!(transcript, "# engine: {}", entry.engine_name());
+            }
+
+            let prefix = if timestamps {
+                format!("[{:>8.2}] ", entry.time())
+            } else {
+                String::new()
+            };
+
+            match entry {
+                DialogueHistoryEntry::Line { line, .. } => match line.character {
+                    Some(ref character) => {
+                        let _ =
+                            writeln!(transcript, "{prefix}{character}: {}", line.formatted_text);
+                    }
+                    None => {
+                        let _ = writeln!(transcript, "{prefix}{}", line.formatted_text);
+                    }
+                },
+                DialogueHistoryEntry::Choices {
+                    choices, selected, ..
+                } => {
+                    for (index, choice) in choices.iter().enumerate() {
+                        let marker = if *selected == Some(index) {
+                            "[picked] "
+                        } else {
+                            ""
+                        };
+                        let _ = writeln!(
+                            transcript,
+                            "{prefix}> {marker}{}",
+                            choice.formatted_line.formatted_text
+                        );
+                    }
+                }
+                DialogueHistoryEntry::NodeChange { node, .. } => {
+                    let _ = writeln!(transcript, "{prefix}# node: {node}");
+                }
+            }
+        }
+
+        transcript
+    }
+
+    /// Builds subtitles for the lines in the history, numbered in order, with each line
+    /// written as `Character: line` with its markup removed. Choices and node changes aren't
+    /// written, but shown choices end the previous line when using
+    /// [SubtitleTiming::Recorded].
+    pub fn to_subtitles(&self, format: SubtitleFormat, timing: SubtitleTiming) -> String {
+        let mut subtitles = String::new();
+        if format == SubtitleFormat::WebVtt {
+            subtitles.push_str("WEBVTT\n\n");
+        }
+
+        let first_time = self.entries.front().map_or(0.0, DialogueHistoryEntry::time);
+        let mut estimated_start = Duration::ZERO;
+        let mut cue = 0;
+
+        for (index, entry) in self.entries.iter().enumerate() {
+            let DialogueHistoryEntry::Line { line, time, .. } = entry else {
+                continue;
+            };
+
+            let (start, end) = match timing {
+                SubtitleTiming::Recorded => {
+                    let start = Duration::from_secs_f64((time - first_time).max(0.0));
+                    let next_shown = self
+                        .entries
+                        .range(index + 1..)
+                        .find(|entry| !matches!(entry, DialogueHistoryEntry::NodeChange { .. }));
+                    let end = match next_shown {
+                        Some(next) => Duration::from_secs_f64((next.time() - first_time).max(0.0)),
+                        None => start + line.estimated_duration,
+                    };
+                    (start, end.max(start))
+                }
+                SubtitleTiming::Estimated => {
+                    let start = estimated_start;
+                    estimated_start += line.estimated_duration;
+                    (start, estimated_start)
+                }
+            };
+
+            let mut text = line
+                .plain_text()
+                .lines()
+                .map(str::trim)
+                .filter(|text| !text.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n");
+            if let Some(ref character) = line.character {
+                text = format!("{character}: {text}");
+            }
+            if format == SubtitleFormat::WebVtt {
+                text = text.replace('&', "&amp;").replace('<', "&lt;");
+            }
+
+            cue += 1;
+            let _ = writeln!(
+                subtitles,
+                "{cue}\n{} --> {}\n{text}\n",
+                subtitle_timestamp(start, format),
+                subtitle_timestamp(end, format)
+            );
+        }
+
+        subtitles
+    }
+
+    /// Writes the transcript built by [DialogueHistory::to_transcript] to the given path
+    #[cfg(all(feature = "transcript", not(target_arch = "wasm32")))]
+    pub fn write_transcript<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+        timestamps: bool,
+    ) -> std::io::Result<()> {
+        std::fs::write(path, self.to_transcript(timestamps))
+    }
+}
+
+/// Formats a subtitle timestamp as `hours:minutes:seconds,milliseconds`, with a `.` before
+/// the milliseconds for WebVTT
+fn subtitle_timestamp(time: Duration, format: SubtitleFormat) -> String {
+    let millis = time.as_millis();
+    let separator = match format {
+        SubtitleFormat::Srt => ',',
+        SubtitleFormat::WebVtt => '.',
+    };
+
+    format!(
+        "{:02}:{:02}:{:02}{separator}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
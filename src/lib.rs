@@ -1,48 +1,231 @@
 #![deny(missing_docs)]
 //! A bevy helper for using the yarn dialogue format.
 
+#[cfg(feature = "analytics")]
+mod analytics;
 pub mod assets;
+mod bark;
+mod characters;
+mod checkpoint;
 pub mod commands;
+mod content_filter;
+mod coverage;
 mod data;
+pub mod debug;
+mod diagnostics;
+mod error;
 mod events;
+mod graph;
+pub mod history;
+mod ids;
+mod locale;
+mod middleware;
+pub mod pipeline;
+mod playback;
+mod pool;
+mod priority;
+mod recording;
+mod remote;
+mod states;
+pub mod styles;
+pub mod testing;
+mod translate;
+#[cfg(feature = "default-ui")]
+pub mod ui;
 
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Arc,
+};
 
 use assets::{
-    BevyYarnMetadataTable, BevyYarnMetadataTableAssetLoader, BevyYarnProgram,
+    decode_node_headers, decode_program, BevyYarnManifestAssetLoader, BevyYarnMetadataTable,
+    BevyYarnMetadataTableAssetLoader, BevyYarnNamedSubstitutions, BevyYarnProgram,
     BevyYarnProjectAssetLoader, BevyYarnStringTable, BevyYarnStringTableAssetLoader,
+    MissingLinePolicy, NodeHeaders, StringTablePatch, StringTablePatchAssetLoader,
+    YarnMissingStringPolicy,
+};
+use bark::{BevyYarnBarkEvent, YarnBark};
+use bevy::asset::LoadState;
+use bevy::ecs::{
+    schedule::{BoxedScheduleLabel, ScheduleLabel},
+    system::SystemParam,
 };
 use bevy::prelude::*;
+use bevy::tasks::{futures_lite::future, AsyncComputeTaskPool, Task};
 use chapter::*;
-use commands::{BevyYarnCommand, CommandHandlers};
-use data::YarnData;
+use characters::{
+    BevyYarnMissingSpeakerEvent, BevyYarnSpeakerChangedEvent, YarnCharacter, YarnCharacterRegistry,
+};
+use checkpoint::DialogueCheckpoint;
+use commands::{
+    BevyYarnCommand, BuiltinCommands, CommandHandler, CommandHandlers, DeferredCommandHandlerFn,
+    EntityCommandHandlerFn, YarnCommandLint, YarnCommandProvider,
+};
+use content_filter::ContentFilter;
+use coverage::DialogueCoverage;
+use data::{
+    DialogueCompleteFn, YarnActorConfig, YarnData, YarnHeadlessData, YarnLineTagKeys,
+    YarnTimingConfig,
+};
+use debug::{
+    describe_suspend_reason, DialogueDebugInfo, DialogueTrace, YarnMissingLines, YarnTraceAll,
+};
+use diagnostics::{YarnDiagnostic, YarnDiagnosticKind, YarnDiagnosticSeverity, YarnDiagnostics};
+use error::BevyYarnError;
+use graph::command_names;
+use history::{DialogueHistory, DialogueHistoryEntry};
+use ids::{LineId, NodeName};
+use locale::{loaded_locale_tables, YarnLocaleChain, YarnManifestLocales};
+use middleware::{LineMiddlewareFn, YarnLineMiddleware};
+use pipeline::{YarnLineSources, YarnQueuedOutput, YarnStepOutput, YarnStepQueue};
+use playback::{program_path, YarnNodePlaybackRules};
+use pool::YarnEnginePool;
 use prelude::{
     BevyYarnChoice, BevyYarnEvent, BevyYarnLine, BevyYarnStepDialogueEvent, CommandHandlerFn,
+    YarnEventMask,
 };
-use regex::Regex;
+use priority::DialoguePriority;
+use recording::{DialogueRecording, DialogueReplayer};
+use remote::BevyYarnSelectOptionEvent;
+use states::YarnStateTransitions;
+use styles::YarnCharacterStyles;
+use translate::{suspend_reason_to_event, YarnTranslationContext};
 
-use crate::assets::get_table_pathbuf_from_yarnc_path;
-
-// TODO: allow setting locale
-/// The locale to use for the yarn engine pluralisation etc
+/// The locale of the base string table, used for pluralisation etc when a line isn't
+/// found in any of the tables in the [YarnLocaleChain]
 pub const LOCALE: &str = "en";
 
 /// Core functionality of the crate
 pub mod prelude {
     pub use crate::{
-        assets::{BevyYarnMetadataTable, BevyYarnProgram, BevyYarnStringTable},
-        commands::{BevyYarnCommand, CommandHandlerFn},
-        data::{BevyYarnChoice, BevyYarnLine, YarnData},
-        events::{BevyYarnEvent, BevyYarnStepDialogueEvent},
-        BevyYarnDialogueEngine, YarnPlugin,
+        assets::{
+            BevyYarnMetadataTable, BevyYarnNamedSubstitutions, BevyYarnProgram,
+            BevyYarnStringTable, MissingLinePolicy, StringTablePatch, YarnMissingStringPolicy,
+            YarnTag,
+        },
+        bark::{BevyYarnBarkEvent, YarnBark},
+        characters::{
+            BevyYarnMissingSpeakerEvent, BevyYarnSpeakerChangedEvent, YarnCharacter,
+            YarnCharacterRegistry,
+        },
+        checkpoint::DialogueCheckpoint,
+        commands::{
+            BevyYarnCommand, CommandHandlerFn, DeferredCommandHandlerFn, EntityCommandHandlerFn,
+            YarnCommandProvider,
+        },
+        content_filter::{ContentFilter, ContentFilterPolicy, ALT_TAG},
+        coverage::{CoverageReport, DialogueCoverage},
+        data::{
+            BevyYarnChoice, BevyYarnDialogueEngineBundle, BevyYarnLine, DialogueCompleteFn,
+            YarnActorConfig, YarnActorType, YarnData, YarnDialogue, YarnHeadlessData,
+            YarnLineTagKeys, YarnTimingConfig,
+        },
+        debug::{
+            DialogueDebugInfo, DialogueTrace, DialogueTraceEntry, YarnDebugAction,
+            YarnMissingLines, YarnTraceAll,
+        },
+        diagnostics::{
+            YarnDiagnostic, YarnDiagnosticKind, YarnDiagnosticSeverity, YarnDiagnostics,
+        },
+        error::BevyYarnError,
+        events::{BevyYarnEvent, BevyYarnStepDialogueEvent, YarnEventMask},
+        graph::ContentReport,
+        history::{
+            DialogueHistory, DialogueHistoryEntry, SubtitleFormat, SubtitleTiming,
+            DEFAULT_HISTORY_CAPACITY,
+        },
+        ids::{LineId, NodeName},
+        locale::YarnLocaleChain,
+        middleware::{LineMiddlewareFn, MiddlewareCtx, YarnLineMiddleware},
+        pipeline::{YarnLineSources, YarnQueuedOutput, YarnStepOutput, YarnStepQueue},
+        playback::{NodePlaybackRule, YarnNodePlaybackRules, COOLDOWN_TAG, ONCE_TAG},
+        pool::{ReturnToPool, YarnEnginePool},
+        priority::DialoguePriority,
+        recording::{
+            DialogueInput, DialogueRecording, DialogueRecordingEntry, DialogueReplayer,
+            ReplayDivergence,
+        },
+        remote::{BevyYarnSelectOptionEvent, SelectChoiceCommand},
+        states::TakesFocus,
+        styles::{YarnCharacterStyle, YarnCharacterStyles},
+        testing::{YarnFuzzReport, YarnFuzzer, YarnTestError, YarnTestRun, YarnTestRunner},
+        translate::{suspend_reason_to_event, YarnTranslationContext},
+        BevyYarnDialogueEngine, UnknownSuspendReasonHandler, YarnGlobalPause, YarnPlugin,
+        YarnSystemSet, YarnTextSources,
     };
+
+    #[cfg(feature = "analytics")]
+    pub use crate::analytics::DialogueTimingAnalytics;
+
+    #[cfg(feature = "debug-plugin")]
+    pub use crate::debug::YarnDebugPlugin;
+
+    #[cfg(feature = "debug-overlay")]
+    pub use crate::debug::BevyYarnDebugOverlayPlugin;
+
+    #[cfg(feature = "default-ui")]
+    pub use crate::ui::{YarnDefaultUiPlugin, YarnDefaultUiSettings};
+}
+
+/// The name of the built in command that runs a sub-conversation from another yarnc
+/// file and returns to the current dialogue when it completes, for instance
+/// `<<call dialogue/shared.yarnc>>` or `<<call dialogue/shared.yarnc SomeNode>>`.
+///
+/// Yarn files that use their own `call` command can disable this with
+/// [YarnPluginBuilder::without_builtin_command].
+pub const CALL_COMMAND: &str = "call";
+
+/// The names of all commands that are handled by the plugin itself. A handler registered
+/// with one of these names is never called while the built in command is enabled, see
+/// [YarnPluginBuilder::without_builtin_commands] to opt out.
+pub const BUILTIN_COMMANDS: &[&str] = &[CALL_COMMAND];
+
+/// The number of lines and choices each [BevyYarnDialogueEngine] keeps for
+/// [BevyYarnDialogueEngine::replay_history], oldest first
+pub const ENGINE_HISTORY_CAPACITY: usize = 100;
+
+/// A suspended dialogue on a [BevyYarnDialogueEngine] call stack
+struct DialogueFrame {
+    vm: VirtualMachine,
+    engine_name: String,
+    string_table: Handle<BevyYarnStringTable>,
+    metadata_table: Handle<BevyYarnMetadataTable>,
+    program: Handle<BevyYarnProgram>,
+    initial_values: HashMap<String, YarnValue>,
+    locale_tables: Vec<(String, Handle<BevyYarnStringTable>)>,
+    current_node: Option<String>,
+    previous_node: Option<String>,
 }
 
 /// A resource to contain the dialogue engine
-#[derive(Component, Resource)]
+///
+/// The engine keeps state alongside its virtual machine: the current and previous nodes,
+/// the visited nodes, the available choices, the call stack of sub-conversations and any
+/// step in progress on another thread. These only stay in step with the virtual machine
+/// while it is changed through the engine's methods, so the virtual machine itself is only
+/// reachable with [BevyYarnDialogueEngine::vm_mut_unchecked].
+///
+/// # Invariants
+///
+/// The engine's methods keep the following true, which the plugin systems rely on:
+///
+/// - [BevyYarnDialogueEngine::current_node_name] is the node the virtual machine is
+///   running, and every node it has run is counted in the visited nodes.
+/// - [BevyYarnDialogueEngine::num_choices] and the option indices are the options the
+///   virtual machine is waiting for one of to be selected, or zero and empty if it isn't
+///   waiting for a selection.
+/// - A selected option is only recorded once it has been set on the virtual machine.
+/// - While a sub-conversation is running, the virtual machine is the sub-conversation's and
+///   the dialogue it was called from is suspended on the call stack.
+/// - While a step is running on another thread, the virtual machine is an empty placeholder
+///   and the dialogue isn't stepped again until the step completes.
+#[derive(Component, Resource, Reflect)]
+#[reflect(Component, Resource, from_reflect = false)]
 pub struct BevyYarnDialogueEngine {
     /// The Yharnam virtual machine that runs the dialogue
-    pub vm: VirtualMachine,
+    #[reflect(ignore)]
+    pub(crate) vm: VirtualMachine,
 
     /// The name of the file this engine was loaded from
     pub engine_name: String,
@@ -53,210 +236,1780 @@ pub struct BevyYarnDialogueEngine {
     /// A flag that is set to true to indicate that the dialogue is complete
     pub is_complete: bool,
 
+    /// The last error raised by the virtual machine while stepping the dialogue, if any
+    pub last_error: Option<String>,
+
+    #[reflect(ignore)]
     string_table: Handle<BevyYarnStringTable>,
+    #[reflect(ignore)]
     metadata_table: Handle<BevyYarnMetadataTable>,
-    _program: Handle<BevyYarnProgram>,
+    #[reflect(ignore)]
+    pub(crate) program: Handle<BevyYarnProgram>,
+    #[reflect(ignore)]
+    pub(crate) initial_values: HashMap<String, YarnValue>,
+    pub(crate) selected_option: Option<usize>,
+    option_details: Vec<(LineId, NodeName)>,
+    #[reflect(ignore)]
+    locale_tables: Vec<(String, Handle<BevyYarnStringTable>)>,
+    #[reflect(ignore)]
+    on_complete: Option<DialogueCompleteFn>,
+    pub(crate) pending_call: Option<(String, String)>,
+    #[reflect(ignore)]
+    call_stack: Vec<DialogueFrame>,
+    pub(crate) step_deferred: bool,
+    /// Whether the dialogue has been stepped since it was loaded. Step events only step
+    /// dialogues that have started, see [YarnData::auto_start].
+    pub(crate) started: bool,
+    pub(crate) is_bark: bool,
+    pub(crate) priority_paused: bool,
+    current_node: Option<String>,
+    previous_node: Option<String>,
+    #[reflect(ignore)]
+    pub(crate) visited_nodes: HashMap<String, u32>,
+    #[reflect(ignore)]
+    pub(crate) async_step: Option<Task<AsyncStep>>,
+    #[reflect(ignore)]
+    async_results: VecDeque<Result<SuspendReason, String>>,
+    line_revealing: bool,
+    last_character: Option<String>,
+    #[reflect(ignore)]
+    history: VecDeque<BevyYarnEvent>,
+}
+
+/// The outcome of stepping a virtual machine on the [AsyncComputeTaskPool], see
+/// [YarnPluginBuilder::with_async_stepping]
+struct AsyncStep {
+    vm: VirtualMachine,
+    results: VecDeque<Result<SuspendReason, String>>,
+    changed_variables: Vec<(String, YarnValue)>,
+}
+
+/// A function that is called when the virtual machine suspends with a [SuspendReason] that
+/// the plugin doesn't handle, for instance one added by a newer version or fork of the
+/// virtual machine. Returns true to stop stepping the dialogue until the next step event,
+/// or false to continue. See [YarnPluginBuilder::with_unknown_suspend_reason_handler].
+pub type UnknownSuspendReasonHandler = fn(&mut BevyYarnDialogueEngine, &SuspendReason) -> bool;
+
+/// The assets and resources used to build lines and choices from the virtual machine's
+/// suspend reasons, see [YarnPlugin::resolve_dialogue_lines]
+#[derive(SystemParam)]
+pub struct YarnTextSources<'w> {
+    /// The loaded programs, for the node headers
+    pub programs: Res<'w, Assets<BevyYarnProgram>>,
+    /// The loaded string tables
+    pub string_tables: Res<'w, Assets<BevyYarnStringTable>>,
+    /// The loaded metadata tables
+    pub metadata_tables: Res<'w, Assets<BevyYarnMetadataTable>>,
+    /// The values for named substitutions in lines
+    pub named_substitutions: Res<'w, BevyYarnNamedSubstitutions>,
+    /// The text used for lines that are missing from the string table
+    pub missing_string_policy: Res<'w, YarnMissingStringPolicy>,
+    /// The reading speed used for [BevyYarnLine::estimated_duration]
+    pub timing: Res<'w, YarnTimingConfig>,
+    /// The structured tags read into the well known fields of lines
+    pub tag_keys: Res<'w, YarnLineTagKeys>,
+    /// The prefixes and tags of narration and stage direction lines
+    pub actor_config: Res<'w, YarnActorConfig>,
+    /// The middleware run on each line once it is built
+    pub line_middleware: Res<'w, YarnLineMiddleware>,
+}
+
+/// A resource that pauses every dialogue while it is set to true, for instance while a
+/// pause menu is open. While paused, dialogues aren't stepped and the default input
+/// handlers ignore input. Step events sent while paused, such as from dialogues that
+/// finish loading, are held and step the dialogues once they are resumed.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct YarnGlobalPause(pub bool);
+
+impl YarnGlobalPause {
+    /// A run condition that is true while dialogues are not paused
+    pub fn is_running(pause: Res<YarnGlobalPause>) -> bool {
+        !pause.0
+    }
+}
+
+/// Settings for how dialogues are stepped, from the [YarnPluginBuilder]
+#[derive(Resource, Default)]
+pub struct YarnStepSettings {
+    /// Whether virtual machines are stepped on the [AsyncComputeTaskPool], see
+    /// [YarnPluginBuilder::with_async_stepping]
+    async_stepping: bool,
+    unknown_suspend_reason_handler: Option<UnknownSuspendReasonHandler>,
+    missing_line_policy: MissingLinePolicy,
+    line_reveal: bool,
+}
+
+impl std::fmt::Debug for BevyYarnDialogueEngine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BevyYarnDialogueEngine")
+            .field("engine_name", &self.engine_name)
+            .field("current_node", &self.current_node)
+            .field("num_choices", &self.num_choices)
+            .field("is_complete", &self.is_complete)
+            .field("call_depth", &self.call_depth())
+            .field("last_error", &self.last_error)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Creates an empty engine with no dialogue loaded, so the engine can be reflected with
+/// [ReflectComponent] and [ReflectResource]. Engines should be created by spawning [YarnData].
+impl FromWorld for BevyYarnDialogueEngine {
+    fn from_world(_world: &mut World) -> Self {
+        Self::empty()
+    }
+}
+
+impl BevyYarnDialogueEngine {
+    /// Creates an engine with no dialogue loaded
+    pub(crate) fn empty() -> Self {
+        let mut engine = Self::new(
+            String::new(),
+            "",
+            VirtualMachine::new(Program::default()),
+            Handle::default(),
+            Handle::default(),
+            Handle::default(),
+        );
+        engine.current_node = None;
+        engine.visited_nodes.clear();
+        engine
+    }
+
+    /// Creates a new engine for a virtual machine that has already been set to its start node
+    pub(crate) fn new(
+        engine_name: String,
+        start_node: &str,
+        vm: VirtualMachine,
+        program: Handle<BevyYarnProgram>,
+        string_table: Handle<BevyYarnStringTable>,
+        metadata_table: Handle<BevyYarnMetadataTable>,
+    ) -> Self {
+        Self {
+            locale_tables: vec![(LOCALE.into(), string_table.clone())],
+            vm,
+            engine_name,
+            num_choices: 0,
+            is_complete: false,
+            last_error: None,
+            string_table,
+            metadata_table,
+            program,
+            initial_values: HashMap::new(),
+            selected_option: None,
+            option_details: Vec::new(),
+            on_complete: None,
+            pending_call: None,
+            call_stack: Vec::new(),
+            step_deferred: false,
+            started: false,
+            is_bark: false,
+            priority_paused: false,
+            current_node: Some(start_node.to_owned()),
+            previous_node: None,
+            visited_nodes: HashMap::from([(start_node.to_owned(), 1)]),
+            async_step: None,
+            async_results: VecDeque::new(),
+            line_revealing: false,
+            last_character: None,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// The name of the node the dialogue is currently running, if known. This is the start
+    /// node until the dialogue moves to another node, and is the node of the sub-conversation
+    /// while one started with [CALL_COMMAND] is running.
+    pub fn current_node_name(&self) -> Option<&str> {
+        self.current_node.as_deref()
+    }
+
+    /// The name of the node the dialogue was running before it moved to the current node,
+    /// or `None` if it hasn't left its start node yet
+    pub fn previous_node_name(&self) -> Option<&str> {
+        self.previous_node.as_deref()
+    }
+
+    /// The character that said the last line of the dialogue, or `None` if the line had no
+    /// character or no line has been said yet. See [BevyYarnEvent::CharacterChanged].
+    pub fn last_character(&self) -> Option<&str> {
+        self.last_character.as_deref()
+    }
+
+    /// Gets the value of a variable from the virtual machine's variable storage, or `None`
+    /// if it hasn't been set. Variable names include the leading `$`, for instance `$gold`.
+    pub fn get_variable(&self, name: &str) -> Option<&YarnValue> {
+        self.vm.variable_storage.get(name)
+    }
+
+    /// Lists the name and value of every variable in the virtual machine's variable
+    /// storage, sorted by name
+    pub fn list_variables(&self) -> Vec<(&str, &YarnValue)> {
+        let mut variables = self
+            .vm
+            .variable_storage
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+            .collect::<Vec<_>>();
+        variables.sort_unstable_by_key(|(name, _)| *name);
+        variables
+    }
+
+    /// Lists the names of the nodes in the running program, sorted, if the program is loaded
+    pub fn list_nodes<'a>(&self, programs: &'a Assets<BevyYarnProgram>) -> Vec<&'a str> {
+        let mut nodes = programs
+            .get(&self.program)
+            .map(|program| {
+                program
+                    .program
+                    .nodes
+                    .keys()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        nodes.sort_unstable();
+        nodes
+    }
+
+    /// Gets mutable access to the virtual machine that runs the dialogue.
+    ///
+    /// This bypasses the state the engine keeps alongside the virtual machine, and is only
+    /// meant for advanced uses that the engine's methods don't cover. Prefer methods such as
+    /// [BevyYarnDialogueEngine::set_variable] and [BevyYarnDialogueEngine::select_option].
+    ///
+    /// # Warning
+    ///
+    /// Changes to the virtual machine are not reflected in the engine, so can break the
+    /// [invariants](BevyYarnDialogueEngine#invariants) the plugin relies on. The caller
+    /// must keep the engine consistent: jumping to another node leaves
+    /// [BevyYarnDialogueEngine::current_node_name] and the visited nodes stale, selecting
+    /// options directly skips the checks in [BevyYarnDialogueEngine::select_option], and
+    /// the virtual machine is a placeholder while a step is running on another thread (see
+    /// [YarnPluginBuilder::with_async_stepping]). While a sub-conversation is running, this
+    /// is the virtual machine of the sub-conversation.
+    pub fn vm_mut_unchecked(&mut self) -> &mut VirtualMachine {
+        &mut self.vm
+    }
+
+    /// Sets the value of a variable in the virtual machine's variable storage. Variable names
+    /// include the leading `$`, for instance `$gold`.
+    pub fn set_variable<N: Into<String>>(&mut self, name: N, value: YarnValue) {
+        self.vm.variable_storage.insert(name.into(), value);
+    }
+
+    /// Overwrites the variable storage with the initial values of the variables declared
+    /// with `<<declare>>` in the running program, removing any other variables. This starts
+    /// a quiz or minigame fresh without clearing each variable by hand. Visited nodes and
+    /// the current node are unchanged.
+    pub fn reset_variables_to_declared(&mut self) {
+        self.vm.variable_storage.clear();
+
+        for (name, value) in &self.initial_values {
+            self.vm.variable_storage.insert(name.clone(), value.clone());
+        }
+    }
+
+    /// Sets variables from a JSON object mapping variable names to booleans, numbers or
+    /// strings, for instance `{ "$gold": 10, "$met_guard": true }`, returning the number of
+    /// variables that were set. Entries with any other type of value are skipped.
+    #[cfg(feature = "serde")]
+    pub fn set_variables_from_json(&mut self, json: &str) -> Result<usize, BevyYarnError> {
+        let serde_json::Value::Object(variables) = serde_json::from_str(json)? else {
+            return Err(BevyYarnError::NotAnObject);
+        };
+
+        let mut count = 0;
+        for (name, value) in variables {
+            let value = match value {
+                serde_json::Value::Bool(value) => YarnValue::Bool(value),
+                serde_json::Value::Number(value) => match value.as_f64() {
+                    Some(value) => YarnValue::Number(value as f32),
+                    None => continue,
+                },
+                serde_json::Value::String(value) => YarnValue::Str(value),
+                _ => {
+                    warn!("Skipping variable {name} with unsupported value {value}");
+                    continue;
+                }
+            };
+
+            self.set_variable(name, value);
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Exports the booleans, numbers and strings in the virtual machine's variable storage
+    /// as a JSON object mapping variable names to values, which can be imported again with
+    /// [BevyYarnDialogueEngine::set_variables_from_json]
+    #[cfg(feature = "serde")]
+    pub fn export_variables_as_json(&self) -> String {
+        serde_json::Value::Object(
+            self.vm
+                .variable_storage
+                .iter()
+                .filter_map(|(name, value)| {
+                    let value = match value {
+                        YarnValue::Bool(value) => serde_json::Value::Bool(*value),
+                        YarnValue::Number(value) => serde_json::json!(value),
+                        YarnValue::Str(value) => serde_json::Value::String(value.clone()),
+                        _ => return None,
+                    };
+
+                    Some((name.clone(), value))
+                })
+                .collect(),
+        )
+        .to_string()
+    }
+
+    /// Exports the node, variables and visit counts of the dialogue, which can be saved and
+    /// imported with [BevyYarnDialogueEngine::import_checkpoint]. While a sub-conversation
+    /// started with [CALL_COMMAND] is running, the checkpoint is of the dialogue that
+    /// called it, at the node that called it.
+    pub fn export_checkpoint(&self) -> DialogueCheckpoint {
+        let (vm, current_node) = match self.call_stack.first() {
+            Some(frame) => (&frame.vm, &frame.current_node),
+            None => (&self.vm, &self.current_node),
+        };
+
+        DialogueCheckpoint {
+            node_name: current_node.clone().unwrap_or_default(),
+            variables: vm.variable_storage.clone(),
+            visited_nodes: self.visited_nodes.clone(),
+        }
+    }
+
+    /// Restores a checkpoint from [BevyYarnDialogueEngine::export_checkpoint], replacing
+    /// the variables and visit counts and moving the dialogue to the start of the
+    /// checkpoint's node. Any sub-conversations are ended and pending choices are cleared.
+    /// The dialogue must be stepped with a [BevyYarnStepDialogueEvent] to say the node's
+    /// first line. Returns an error and leaves the engine unchanged if the node can't be
+    /// started.
+    pub fn import_checkpoint(
+        &mut self,
+        checkpoint: DialogueCheckpoint,
+    ) -> Result<(), BevyYarnError> {
+        let root_vm = self
+            .call_stack
+            .first_mut()
+            .map_or(&mut self.vm, |frame| &mut frame.vm);
+        root_vm
+            .set_node(&checkpoint.node_name)
+            .map_err(|e| BevyYarnError::InvalidNode(format!("{e:?}")))?;
+
+        while self.pop_frame() {}
+
+        self.vm.variable_storage = checkpoint.variables;
+        self.visited_nodes = checkpoint.visited_nodes;
+        self.clear_pending();
+        self.is_complete = false;
+        self.last_error = None;
+        self.last_character = None;
+        self.history.clear();
+        self.pending_call = None;
+        self.step_deferred = false;
+        self.current_node = Some(checkpoint.node_name);
+        self.previous_node = None;
+
+        Ok(())
+    }
+
+    /// Forgets the choices being shown, any selected choice that hasn't been applied yet and
+    /// whether the last line is still being revealed, for instance to recover UI state after
+    /// an error, without restarting the dialogue. The node, variables and visited nodes are
+    /// left unchanged. Nothing is sent, so UI showing the line or choices must be
+    /// cleared separately.
+    ///
+    /// If choices were being shown, the virtual machine still expects one to be selected,
+    /// so the dialogue can't be stepped until it is moved to another node.
+    pub fn clear_pending(&mut self) {
+        self.num_choices = 0;
+        self.selected_option = None;
+        self.option_details.clear();
+        self.line_revealing = false;
+    }
+
+    /// Whether the dialogue is paused because a dialogue with a higher [DialoguePriority] is
+    /// running
+    pub fn is_priority_paused(&self) -> bool {
+        self.priority_paused
+    }
+
+    /// Whether the last line said is still being revealed, so the next step completes the
+    /// reveal rather than advancing the dialogue. Always false unless the plugin is built
+    /// with [YarnPluginBuilder::with_line_reveal].
+    pub fn is_line_revealing(&self) -> bool {
+        self.line_revealing
+    }
+
+    /// Marks the last line said as fully revealed, so the next step advances the dialogue.
+    /// Typewriters call this when they finish revealing a line by themselves.
+    pub fn finish_line_reveal(&mut self) {
+        self.line_revealing = false;
+    }
+
+    /// Whether the dialogue has run the given node, including the start node and nodes
+    /// run by sub-conversations, or has followed a choice to the given destination, see
+    /// [BevyYarnChoice::destination_node]
+    pub fn has_visited(&self, node: &str) -> bool {
+        self.visited_nodes.contains_key(node)
+    }
+
+    /// The number of times the dialogue has run the given node, see
+    /// [BevyYarnDialogueEngine::has_visited]
+    pub fn visit_count(&self, node: &str) -> u32 {
+        self.visited_nodes.get(node).copied().unwrap_or_default()
+    }
+
+    /// Records a visit to the given node
+    pub(crate) fn record_visit(&mut self, node: &str) {
+        *self.visited_nodes.entry(node.to_owned()).or_default() += 1;
+    }
+
+    /// Records that the dialogue moved to the given node
+    pub(crate) fn set_current_node(&mut self, node: String) {
+        self.record_visit(&node);
+        self.previous_node = self.current_node.replace(node);
+    }
+
+    /// Resets a completed engine to run its dialogue again from the given node, for reuse
+    /// by the [YarnEnginePool]. Variables and visited nodes are cleared unless
+    /// `keep_variables` is set.
+    pub(crate) fn reset(&mut self, start_node: &str, keep_variables: bool) -> Result<(), String> {
+        while self.pop_frame() {}
+
+        self.vm.set_node(start_node).map_err(|e| format!("{e:?}"))?;
+
+        if !keep_variables {
+            self.vm.variable_storage.clear();
+            self.visited_nodes.clear();
+        }
+
+        self.record_visit(start_node);
+        self.clear_pending();
+        self.is_complete = false;
+        self.last_error = None;
+        self.last_character = None;
+        self.history.clear();
+        self.on_complete = None;
+        self.pending_call = None;
+        self.step_deferred = false;
+        self.current_node = Some(start_node.to_owned());
+        self.previous_node = None;
+
+        Ok(())
+    }
+
+    /// Moves the virtual machine to a task that steps it until it suspends with a line,
+    /// options, the end of the dialogue, a [CALL_COMMAND] or an error. The engine holds an
+    /// empty virtual machine until the task completes.
+    fn start_async_step(&mut self, track_variables: bool) {
+        let mut vm = std::mem::replace(&mut self.vm, VirtualMachine::new(Program::default()));
+
+        self.async_step = Some(AsyncComputeTaskPool::get().spawn(async move {
+            let previous_variables = track_variables.then(|| vm.variable_storage.clone());
+            let mut results = VecDeque::new();
+
+            loop {
+                let result = vm.continue_dialogue().map_err(|e| format!("{e:?}"));
+
+                // these are the results that the dialogue isn't continued past when stepping
+                let suspends = match &result {
+                    Ok(SuspendReason::Command(cmd_text)) => {
+                        cmd_text.split_whitespace().next() == Some(CALL_COMMAND)
+                    }
+                    Ok(
+                        SuspendReason::Nop
+                        | SuspendReason::NodeChange { .. }
+                        | SuspendReason::InvalidOption(_),
+                    ) => false,
+                    _ => true,
+                };
+
+                results.push_back(result);
+                if suspends {
+                    break;
+                }
+            }
+
+            let changed_variables = previous_variables
+                .map(|previous_variables| {
+                    vm.variable_storage
+                        .iter()
+                        .filter(|(name, value)| previous_variables.get(*name) != Some(*value))
+                        .map(|(name, value)| (name.clone(), value.clone()))
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            AsyncStep {
+                vm,
+                results,
+                changed_variables,
+            }
+        }));
+    }
+
+    /// The number of sub-conversations started with [CALL_COMMAND] that are currently running
+    pub fn call_depth(&self) -> usize {
+        self.call_stack.len()
+    }
+
+    /// Swaps the running dialogue with the dialogue in the given frame
+    fn swap_frame(&mut self, frame: &mut DialogueFrame) {
+        std::mem::swap(&mut self.vm, &mut frame.vm);
+        std::mem::swap(&mut self.engine_name, &mut frame.engine_name);
+        std::mem::swap(&mut self.string_table, &mut frame.string_table);
+        std::mem::swap(&mut self.metadata_table, &mut frame.metadata_table);
+        std::mem::swap(&mut self.program, &mut frame.program);
+        std::mem::swap(&mut self.initial_values, &mut frame.initial_values);
+        std::mem::swap(&mut self.locale_tables, &mut frame.locale_tables);
+        std::mem::swap(&mut self.current_node, &mut frame.current_node);
+        std::mem::swap(&mut self.previous_node, &mut frame.previous_node);
+    }
+
+    /// Suspends the running dialogue and starts running the given dialogue
+    fn push_frame(&mut self, mut frame: DialogueFrame) {
+        self.swap_frame(&mut frame);
+        self.call_stack.push(frame);
+    }
+
+    /// Ends the running sub-conversation and resumes the dialogue that called it.
+    /// Returns false if there is no sub-conversation running.
+    fn pop_frame(&mut self) -> bool {
+        match self.call_stack.pop() {
+            Some(mut frame) => {
+                self.swap_frame(&mut frame);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The number of choices offered in the last [BevyYarnEvent::Choices] event, or zero if the
+    /// dialogue has moved on since then
+    pub fn option_count(&self) -> usize {
+        self.num_choices
+    }
+
+    /// The line ids of the choices offered in the last [BevyYarnEvent::Choices] event, in the
+    /// same order, or empty if the dialogue has moved on since then. Line ids are unique and
+    /// stable within a program, so these can be used as keys for choice widgets.
+    pub fn current_choice_ids(&self) -> Vec<LineId> {
+        if self.num_choices == 0 {
+            return Vec::new();
+        }
+
+        self.option_details
+            .iter()
+            .map(|(line_id, _)| line_id.clone())
+            .collect()
+    }
+
+    /// Whether the dialogue is showing choices and waiting for one to be selected with
+    /// [BevyYarnDialogueEngine::select_option]
+    pub fn is_awaiting_choice(&self) -> bool {
+        self.num_choices > 0 && self.selected_option.is_none() && !self.is_complete
+    }
+
+    /// Steps this dialogue in the next [YarnSystemSet::ProcessEvents], unless it is waiting
+    /// for a choice to be selected or has completed, returning whether it will advance.
+    /// Unlike sending a [BevyYarnStepDialogueEvent], this only steps this dialogue, so a
+    /// single input handler can call this on space without checking for choices itself.
+    pub fn try_advance(&mut self) -> bool {
+        // a selected choice is only applied when the dialogue is stepped
+        if self.is_awaiting_choice() || self.is_complete {
+            return false;
+        }
+
+        self.step_deferred = true;
+        true
+    }
+
+    /// Selects the choice with the given [BevyYarnChoice::option_index], which is also its
+    /// position in the last [BevyYarnEvent::Choices] event, returning false if the index is
+    /// not one of the currently available choices. The dialogue must be stepped
+    /// with a [BevyYarnStepDialogueEvent] or [BevyYarnDialogueEngine::try_advance] for the
+    /// selection to take effect. If the selected choice leads to the end of the dialogue,
+    /// that step completes it and raises [BevyYarnEvent::EndConversation].
+    pub fn select_option(&mut self, option_index: usize) -> bool {
+        if option_index >= self.num_choices {
+            return false;
+        }
+
+        let _ = self.vm.set_selected_option(option_index);
+        self.selected_option = Some(option_index);
+        true
+    }
+
+    /// Re-sends the last [ENGINE_HISTORY_CAPACITY] lines and choices this engine has raised,
+    /// oldest first, with [BevyYarnEvent::is_replay] set. This is useful for UI that is
+    /// attached after the dialogue has started, for instance a subtitle widget spawned
+    /// mid-conversation. The history is cleared when the dialogue is restarted or a
+    /// checkpoint is imported.
+    pub fn replay_history(&self, writer: &mut EventWriter<BevyYarnEvent>) {
+        writer.send_batch(self.history.iter().cloned().map(BevyYarnEvent::into_replay));
+    }
+
+    /// Records a line or choices raised by this engine for
+    /// [BevyYarnDialogueEngine::replay_history], forgetting the oldest once
+    /// [ENGINE_HISTORY_CAPACITY] are recorded
+    pub(crate) fn record_history(&mut self, event: &BevyYarnEvent) {
+        if !matches!(event, BevyYarnEvent::Say(_) | BevyYarnEvent::Choices { .. }) {
+            return;
+        }
+
+        if self.history.len() == ENGINE_HISTORY_CAPACITY {
+            self.history.pop_front();
+        }
+
+        self.history.push_back(event.clone());
+    }
+
+    /// Gets the metadata for the given line ID from this engine's metadata table, if the
+    /// table is loaded and the line has metadata.
+    pub fn get_line_metadata<'a>(
+        &self,
+        line_id: &str,
+        metadata_tables: &'a Assets<BevyYarnMetadataTable>,
+    ) -> Option<&'a MetadataInfo> {
+        metadata_tables
+            .get(&self.metadata_table)
+            .and_then(|table| table.0.get(line_id))
+    }
+
+    /// Gets the string table entry for the given line ID from this engine's string table,
+    /// if the table is loaded and the line exists.
+    pub fn get_string_for_id<'a>(
+        &self,
+        line_id: &str,
+        string_tables: &'a Assets<BevyYarnStringTable>,
+    ) -> Option<&'a LineInfo> {
+        string_tables
+            .get(&self.string_table)
+            .and_then(|table| table.line_info(line_id))
+    }
+}
+
+/// System sets for ordering systems around the systems added by [YarnPlugin]. For instance
+/// UI that reads [BevyYarnEvent]s in the same frame they are sent can be added with
+/// `my_ui.after(YarnSystemSet::ProcessEvents)`.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum YarnSystemSet {
+    /// Loads [YarnData] into dialogue engines and starts sub-conversations, in [PreUpdate]
+    /// unless changed with [YarnPluginBuilder::with_load_schedule]
+    LoadData,
+    /// Steps the dialogue engines and sends [BevyYarnEvent]s, in [Update] unless changed
+    /// with [YarnPluginBuilder::with_process_schedule]
+    ProcessEvents,
+    /// Handles input that selects choices and steps the dialogue, in the same schedule as
+    /// [YarnSystemSet::ProcessEvents]. Runs before [YarnSystemSet::ProcessEvents] so input
+    /// produces the next line in the same frame.
+    Input,
+    /// The first step of [YarnSystemSet::ProcessEvents], which steps the virtual machines
+    /// with [YarnPlugin::step_dialogue_vm] and queues the results in the [YarnStepQueue]
+    StepDialogue,
+    /// The second step of [YarnSystemSet::ProcessEvents], which looks up the text of queued
+    /// lines and choices with [YarnPlugin::resolve_dialogue_lines]
+    ResolveLines,
+    /// The last step of [YarnSystemSet::ProcessEvents], which sends the queued events with
+    /// [YarnPlugin::dispatch_dialogue_events]
+    DispatchEvents,
 }
 
 /// A plugin that adds support for the Yarn engine
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct YarnPlugin {
     commands: Vec<(String, CommandHandlerFn)>,
+    entity_commands: Vec<(String, EntityCommandHandlerFn)>,
+    deferred_commands: Vec<(String, DeferredCommandHandlerFn)>,
+    disabled_builtins: HashSet<String>,
+    state_transitions: Option<StateTransitionsFn>,
+    schedules: YarnSchedules,
+    async_stepping: bool,
+    unknown_suspend_reason_handler: Option<UnknownSuspendReasonHandler>,
+    missing_line_policy: MissingLinePolicy,
+    line_reveal: bool,
+    line_middleware: Vec<LineMiddlewareFn>,
+    replaced_systems: HashSet<YarnSystemSet>,
+}
+
+/// Adds the resource and system for the states given to
+/// [YarnPluginBuilder::with_state_transitions] to the given schedule, as the state type
+/// isn't known by the plugin
+type StateTransitionsFn = Arc<dyn Fn(&mut App, BoxedScheduleLabel) + Send + Sync>;
+
+/// The schedules the plugin's systems are added to
+#[derive(Clone)]
+struct YarnSchedules {
+    load: BoxedScheduleLabel,
+    process: BoxedScheduleLabel,
+}
+
+impl Default for YarnSchedules {
+    fn default() -> Self {
+        Self {
+            load: Box::new(PreUpdate),
+            process: Box::new(Update),
+        }
+    }
 }
 
 impl Plugin for YarnPlugin {
     fn build(&self, app: &mut App) {
-        app.add_asset::<BevyYarnProgram>()
-            .init_asset_loader::<BevyYarnProjectAssetLoader>()
+        #[cfg(all(feature = "compile-on-load", not(target_arch = "wasm32")))]
+        app.init_asset_loader::<assets::BevyYarnSourceAssetLoader>();
+
+        let diagnostics = YarnDiagnostics::default();
+        let manifest_locales = YarnManifestLocales::default();
+
+        app.register_type::<BevyYarnDialogueEngine>()
+            .register_type::<YarnData>()
+            .register_type::<DialoguePriority>()
+            .register_type::<YarnCharacter>()
+            .register_type::<BevyYarnLine>()
+            .register_type::<BevyYarnChoice>()
+            .add_asset::<BevyYarnProgram>()
+            .add_asset_loader(BevyYarnProjectAssetLoader {
+                diagnostics: diagnostics.loader_diagnostics.clone(),
+            })
+            .add_asset_loader(BevyYarnManifestAssetLoader {
+                diagnostics: diagnostics.loader_diagnostics.clone(),
+                locales: manifest_locales.clone(),
+            })
             .add_asset::<BevyYarnStringTable>()
-            .init_asset_loader::<BevyYarnStringTableAssetLoader>()
+            .add_asset_loader(BevyYarnStringTableAssetLoader {
+                diagnostics: diagnostics.loader_diagnostics.clone(),
+            })
             .add_asset::<BevyYarnMetadataTable>()
-            .init_asset_loader::<BevyYarnMetadataTableAssetLoader>()
+            .add_asset_loader(BevyYarnMetadataTableAssetLoader {
+                diagnostics: diagnostics.loader_diagnostics.clone(),
+            })
+            .add_asset::<StringTablePatch>()
+            .init_asset_loader::<StringTablePatchAssetLoader>()
             .add_event::<BevyYarnEvent>()
             .add_event::<BevyYarnStepDialogueEvent>()
-            .insert_resource(CommandHandlers(HashMap::from_iter(self.commands.clone())))
-            .add_systems(PreUpdate, (Self::load_yarn_data,))
-            .add_systems(Update, (Self::process_yarn_events,));
+            .add_event::<BevyYarnBarkEvent>()
+            .add_event::<BevyYarnSelectOptionEvent>()
+            .add_event::<BevyYarnMissingSpeakerEvent>()
+            .add_event::<BevyYarnSpeakerChangedEvent>()
+            .init_resource::<YarnEventMask>()
+            .init_resource::<YarnCharacterStyles>()
+            .init_resource::<YarnLocaleChain>()
+            .insert_resource(manifest_locales)
+            .init_resource::<BevyYarnNamedSubstitutions>()
+            .init_resource::<YarnMissingStringPolicy>()
+            .init_resource::<DialogueHistory>()
+            .init_resource::<YarnEnginePool>()
+            .init_resource::<YarnGlobalPause>()
+            .init_resource::<YarnMissingLines>()
+            .insert_resource(diagnostics)
+            .init_resource::<YarnTimingConfig>()
+            .init_resource::<YarnLineTagKeys>()
+            .init_resource::<YarnActorConfig>()
+            .init_resource::<YarnNodePlaybackRules>()
+            .init_resource::<YarnStepQueue>()
+            .init_resource::<ContentFilter>()
+            .init_resource::<YarnCharacterRegistry>()
+            .insert_resource(CommandHandlers(HashMap::from_iter(
+                self.commands
+                    .iter()
+                    .map(|(name, handler)| (name.clone(), CommandHandler::Args(*handler)))
+                    .chain(
+                        self.entity_commands.iter().map(|(name, handler)| {
+                            (name.clone(), CommandHandler::Entity(*handler))
+                        }),
+                    )
+                    .chain(
+                        self.deferred_commands.iter().map(|(name, handler)| {
+                            (name.clone(), CommandHandler::Deferred(*handler))
+                        }),
+                    ),
+            )))
+            .insert_resource(YarnLineMiddleware(self.line_middleware.clone()))
+            .insert_resource(BuiltinCommands(
+                BUILTIN_COMMANDS
+                    .iter()
+                    .filter(|name| !self.disabled_builtins.contains(**name))
+                    .map(|name| name.to_string())
+                    .collect(),
+            ))
+            .configure_set(
+                self.schedules.process.dyn_clone(),
+                YarnSystemSet::Input.before(YarnSystemSet::ProcessEvents),
+            )
+            .configure_sets(
+                self.schedules.process.dyn_clone(),
+                (
+                    YarnSystemSet::StepDialogue,
+                    YarnSystemSet::ResolveLines,
+                    YarnSystemSet::DispatchEvents,
+                )
+                    .chain()
+                    .in_set(YarnSystemSet::ProcessEvents),
+            )
+            .add_systems(
+                self.schedules.load.dyn_clone(),
+                (
+                    YarnNodePlaybackRules::collect_tag_rules,
+                    Self::load_yarn_data,
+                )
+                    .chain()
+                    .in_set(YarnSystemSet::LoadData),
+            )
+            .add_systems(
+                self.schedules.load.dyn_clone(),
+                YarnManifestLocales::sync.before(YarnSystemSet::LoadData),
+            )
+            .add_systems(
+                self.schedules.load.dyn_clone(),
+                (
+                    Self::apply_string_table_patches,
+                    Self::load_headless_data,
+                    Self::start_sub_conversations,
+                    YarnBark::start_barks,
+                )
+                    .in_set(YarnSystemSet::LoadData),
+            )
+            .add_systems(
+                self.schedules.process.dyn_clone(),
+                (
+                    DialoguePriority::arbitrate.before(YarnSystemSet::Input),
+                    BevyYarnSelectOptionEvent::apply_selections.in_set(YarnSystemSet::Input),
+                    DialogueReplayer::replay
+                        .run_if(resource_exists::<DialogueReplayer>())
+                        .in_set(YarnSystemSet::Input),
+                    DialogueRecording::record
+                        .run_if(resource_exists::<DialogueRecording>())
+                        .after(YarnSystemSet::Input)
+                        .before(YarnSystemSet::ProcessEvents),
+                    Self::abort_removed_engines.after(YarnSystemSet::ProcessEvents),
+                    YarnEnginePool::return_completed.after(YarnSystemSet::ProcessEvents),
+                    YarnBark::advance_barks.after(YarnSystemSet::ProcessEvents),
+                    YarnNodePlaybackRules::record_playback.after(YarnSystemSet::ProcessEvents),
+                    DialogueCoverage::record
+                        .run_if(resource_exists::<DialogueCoverage>())
+                        .after(YarnSystemSet::ProcessEvents),
+                    YarnDiagnostics::collect.after(YarnSystemSet::ProcessEvents),
+                    DialogueDebugInfo::update_all.after(YarnSystemSet::ProcessEvents),
+                    DialogueTrace::attach_all
+                        .run_if(resource_exists::<YarnTraceAll>())
+                        .before(YarnSystemSet::ProcessEvents),
+                    YarnCharacterRegistry::sync.before(YarnSystemSet::ProcessEvents),
+                ),
+            );
+
+        if !self.replaced_systems.contains(&YarnSystemSet::StepDialogue) {
+            app.add_systems(
+                self.schedules.process.dyn_clone(),
+                Self::step_dialogue_vm.in_set(YarnSystemSet::StepDialogue),
+            );
+        }
+
+        if !self.replaced_systems.contains(&YarnSystemSet::ResolveLines) {
+            app.add_systems(
+                self.schedules.process.dyn_clone(),
+                Self::resolve_dialogue_lines.in_set(YarnSystemSet::ResolveLines),
+            );
+        }
+
+        if !self
+            .replaced_systems
+            .contains(&YarnSystemSet::DispatchEvents)
+        {
+            app.add_systems(
+                self.schedules.process.dyn_clone(),
+                Self::dispatch_dialogue_events.in_set(YarnSystemSet::DispatchEvents),
+            );
+        }
 
         #[cfg(feature = "input-handlers")]
-        app.add_systems(Update, (Self::handle_input,));
+        app.add_systems(
+            self.schedules.process.dyn_clone(),
+            (Self::handle_input,)
+                .in_set(YarnSystemSet::Input)
+                .run_if(YarnGlobalPause::is_running),
+        );
+
+        #[cfg(feature = "analytics")]
+        app.add_systems(
+            self.schedules.process.dyn_clone(),
+            analytics::DialogueTimingAnalytics::record
+                .run_if(resource_exists::<analytics::DialogueTimingAnalytics>())
+                .after(YarnSystemSet::ProcessEvents),
+        );
+
+        app.insert_resource(YarnStepSettings {
+            async_stepping: self.async_stepping,
+            unknown_suspend_reason_handler: self.unknown_suspend_reason_handler,
+            missing_line_policy: self.missing_line_policy,
+            line_reveal: self.line_reveal,
+        });
+
+        if self.async_stepping {
+            app.add_systems(
+                self.schedules.process.dyn_clone(),
+                Self::poll_async_steps
+                    .after(YarnSystemSet::Input)
+                    .before(YarnSystemSet::ProcessEvents),
+            );
+        }
+
+        if let Some(ref add_state_transitions) = self.state_transitions {
+            app.register_type::<TakesFocus>();
+            add_state_transitions(app, self.schedules.process.dyn_clone());
+        }
     }
 }
 
 impl YarnPlugin {
     /// A system that runs when a "yarn file" component is added and initialises the
     /// engine with the given data. Once the asset file is loaded, this system will
-    /// remove the [`YarnData`] component and initialise a virtual machine.
+    /// remove the [`YarnData`] component and initialise a virtual machine, unless
+    /// [YarnData::keep_after_load] is set. A [`YarnData`] resource is loaded the same way
+    /// into a [BevyYarnDialogueEngine] resource.
     fn load_yarn_data(
         mut commands: Commands,
         asset_server: Res<AssetServer>,
         programs: Res<Assets<BevyYarnProgram>>,
-        mut event_sender: EventWriter<BevyYarnStepDialogueEvent>,
-        yarn_datas: Query<(Entity, &YarnData)>,
+        event_mask: Res<YarnEventMask>,
+        locale_chain: Res<YarnLocaleChain>,
+        command_handlers: Res<CommandHandlers>,
+        builtin_commands: Res<BuiltinCommands>,
+        command_lint: Option<Res<YarnCommandLint>>,
+        mut diagnostics: ResMut<YarnDiagnostics>,
+        mut send_yarn_events: EventWriter<BevyYarnEvent>,
+        mut pool: ResMut<YarnEnginePool>,
+        playback_rules: Res<YarnNodePlaybackRules>,
+        resource_data: Option<ResMut<YarnData>>,
+        mut yarn_datas: Query<(Entity, &mut YarnData)>,
     ) {
-        for (entity, data) in yarn_datas.iter() {
+        let yarn_datas = yarn_datas
+            .iter_mut()
+            .map(|(entity, data)| (Some(entity), data))
+            .chain(resource_data.map(|data| (None, data.into())));
+
+        for (entity, mut data) in yarn_datas {
+            // data deserialized from a scene may not have had its path set yet
+            if data.yarnc_path.is_empty() {
+                continue;
+            }
+
+            // kept data is only loaded again once it changes
+            if data.loaded {
+                if !data.is_changed() {
+                    continue;
+                }
+                data.bypass_change_detection().loaded = false;
+            }
+
             let program_handle: Handle<BevyYarnProgram> = asset_server.load(&data.yarnc_path);
 
             if let Some(program) = programs.get(&program_handle) {
-                let mut vm = VirtualMachine::new(program.program.clone());
-                let string_table: Handle<BevyYarnStringTable> =
-                    asset_server.load(get_table_pathbuf_from_yarnc_path(&data.yarnc_path, "lines"));
-                let metadata_table: Handle<BevyYarnMetadataTable> = asset_server.load(
-                    get_table_pathbuf_from_yarnc_path(&data.yarnc_path, "metadata"),
-                );
+                if !playback_rules.can_play(&program_path(&asset_server, &program_handle), "Start")
+                {
+                    info!(
+                        "Not starting {}, its Start node can't be played again yet",
+                        data.yarnc_path
+                    );
+                    Self::finish_loading_data(&mut commands, entity, &mut data);
+                    continue;
+                }
+
+                if let Some(ref lint) = command_lint {
+                    let unhandled = command_names(&program.program)
+                        .into_iter()
+                        .filter(|name| {
+                            !builtin_commands.is_enabled(name)
+                                && !command_handlers.0.contains_key(name)
+                                && !lint.allowlist.contains(name)
+                        })
+                        .collect::<Vec<_>>();
 
-                vm.set_node("Start").expect("set Start node");
-                commands
-                    .entity(entity)
-                    .insert(BevyYarnDialogueEngine {
+                    if !unhandled.is_empty() {
+                        let message = format!(
+                            "{} uses commands with no registered handler: {}",
+                            data.yarnc_path,
+                            unhandled.join(", ")
+                        );
+                        warn!("{message}");
+                        diagnostics.push(
+                            YarnDiagnostic::new(
+                                YarnDiagnosticSeverity::Warning,
+                                YarnDiagnosticKind::UnhandledCommand,
+                                message,
+                            )
+                            .with_path(std::path::Path::new(&data.yarnc_path)),
+                        );
+                    }
+                }
+
+                // reuse an engine that was returned to the pool if there is one
+                let mut engine = pool.take(&data.yarnc_path).unwrap_or_else(|| {
+                    let mut vm = VirtualMachine::new(program.program.clone());
+                    vm.set_node("Start").expect("set Start node");
+
+                    let mut engine = BevyYarnDialogueEngine::new(
+                        data.yarnc_path.clone(),
+                        "Start",
                         vm,
-                        engine_name: data.yarnc_path.clone(),
-                        _program: program_handle,
-                        string_table,
-                        metadata_table,
-                        num_choices: 0,
-                        is_complete: false,
-                    })
-                    .remove::<YarnData>();
-
-                // trigger the first step
+                        program_handle,
+                        program.string_table.clone(),
+                        program.metadata_table.clone(),
+                    );
+                    engine.initial_values = program.initial_values();
+                    engine
+                });
+                engine.on_complete = data.on_complete;
+                engine.locale_tables =
+                    locale_chain.load_tables(&asset_server, &data.yarnc_path, &engine.string_table);
+
+                // trigger the first step of this dialogue only, so other running dialogues
+                // don't advance
+                engine.started = false;
+                if data.auto_start {
+                    engine.try_advance();
+                }
+
+                match entity {
+                    Some(entity) => {
+                        commands.entity(entity).insert(engine);
+                    }
+                    None => commands.insert_resource(engine),
+                }
+
+                Self::finish_loading_data(&mut commands, entity, &mut data);
+
                 info!("Finished loading program from {}", data.yarnc_path);
-                event_sender.send(BevyYarnStepDialogueEvent);
+                if event_mask.contains(YarnEventMask::DIALOGUE_READY) {
+                    send_yarn_events.send(BevyYarnEvent::DialogueReady(
+                        entity.unwrap_or(Entity::PLACEHOLDER),
+                    ));
+                }
             }
         }
     }
 
-    /// Takes updates from the Yarn engine and forwards them to the ECS
-    fn process_yarn_events(
+    /// Marks kept data as loaded, or removes data that isn't kept
+    fn finish_loading_data(
+        commands: &mut Commands,
+        entity: Option<Entity>,
+        data: &mut Mut<YarnData>,
+    ) {
+        if data.keep_after_load {
+            data.bypass_change_detection().loaded = true;
+        } else if let Some(entity) = entity {
+            commands.entity(entity).remove::<YarnData>();
+        } else {
+            commands.remove_resource::<YarnData>();
+        }
+    }
+
+    /// A system that creates dialogue engines from the compiled files in [YarnHeadlessData],
+    /// adding the program and tables to their asset collections directly
+    fn load_headless_data(
         mut commands: Commands,
+        mut programs: ResMut<Assets<BevyYarnProgram>>,
+        mut string_tables: ResMut<Assets<BevyYarnStringTable>>,
+        mut metadata_tables: ResMut<Assets<BevyYarnMetadataTable>>,
+        event_mask: Res<YarnEventMask>,
+        mut send_yarn_events: EventWriter<BevyYarnEvent>,
+        headless_datas: Query<(Entity, &YarnHeadlessData, Option<&YarnData>)>,
+    ) {
+        for (entity, headless, data) in headless_datas.iter() {
+            if headless.is_empty() {
+                continue;
+            }
+            commands
+                .entity(entity)
+                .remove::<(YarnHeadlessData, YarnData)>();
+
+            let decoded = (|| -> Result<_, bevy::asset::Error> {
+                let (program, format_version) =
+                    decode_program(&headless.yarnc, std::path::Path::new("headless"))?;
+                let headers = decode_node_headers(&headless.yarnc)?;
+                let string_table = BevyYarnStringTable::from_csv(&headless.lines_csv)?;
+                let metadata_table = BevyYarnMetadataTable::from_csv(&headless.metadata_csv)?;
+                Ok((
+                    program,
+                    format_version,
+                    headers,
+                    string_table,
+                    metadata_table,
+                ))
+            })();
+
+            let (program, format_version, headers, string_table, metadata_table) = match decoded {
+                Ok(decoded) => decoded,
+                Err(e) => {
+                    error!("Unable to load headless dialogue on {entity:?}: {e}");
+                    continue;
+                }
+            };
+
+            let mut vm = VirtualMachine::new(program.clone());
+            if let Err(e) = vm.set_node("Start") {
+                error!("Unable to start headless dialogue on {entity:?}: {e:?}");
+                continue;
+            }
+
+            let string_table = string_tables.add(string_table);
+            let metadata_table = metadata_tables.add(metadata_table);
+            let program = BevyYarnProgram {
+                program,
+                format_version,
+                headers,
+                string_table: string_table.clone(),
+                metadata_table: metadata_table.clone(),
+            };
+            let initial_values = program.initial_values();
+
+            let mut engine = BevyYarnDialogueEngine::new(
+                "headless".into(),
+                "Start",
+                vm,
+                programs.add(program),
+                string_table,
+                metadata_table,
+            );
+            engine.initial_values = initial_values;
+            engine.on_complete = data.and_then(|data| data.on_complete);
+            if data.map_or(true, |data| data.auto_start) {
+                engine.try_advance();
+            }
+            commands.entity(entity).insert(engine);
+
+            info!("Finished loading headless program on {entity:?}");
+            if event_mask.contains(YarnEventMask::DIALOGUE_READY) {
+                send_yarn_events.send(BevyYarnEvent::DialogueReady(entity));
+            }
+        }
+    }
+
+    /// A system that starts sub-conversations requested with the [CALL_COMMAND] once their
+    /// yarnc file and tables have loaded, suspending the calling dialogue until they complete.
+    /// If they fail to load, a [BevyYarnEvent::Error] is raised and the caller resumes.
+    fn start_sub_conversations(
+        asset_server: Res<AssetServer>,
+        programs: Res<Assets<BevyYarnProgram>>,
         string_tables: Res<Assets<BevyYarnStringTable>>,
         metadata_tables: Res<Assets<BevyYarnMetadataTable>>,
-        command_handlers: Res<CommandHandlers>,
-        mut read_step_events: EventReader<BevyYarnStepDialogueEvent>,
+        locale_chain: Res<YarnLocaleChain>,
+        event_mask: Res<YarnEventMask>,
         mut send_yarn_events: EventWriter<BevyYarnEvent>,
+        resource_engine: Option<ResMut<BevyYarnDialogueEngine>>,
         mut yarn_engines: Query<&mut BevyYarnDialogueEngine>,
     ) {
-        for _ in read_step_events.iter() {
-            debug!("Reading step event in process_yarn_events");
+        let yarn_engines = yarn_engines
+            .iter_mut()
+            .map(Mut::into_inner)
+            .chain(resource_engine.map(ResMut::into_inner));
 
-            for mut yarn_engine in yarn_engines.iter_mut() {
-                let string_table = string_tables.get(&yarn_engine.string_table).unwrap();
-                let metadata_table = metadata_tables.get(&yarn_engine.metadata_table).unwrap();
+        for yarn_engine in yarn_engines {
+            let Some((yarnc_path, start_node)) = yarn_engine.pending_call.clone() else {
+                continue;
+            };
 
-                loop {
-                    match yarn_engine.vm.continue_dialogue() {
-                        Ok(result) => {
-                            match result {
-                                SuspendReason::Nop => {}
-                                SuspendReason::Line(line) => {
-                                    yarn_engine.num_choices = 0;
+            let program_handle: Handle<BevyYarnProgram> = asset_server.load(&yarnc_path);
+            let program = programs.get(&program_handle);
+            let load_failed = asset_server.get_load_state(&program_handle) == LoadState::Failed
+                || program.map_or(false, |program| {
+                    asset_server.get_load_state(&program.string_table) == LoadState::Failed
+                        || asset_server.get_load_state(&program.metadata_table) == LoadState::Failed
+                });
 
-                                    let (character, formatted_text) =
-                                        string_table.get_final_text(&line, LOCALE);
+            // the caller carries on past the call rather than waiting forever
+            if load_failed {
+                let error = BevyYarnError::CallFailed(yarnc_path);
+                warn!("{error}, resuming {}", yarn_engine.engine_name);
+                if event_mask.contains(YarnEventMask::ERROR) {
+                    send_yarn_events.send(BevyYarnEvent::Error(error));
+                }
+                yarn_engine.pending_call = None;
+                yarn_engine.try_advance();
+                continue;
+            }
 
-                                    send_yarn_events.send(BevyYarnEvent::Say(BevyYarnLine {
-                                        line: line.clone(),
-                                        formatted_text,
-                                        character,
-                                        tags: metadata_table.get_tags_for_line(&line),
-                                    }));
-                                    break;
-                                }
-                                SuspendReason::Options(options) => {
-                                    let choices = options
-                                        .iter()
-                                        .map(|choice| {
-                                            let (character, formatted_text) =
-                                                string_table.get_final_text(&choice.line, LOCALE);
-
-                                            BevyYarnChoice {
-                                                line_id: choice.line.id.clone(),
-                                                formatted_line: BevyYarnLine {
-                                                    formatted_text,
-                                                    character,
-                                                    tags: metadata_table
-                                                        .get_tags_for_line(&choice.line),
-                                                    line: choice.line.clone(),
-                                                },
-                                                destination_node: choice.destination_node.clone(),
-                                            }
-                                        })
-                                        .collect::<Vec<_>>();
-                                    yarn_engine.num_choices = choices.len();
+            let Some(program) = program else {
+                continue;
+            };
 
-                                    send_yarn_events.send(BevyYarnEvent::Choices(choices));
-                                    break;
-                                }
-                                SuspendReason::Command(cmd_text) => {
-                                    debug!("Received command {cmd_text}");
-                                    yarn_engine.num_choices = 0;
+            let string_table = program.string_table.clone();
+            let metadata_table = program.metadata_table.clone();
 
-                                    let command_parser =
-                                        Regex::new(r#"(("[^"]+")|\S+)+"#).expect("parse regex");
-
-                                    // parse the command name and args
-                                    let (command_name, args) = command_parser
-                                        .find_iter(&cmd_text)
-                                        .map(|cap| cap.as_str().to_owned().replace('"', ""))
-                                        .enumerate()
-                                        .fold(
-                                            (String::new(), Vec::<String>::new()),
-                                            |mut acc, (index, item)| {
-                                                if index == 0 {
-                                                    (item, acc.1)
-                                                } else {
-                                                    acc.1.push(item);
-                                                    acc
-                                                }
-                                            },
-                                        );
+            if !string_tables.contains(&string_table) || !metadata_tables.contains(&metadata_table)
+            {
+                continue;
+            }
 
-                                    let mut bevy_command = BevyYarnCommand {
-                                        command_name,
-                                        args,
-                                        handled: false,
-                                    };
+            yarn_engine.pending_call = None;
 
-                                    // see if we have a handler registered
-                                    if command_handlers.0.get(&bevy_command.command_name).is_some()
-                                    {
-                                        info!(
-                                            "Calling registered command {} with args {:?}",
-                                            bevy_command.command_name, bevy_command.args
-                                        );
-                                        bevy_command.handled = true;
-                                        commands.add(bevy_command.clone());
-                                    } else {
-                                        info!(
-                                            "Found unregistered command {} with args {:?}",
-                                            bevy_command.command_name, bevy_command.args
-                                        );
-                                    }
+            let mut vm = VirtualMachine::new(program.program.clone());
+            if let Err(e) = vm.set_node(&start_node) {
+                warn!("Unable to call {yarnc_path} from node {start_node}: {e:?}");
+                yarn_engine.try_advance();
+                continue;
+            }
+
+            info!("Calling {yarnc_path} from {}", yarn_engine.engine_name);
+            yarn_engine.record_visit(&start_node);
+            yarn_engine.push_frame(DialogueFrame {
+                locale_tables: locale_chain.load_tables(&asset_server, &yarnc_path, &string_table),
+                current_node: Some(start_node),
+                previous_node: None,
+                vm,
+                engine_name: yarnc_path,
+                string_table,
+                metadata_table,
+                program: program_handle,
+                initial_values: program.initial_values(),
+            });
+            yarn_engine.try_advance();
+        }
+    }
+
+    /// Applies string table patches to their string tables whenever a patch is loaded or changes
+    fn apply_string_table_patches(
+        mut patch_events: EventReader<AssetEvent<StringTablePatch>>,
+        patches: Res<Assets<StringTablePatch>>,
+        mut string_tables: ResMut<Assets<BevyYarnStringTable>>,
+    ) {
+        for event in patch_events.iter() {
+            let (AssetEvent::Created { handle } | AssetEvent::Modified { handle }) = event else {
+                continue;
+            };
+
+            let Some(patch) = patches.get(handle) else {
+                continue;
+            };
+
+            match string_tables.get_mut(&patch.string_table) {
+                Some(string_table) => {
+                    debug!(
+                        "Applying string table patch with {} lines",
+                        patch.lines.len()
+                    );
+                    string_table.apply_patch(
+                        patch
+                            .lines
+                            .iter()
+                            .map(|line_info| (line_info.id.clone().into(), line_info.clone()))
+                            .collect(),
+                    );
+                }
+                None => warn!("Received a string table patch but its string table isn't loaded"),
+            }
+        }
+    }
+
+    /// Sends an aborted [BevyYarnEvent::EndConversation] for engines that are removed or
+    /// despawned before their dialogue completes, so listeners aren't left waiting. Anything
+    /// else associated with an engine, such as pending steps and sub-conversations, is
+    /// stored on the engine and is dropped with it.
+    fn abort_removed_engines(
+        event_mask: Res<YarnEventMask>,
+        mut running_engines: Local<HashSet<Entity>>,
+        mut removed_engines: RemovedComponents<BevyYarnDialogueEngine>,
+        mut send_yarn_events: EventWriter<BevyYarnEvent>,
+        resource_engine: Option<Res<BevyYarnDialogueEngine>>,
+        yarn_engines: Query<(Entity, &BevyYarnDialogueEngine)>,
+    ) {
+        // a running resource engine is tracked with a placeholder entity
+        if resource_engine.is_none() && running_engines.remove(&Entity::PLACEHOLDER) {
+            info!("Dialogue engine resource was removed before it completed");
+
+            if event_mask.contains(YarnEventMask::END_CONVERSATION) {
+                send_yarn_events.send(BevyYarnEvent::EndConversation {
+                    engine_entity: Entity::PLACEHOLDER,
+                    aborted: true,
+                });
+            }
+        }
+
+        for entity in removed_engines.iter() {
+            if running_engines.remove(&entity) && !yarn_engines.contains(entity) {
+                info!("Dialogue engine on {entity:?} was removed before it completed");
+
+                if event_mask.contains(YarnEventMask::END_CONVERSATION) {
+                    send_yarn_events.send(BevyYarnEvent::EndConversation {
+                        engine_entity: entity,
+                        aborted: true,
+                    });
+                }
+            }
+        }
+
+        running_engines.clear();
+        running_engines.extend(
+            yarn_engines
+                .iter()
+                .filter(|(_, yarn_engine)| !yarn_engine.is_complete && !yarn_engine.is_bark)
+                .map(|(entity, _)| entity),
+        );
+
+        if resource_engine.is_some_and(|yarn_engine| !yarn_engine.is_complete) {
+            running_engines.insert(Entity::PLACEHOLDER);
+        }
+    }
+
+    /// Steps the virtual machines of the dialogue engines and queues the results in the
+    /// [YarnStepQueue], dispatching commands and updating the engines as it goes. Lines and
+    /// choices are queued unresolved, to be looked up by [YarnPlugin::resolve_dialogue_lines].
+    /// A [BevyYarnDialogueEngine] resource is stepped after any engine components.
+    ///
+    /// Runs in [YarnSystemSet::StepDialogue], see
+    /// [YarnPluginBuilder::without_default_system] to replace it.
+    pub fn step_dialogue_vm(
+        mut commands: Commands,
+        string_tables: Res<Assets<BevyYarnStringTable>>,
+        metadata_tables: Res<Assets<BevyYarnMetadataTable>>,
+        command_handlers: Res<CommandHandlers>,
+        builtin_commands: Res<BuiltinCommands>,
+        event_mask: Res<YarnEventMask>,
+        global_pause: Res<YarnGlobalPause>,
+        time: Res<Time>,
+        mut missing_lines: ResMut<YarnMissingLines>,
+        mut read_step_events: EventReader<BevyYarnStepDialogueEvent>,
+        content_filter: Res<ContentFilter>,
+        mut queue: ResMut<YarnStepQueue>,
+        mut step_count: Local<u64>,
+        step_settings: Res<YarnStepSettings>,
+        mut resource_engine: Option<ResMut<BevyYarnDialogueEngine>>,
+        mut yarn_engines: Query<(
+            Entity,
+            &mut BevyYarnDialogueEngine,
+            Option<&mut DialogueDebugInfo>,
+            Option<&mut DialogueTrace>,
+        )>,
+    ) {
+        // engines that were stepped before their tables loaded are stepped again, even if
+        // there are no new step events
+        let new_steps = read_step_events.iter().count();
+
+        // steps sent while paused are held until the dialogues are resumed
+        if global_pause.0 {
+            if new_steps > 0 {
+                for (_, mut yarn_engine, ..) in yarn_engines.iter_mut() {
+                    yarn_engine.step_deferred |=
+                        yarn_engine.started && !yarn_engine.is_bark && !yarn_engine.priority_paused;
+                }
+                if let Some(ref mut yarn_engine) = resource_engine {
+                    yarn_engine.step_deferred |=
+                        yarn_engine.started && !yarn_engine.priority_paused;
+                }
+            }
+            return;
+        }
+
+        let has_deferred = yarn_engines
+            .iter()
+            .map(|(_, yarn_engine, ..)| yarn_engine)
+            .chain(resource_engine.as_deref())
+            .any(|yarn_engine| yarn_engine.step_deferred);
+
+        for step in 0..new_steps.max(has_deferred as usize) {
+            debug!("Reading step event in step_dialogue_vm");
+            *step_count += 1;
+
+            let yarn_engines = yarn_engines
+                .iter_mut()
+                .map(|(entity, yarn_engine, debug_info, trace)| {
+                    (
+                        entity,
+                        yarn_engine.into_inner(),
+                        debug_info.map(Mut::into_inner),
+                        trace.map(Mut::into_inner),
+                    )
+                })
+                .chain(
+                    resource_engine
+                        .as_deref_mut()
+                        .map(|yarn_engine| (Entity::PLACEHOLDER, yarn_engine, None, None)),
+                );
+
+            for (entity, yarn_engine, mut debug_info, mut trace) in yarn_engines {
+                if step >= new_steps && !yarn_engine.step_deferred {
+                    continue;
+                }
+
+                // dialogues only start when they are advanced themselves, so loading one
+                // doesn't step the others and step events don't start dialogues early
+                if !yarn_engine.started && !yarn_engine.step_deferred {
+                    continue;
+                }
+
+                if yarn_engine.pending_call.is_some()
+                    || yarn_engine.async_step.is_some()
+                    || yarn_engine.priority_paused
+                {
+                    continue;
+                }
+
+                // barks advance by themselves, so ignore step events
+                if yarn_engine.is_bark && !yarn_engine.step_deferred {
+                    continue;
+                }
+                yarn_engine.started = true;
+
+                let mut queue_output = |yarn_engine: &BevyYarnDialogueEngine, output| {
+                    queue.entries.push(YarnQueuedOutput {
+                        entity,
+                        engine_name: yarn_engine.engine_name.clone(),
+                        is_bark: yarn_engine.is_bark,
+                        time: time.elapsed_seconds_f64(),
+                        output,
+                    });
+                };
+
+                // the first step while a line is revealing completes the reveal instead
+                if yarn_engine.line_revealing {
+                    yarn_engine.line_revealing = false;
+                    yarn_engine.step_deferred = false;
+
+                    if event_mask.contains(YarnEventMask::LINE_REVEAL_COMPLETE) {
+                        queue_output(
+                            yarn_engine,
+                            YarnStepOutput::Event(BevyYarnEvent::LineRevealComplete),
+                        );
+                    }
+                    continue;
+                }
+
+                #[cfg(feature = "trace")]
+                let _engine_span =
+                    trace_span!("yarn_engine", engine = %yarn_engine.engine_name).entered();
+
+                if let Some(index) = yarn_engine.selected_option.take() {
+                    queue_output(yarn_engine, YarnStepOutput::Selection(index));
+
+                    if let Some((line_id, destination_node)) =
+                        yarn_engine.option_details.get(index).cloned()
+                    {
+                        // options usually lead to a label in their node rather than to
+                        // another node, so the destination is recorded for
+                        // BevyYarnChoice::was_previously_selected
+                        yarn_engine.record_visit(destination_node.as_str());
+
+                        if event_mask.contains(YarnEventMask::CHOICE_SELECTED) {
+                            let event = BevyYarnEvent::ChoiceSelected {
+                                line_id,
+                                destination_node,
+                            };
+                            queue_output(yarn_engine, YarnStepOutput::Event(event));
+                        }
+                    }
+                }
+
+                loop {
+                    // the tables are fetched each step as they change when sub-conversations
+                    // start or finish
+                    let (Some(string_table), Some(metadata_table)) = (
+                        string_tables.get(&yarn_engine.string_table),
+                        metadata_tables.get(&yarn_engine.metadata_table),
+                    ) else {
+                        debug!(
+                            "Tables for {} are not loaded yet, retrying next frame",
+                            yarn_engine.engine_name
+                        );
+                        yarn_engine.step_deferred = true;
+                        break;
+                    };
+                    yarn_engine.step_deferred = false;
+                    let locale_tables = yarn_engine.locale_tables.clone();
+                    let locale_tables = loaded_locale_tables(&locale_tables, &string_tables);
+                    let has_line = |line_id: &str| {
+                        locale_tables
+                            .iter()
+                            .any(|(_, table)| table.line_info(line_id).is_some())
+                            || string_table.line_info(line_id).is_some()
+                    };
+
+                    let step_result =
+                        if let Some(step_result) = yarn_engine.async_results.pop_front() {
+                            step_result
+                        } else if step_settings.async_stepping {
+                            yarn_engine
+                                .start_async_step(event_mask.contains(YarnEventMask::VARIABLE_SET));
+                            break;
+                        } else {
+                            let previous_variables = event_mask
+                                .contains(YarnEventMask::VARIABLE_SET)
+                                .then(|| yarn_engine.vm.variable_storage.clone());
+
+                            let step_result = {
+                                #[cfg(feature = "trace")]
+                                let _step_span = trace_span!("continue_dialogue").entered();
+
+                                yarn_engine
+                                    .vm
+                                    .continue_dialogue()
+                                    .map_err(|e| format!("{e:?}"))
+                            };
+
+                            if let Some(previous_variables) = previous_variables {
+                                for (name, value) in yarn_engine.vm.variable_storage.iter() {
+                                    if previous_variables.get(name) != Some(value) {
+                                        let event = BevyYarnEvent::VariableSet {
+                                            name: name.clone(),
+                                            value: value.clone(),
+                                        };
+                                        queue_output(yarn_engine, YarnStepOutput::Event(event));
+                                    }
+                                }
+                            }
+
+                            step_result
+                        };
+
+                    match step_result {
+                        Ok(result) => {
+                            if let Some(ref mut debug_info) = debug_info {
+                                debug_info.record_suspend_reason(&result);
+                            }
+
+                            if let Some(ref mut trace) = trace {
+                                trace.record_suspend_reason(
+                                    *step_count,
+                                    time.elapsed_seconds_f64(),
+                                    &yarn_engine,
+                                    &result,
+                                );
+                            }
+
+                            let unresolved =
+                                |yarn_engine: &BevyYarnDialogueEngine, reason: SuspendReason| {
+                                    YarnStepOutput::Unresolved {
+                                        reason,
+                                        sources: YarnLineSources {
+                                            program: yarn_engine.program.clone(),
+                                            string_table: yarn_engine.string_table.clone(),
+                                            metadata_table: yarn_engine.metadata_table.clone(),
+                                            locale_tables: yarn_engine.locale_tables.clone(),
+                                        },
+                                    }
+                                };
+
+                            match &result {
+                                SuspendReason::Nop => {}
+                                SuspendReason::Line(line) => {
+                                    yarn_engine.num_choices = 0;
+
+                                    let Some(line) =
+                                        content_filter.filter_line(line, metadata_table, false)
+                                    else {
+                                        debug!("Skipping filtered line {}", line.id);
+                                        continue;
+                                    };
+
+                                    if !has_line(&line.id) {
+                                        missing_lines.record(
+                                            &yarn_engine.string_table,
+                                            &line.id,
+                                            yarn_engine.current_node_name(),
+                                        );
+
+                                        match step_settings.missing_line_policy {
+                                            MissingLinePolicy::Placeholder => {}
+                                            MissingLinePolicy::SkipLine => {
+                                                debug!("Skipping missing line {}", line.id);
+                                                continue;
+                                            }
+                                            MissingLinePolicy::Error => {
+                                                let error = BevyYarnError::MissingLine(
+                                                    line.id.clone().into(),
+                                                );
+                                                yarn_engine.last_error = Some(error.to_string());
+
+                                                if event_mask.contains(YarnEventMask::ERROR) {
+                                                    queue_output(
+                                                        yarn_engine,
+                                                        YarnStepOutput::Event(
+                                                            BevyYarnEvent::Error(error),
+                                                        ),
+                                                    );
+                                                }
+                                                break;
+                                            }
+                                        }
+                                    }
+
+                                    if yarn_engine.is_bark {
+                                        let reason = SuspendReason::Line(line);
+                                        queue_output(yarn_engine, unresolved(yarn_engine, reason));
+                                    } else if event_mask.contains(YarnEventMask::SAY) {
+                                        let reason = SuspendReason::Line(line);
+                                        queue_output(yarn_engine, unresolved(yarn_engine, reason));
+                                        yarn_engine.line_revealing = step_settings.line_reveal;
+                                    }
+                                    break;
+                                }
+                                SuspendReason::Options(options) => {
+                                    if yarn_engine.is_bark {
+                                        warn!(
+                                            "Bark {} offered choices, ending it",
+                                            yarn_engine.engine_name
+                                        );
+                                        yarn_engine.is_complete = true;
+                                        queue_output(
+                                            yarn_engine,
+                                            YarnStepOutput::Event(BevyYarnEvent::EndConversation {
+                                                engine_entity: entity,
+                                                aborted: false,
+                                            }),
+                                        );
+                                        break;
+                                    }
+
+                                    yarn_engine.num_choices = options.len();
+                                    yarn_engine.option_details = options
+                                        .iter()
+                                        .map(|choice| {
+                                            (
+                                                choice.line.id.clone().into(),
+                                                choice.destination_node.clone().into(),
+                                            )
+                                        })
+                                        .collect();
+
+                                    if event_mask.contains(YarnEventMask::CHOICES) {
+                                        // choices are replaced but never skipped, so the
+                                        // option indices stay the same
+                                        let options = options
+                                            .iter()
+                                            .cloned()
+                                            .map(|mut choice| {
+                                                if let Some(line) = content_filter.filter_line(
+                                                    &choice.line,
+                                                    metadata_table,
+                                                    true,
+                                                ) {
+                                                    choice.line = line;
+                                                }
+                                                choice
+                                            })
+                                            .collect();
+                                        let reason = SuspendReason::Options(options);
+                                        queue_output(yarn_engine, unresolved(yarn_engine, reason));
+                                    }
+                                    break;
+                                }
+                                SuspendReason::Command(cmd_text) => {
+                                    debug!("Received command {cmd_text}");
+                                    yarn_engine.num_choices = 0;
+
+                                    let mut bevy_command = BevyYarnCommand::parse(cmd_text);
+                                    let suspend = Self::dispatch_yarn_command(
+                                        &mut commands,
+                                        &command_handlers,
+                                        &builtin_commands,
+                                        yarn_engine,
+                                        &mut bevy_command,
+                                    );
 
                                     // raise an event either way
-                                    send_yarn_events.send(BevyYarnEvent::Command(bevy_command));
+                                    if event_mask.contains(YarnEventMask::COMMAND) {
+                                        queue_output(
+                                            yarn_engine,
+                                            YarnStepOutput::Event(BevyYarnEvent::Command(
+                                                bevy_command,
+                                            )),
+                                        );
+                                    }
+
+                                    if suspend {
+                                        break;
+                                    }
                                 }
                                 SuspendReason::NodeChange { start, end } => {
                                     debug!("Move from node {start} to node {end}");
                                     yarn_engine.num_choices = 0;
+                                    yarn_engine.set_current_node(end.clone());
+                                    queue_output(
+                                        yarn_engine,
+                                        YarnStepOutput::NodeChange(end.clone()),
+                                    );
 
                                     // do not break here as we want to trigger the first line of the next node
                                 }
                                 SuspendReason::DialogueComplete(last_node) => {
                                     debug!("End dialogue on {last_node}");
                                     yarn_engine.num_choices = 0;
+
+                                    // resume the calling dialogue when a sub-conversation ends
+                                    if yarn_engine.pop_frame() {
+                                        info!("Returning to {}", yarn_engine.engine_name);
+                                        continue;
+                                    }
+
                                     yarn_engine.is_complete = true;
 
-                                    send_yarn_events.send(BevyYarnEvent::EndConversation);
+                                    if let Some(on_complete) = yarn_engine.on_complete.take() {
+                                        commands.add(move |world: &mut World| {
+                                            on_complete(world, entity)
+                                        });
+                                    }
+
+                                    if yarn_engine.is_bark
+                                        || event_mask.contains(YarnEventMask::END_CONVERSATION)
+                                    {
+                                        queue_output(
+                                            yarn_engine,
+                                            YarnStepOutput::Event(BevyYarnEvent::EndConversation {
+                                                engine_entity: entity,
+                                                aborted: false,
+                                            }),
+                                        );
+                                    }
                                     break;
                                 }
                                 SuspendReason::InvalidOption(option) => {
                                     warn!("Invalid option selected: {option}");
                                 }
+                                // suspend reasons added by newer versions of the virtual machine
+                                #[allow(unreachable_patterns)]
+                                reason => {
+                                    let stop = match step_settings.unknown_suspend_reason_handler {
+                                        Some(handler) => handler(yarn_engine, reason),
+                                        None => {
+                                            let error = BevyYarnError::UnknownSuspendReason(
+                                                describe_suspend_reason(reason),
+                                            );
+                                            warn!("{error}");
+
+                                            if event_mask.contains(YarnEventMask::ERROR) {
+                                                queue_output(
+                                                    yarn_engine,
+                                                    YarnStepOutput::Event(BevyYarnEvent::Error(
+                                                        error,
+                                                    )),
+                                                );
+                                            }
+                                            true
+                                        }
+                                    };
+
+                                    if stop {
+                                        break;
+                                    }
+                                }
                             }
                         }
                         Err(e) => {
-                            warn!("Encountered error during yarn execution: {e:?}");
+                            warn!("Encountered error during yarn execution: {e}");
+
+                            if let Some(ref mut trace) = trace {
+                                trace.record_error(
+                                    *step_count,
+                                    time.elapsed_seconds_f64(),
+                                    &yarn_engine,
+                                    e.clone(),
+                                );
+                            }
+
+                            yarn_engine.last_error = Some(e);
+                            break;
                         }
                     }
                 }
@@ -264,39 +2017,407 @@ impl YarnPlugin {
         }
     }
 
+    /// Replaces the unresolved lines and choices in the [YarnStepQueue] with
+    /// [BevyYarnEvent::Say] and [BevyYarnEvent::Choices] events, looking up their text and
+    /// metadata in the tables of the dialogue they came from. Lines whose tables have been
+    /// unloaded are left unresolved.
+    ///
+    /// Runs in [YarnSystemSet::ResolveLines]. It can be replaced, for instance to look lines
+    /// up in a translation service, with a system that changes the unresolved entries of
+    /// the queue:
+    ///
+    /// ```ignore
+    /// app.add_plugins(
+    ///     YarnPluginBuilder::default()
+    ///         .without_default_system(YarnSystemSet::ResolveLines)
+    ///         .build(),
+    /// )
+    /// .add_systems(Update, my_resolve_lines.in_set(YarnSystemSet::ResolveLines));
+    /// ```
+    pub fn resolve_dialogue_lines(
+        text_sources: YarnTextSources,
+        content_filter: Res<ContentFilter>,
+        mut missing_lines: ResMut<YarnMissingLines>,
+        mut queue: ResMut<YarnStepQueue>,
+        resource_engine: Option<Res<BevyYarnDialogueEngine>>,
+        yarn_engines: Query<&BevyYarnDialogueEngine>,
+    ) {
+        // engines whose program has been unloaded have no node headers
+        let no_headers = NodeHeaders::new();
+        let no_visited_nodes = HashMap::new();
+
+        for entry in queue.entries.iter_mut() {
+            let YarnStepOutput::Unresolved {
+                ref reason,
+                ref sources,
+            } = entry.output
+            else {
+                continue;
+            };
+
+            let (Some(string_table), Some(metadata_table)) = (
+                text_sources.string_tables.get(&sources.string_table),
+                text_sources.metadata_tables.get(&sources.metadata_table),
+            ) else {
+                continue;
+            };
+
+            #[cfg(feature = "trace")]
+            let _translate_span = trace_span!("translate_yarn_event").entered();
+
+            let locale_tables =
+                loaded_locale_tables(&sources.locale_tables, &text_sources.string_tables);
+            let node_headers = text_sources
+                .programs
+                .get(&sources.program)
+                .map_or(&no_headers, |program| &program.headers);
+            let yarn_engine = if entry.entity == Entity::PLACEHOLDER {
+                resource_engine.as_deref()
+            } else {
+                yarn_engines.get(entry.entity).ok()
+            };
+            let visited_nodes =
+                yarn_engine.map_or(&no_visited_nodes, |yarn_engine| &yarn_engine.visited_nodes);
+            let node = yarn_engine.and_then(BevyYarnDialogueEngine::current_node_name);
+
+            let context = YarnTranslationContext {
+                string_table,
+                metadata_table,
+                locale_tables: &locale_tables,
+                named_substitutions: &text_sources.named_substitutions,
+                missing_string_policy: &text_sources.missing_string_policy,
+                timing: &text_sources.timing,
+                tag_keys: &text_sources.tag_keys,
+                actor_config: &text_sources.actor_config,
+                node_headers,
+                visited_nodes,
+                engine_entity: entry.entity,
+            };
+
+            // missing lines are recorded when stepping, but choices are only checked here
+            if let SuspendReason::Options(options) = reason {
+                for choice in options {
+                    let (_, table) = context.resolve_table(&choice.line.id);
+                    if table.line_info(&choice.line.id).is_none() {
+                        missing_lines.record(&sources.string_table, &choice.line.id, node);
+                    }
+                }
+            }
+
+            if let Some(mut event) = suspend_reason_to_event(reason, &context) {
+                content_filter.censor(&mut event);
+                text_sources
+                    .line_middleware
+                    .apply(&mut event, entry.entity, node);
+                entry.output = YarnStepOutput::Event(event);
+            }
+        }
+    }
+
+    /// Sends the events in the [YarnStepQueue] and records them in the [DialogueHistory],
+    /// emptying the queue. The lines and end of barks are sent as [BevyYarnBarkEvent]s. The
+    /// [BevyYarnDialogueEngine::last_character] of each dialogue is updated as its lines are
+    /// sent, and the [BevyYarnLine::speaker_entity] of each line is set from the
+    /// [YarnCharacterRegistry]. A [BevyYarnSpeakerChangedEvent] is sent before each line
+    /// with a different speaker.
+    ///
+    /// Runs in [YarnSystemSet::DispatchEvents], see
+    /// [YarnPluginBuilder::without_default_system] to replace it.
+    pub fn dispatch_dialogue_events(
+        event_mask: Res<YarnEventMask>,
+        mut history: ResMut<DialogueHistory>,
+        mut queue: ResMut<YarnStepQueue>,
+        mut send_yarn_events: EventWriter<BevyYarnEvent>,
+        mut send_bark_events: EventWriter<BevyYarnBarkEvent>,
+        mut send_missing_speakers: EventWriter<BevyYarnMissingSpeakerEvent>,
+        mut send_speaker_changes: EventWriter<BevyYarnSpeakerChangedEvent>,
+        mut speakers: Local<HashMap<Entity, Option<String>>>,
+        mut character_registry: ResMut<YarnCharacterRegistry>,
+        mut diagnostics: ResMut<YarnDiagnostics>,
+        mut resource_engine: Option<ResMut<BevyYarnDialogueEngine>>,
+        mut yarn_engines: Query<&mut BevyYarnDialogueEngine>,
+    ) {
+        for mut entry in queue.entries.drain(..) {
+            // set the speaker of each line from the registry as it is sent
+            if let YarnStepOutput::Event(BevyYarnEvent::Say(ref mut line)) = entry.output {
+                if let Some(ref character) = line.character {
+                    let (speaker_entity, newly_missing) = character_registry.resolve(character);
+                    line.speaker_entity = speaker_entity;
+
+                    if newly_missing {
+                        let message = format!(
+                            "{} said line {} as {character}, but no entity has YarnCharacter({character})",
+                            entry.engine_name, line.line.id
+                        );
+                        warn!("{message}");
+                        diagnostics.push(
+                            YarnDiagnostic::new(
+                                YarnDiagnosticSeverity::Warning,
+                                YarnDiagnosticKind::MissingSpeaker,
+                                message,
+                            )
+                            .with_line_id(&line.line.id),
+                        );
+                        send_missing_speakers.send(BevyYarnMissingSpeakerEvent {
+                            character: character.clone(),
+                            line_id: line.line.id.clone().into(),
+                        });
+                    }
+                }
+            }
+
+            let YarnQueuedOutput {
+                entity,
+                engine_name,
+                is_bark,
+                time,
+                output,
+            } = entry;
+
+            match output {
+                YarnStepOutput::Event(BevyYarnEvent::Say(line)) if is_bark => {
+                    send_bark_events.send(BevyYarnBarkEvent::Line { entity, line });
+                }
+                YarnStepOutput::Event(BevyYarnEvent::EndConversation { .. }) if is_bark => {
+                    send_bark_events.send(BevyYarnBarkEvent::Finished(entity));
+                }
+                YarnStepOutput::Event(event) => {
+                    let mut yarn_engine = if entity == Entity::PLACEHOLDER {
+                        resource_engine.as_deref_mut()
+                    } else {
+                        yarn_engines.get_mut(entity).ok().map(Mut::into_inner)
+                    };
+
+                    match event {
+                        BevyYarnEvent::Say(ref line) => {
+                            if let Some(yarn_engine) = yarn_engine.as_deref_mut() {
+                                if yarn_engine.last_character != line.character {
+                                    let from = std::mem::replace(
+                                        &mut yarn_engine.last_character,
+                                        line.character.clone(),
+                                    );
+
+                                    if event_mask.contains(YarnEventMask::CHARACTER_CHANGED) {
+                                        send_yarn_events.send(BevyYarnEvent::CharacterChanged {
+                                            from,
+                                            to: line.character.clone(),
+                                        });
+                                    }
+                                }
+                            }
+
+                            // the speaker is forgotten when the node changes or the
+                            // dialogue ends, so the next line always re-frames
+                            if speakers.get(&entity) != Some(&line.character) {
+                                speakers.insert(entity, line.character.clone());
+                                send_speaker_changes.send(BevyYarnSpeakerChangedEvent {
+                                    engine: entity,
+                                    speaker: line.speaker_entity,
+                                    character: line.character.clone(),
+                                });
+                            }
+
+                            history.push(DialogueHistoryEntry::Line {
+                                engine_name,
+                                time,
+                                line: line.clone(),
+                            });
+                        }
+                        BevyYarnEvent::Choices {
+                            engine_entity,
+                            ref choices,
+                            ..
+                        } => {
+                            history.push(DialogueHistoryEntry::Choices {
+                                engine_name,
+                                time,
+                                engine_entity,
+                                choices: choices.clone(),
+                                selected: None,
+                            });
+                        }
+                        BevyYarnEvent::EndConversation { .. } => {
+                            speakers.remove(&entity);
+                        }
+                        _ => {}
+                    }
+
+                    if let Some(yarn_engine) = yarn_engine {
+                        yarn_engine.record_history(&event);
+                    }
+
+                    send_yarn_events.send(event);
+                }
+                YarnStepOutput::NodeChange(node) => {
+                    speakers.remove(&entity);
+                    history.push(DialogueHistoryEntry::NodeChange {
+                        engine_name,
+                        time,
+                        node,
+                    });
+                }
+                YarnStepOutput::Selection(index) => {
+                    history.record_selection(&engine_name, index);
+                }
+                YarnStepOutput::Unresolved { reason, .. } => {
+                    warn!(
+                        "Dropping unresolved {} from {engine_name}",
+                        describe_suspend_reason(&reason)
+                    );
+                }
+            }
+        }
+    }
+
+    /// Runs the handler registered for a command, or records the sub-conversation to start
+    /// for the `call` builtin, marking the command as handled if either is found. Returns
+    /// true if the dialogue should suspend until the sub-conversation starts
+    fn dispatch_yarn_command(
+        commands: &mut Commands,
+        command_handlers: &CommandHandlers,
+        builtin_commands: &BuiltinCommands,
+        yarn_engine: &mut BevyYarnDialogueEngine,
+        bevy_command: &mut BevyYarnCommand,
+    ) -> bool {
+        // sub-conversations are started once their program loads, so the dialogue is
+        // suspended here
+        if bevy_command.command_name == CALL_COMMAND && builtin_commands.is_enabled(CALL_COMMAND) {
+            match bevy_command.args.first() {
+                Some(yarnc_path) => {
+                    yarn_engine.pending_call = Some((
+                        yarnc_path.clone(),
+                        bevy_command
+                            .args
+                            .get(1)
+                            .cloned()
+                            .unwrap_or_else(|| "Start".into()),
+                    ));
+                    bevy_command.handled = true;
+                }
+                None => warn!("The {CALL_COMMAND} command requires a yarnc path"),
+            }
+
+            return yarn_engine.pending_call.is_some();
+        }
+
+        if let Some(handler) = command_handlers.0.get(&bevy_command.command_name) {
+            info!(
+                "Calling registered command {} with args {:?}",
+                bevy_command.command_name, bevy_command.args
+            );
+            bevy_command.handled = true;
+
+            // deferred handlers only queue commands, so don't need exclusive world access
+            match handler {
+                CommandHandler::Deferred(handler) => handler(commands, bevy_command.args.clone()),
+                _ => commands.add(bevy_command.clone()),
+            }
+        } else {
+            info!(
+                "Found unregistered command {} with args {:?}",
+                bevy_command.command_name, bevy_command.args
+            );
+        }
+
+        false
+    }
+
+    /// Returns the virtual machines of completed asynchronous steps to their engines, and
+    /// queues the results to be turned into events by [YarnPlugin::step_dialogue_vm]
+    fn poll_async_steps(
+        event_mask: Res<YarnEventMask>,
+        mut send_yarn_events: EventWriter<BevyYarnEvent>,
+        resource_engine: Option<ResMut<BevyYarnDialogueEngine>>,
+        mut yarn_engines: Query<&mut BevyYarnDialogueEngine>,
+    ) {
+        let yarn_engines = yarn_engines
+            .iter_mut()
+            .filter(|yarn_engine| yarn_engine.async_step.is_some())
+            .map(Mut::into_inner)
+            .chain(resource_engine.map(ResMut::into_inner));
+
+        for yarn_engine in yarn_engines {
+            let Some(ref mut task) = yarn_engine.async_step else {
+                continue;
+            };
+
+            let Some(step) = future::block_on(future::poll_once(task)) else {
+                continue;
+            };
+
+            yarn_engine.async_step = None;
+            yarn_engine.vm = step.vm;
+            yarn_engine.async_results = step.results;
+
+            // process the results even if there are no new step events
+            yarn_engine.step_deferred = true;
+
+            if event_mask.contains(YarnEventMask::VARIABLE_SET) {
+                for (name, value) in step.changed_variables {
+                    send_yarn_events.send(BevyYarnEvent::VariableSet { name, value });
+                }
+            }
+        }
+    }
+
+    /// Selects choices with the number keys and steps the dialogue with space. Selections are
+    /// sent with a [remote::SelectChoiceCommand] to the engine that offered the last choices,
+    /// which steps only that engine.
     #[cfg(feature = "input-handlers")]
     fn handle_input(
+        mut commands: Commands,
         keys: Res<Input<KeyCode>>,
+        mut yarn_events: EventReader<BevyYarnEvent>,
+        mut offering_engine: Local<Option<Entity>>,
         mut event_sender: EventWriter<BevyYarnStepDialogueEvent>,
-        mut engines: Query<&mut BevyYarnDialogueEngine>,
+        resource_engine: Option<Res<BevyYarnDialogueEngine>>,
+        engines: Query<&BevyYarnDialogueEngine>,
     ) {
-        for mut engine in engines.iter_mut() {
-            if engine.num_choices > 0 {
-                if keys.just_pressed(KeyCode::Key1) || keys.just_pressed(KeyCode::Numpad1) {
-                    info!("Sending step event (option 1 pressed)");
-                    let _ = engine.vm.set_selected_option(0);
-                    event_sender.send(BevyYarnStepDialogueEvent);
-                }
+        for event in yarn_events.iter() {
+            if let BevyYarnEvent::Choices { engine_entity, .. } = event {
+                *offering_engine = Some(*engine_entity);
+            }
+        }
 
-                if engine.num_choices > 1 && keys.just_pressed(KeyCode::Key2)
-                    || keys.just_pressed(KeyCode::Numpad2)
-                {
-                    info!("Sending step event (option 2 pressed)");
-                    let _ = engine.vm.set_selected_option(1);
-                    event_sender.send(BevyYarnStepDialogueEvent);
+        // the choices are stale once the engine has been stepped past them
+        let num_choices = offering_engine
+            .and_then(|entity| {
+                if entity == Entity::PLACEHOLDER {
+                    resource_engine.as_deref()
+                } else {
+                    engines.get(entity).ok()
                 }
+            })
+            .filter(|engine| engine.is_awaiting_choice() && !engine.priority_paused)
+            .map_or(0, |engine| engine.num_choices);
 
-                if engine.num_choices > 2 && keys.just_pressed(KeyCode::Key3)
-                    || keys.just_pressed(KeyCode::Numpad3)
-                {
-                    info!("Sending step event (option 3 pressed)");
-                    let _ = engine.vm.set_selected_option(2);
-                    event_sender.send(BevyYarnStepDialogueEvent);
-                }
-            } else if keys.just_pressed(KeyCode::Space) {
+        if num_choices == 0 {
+            *offering_engine = None;
+
+            if keys.just_pressed(KeyCode::Space) {
                 info!("Sending step event (space pressed)");
                 event_sender.send(BevyYarnStepDialogueEvent);
             }
+            return;
+        }
+
+        let choice_keys = [
+            (KeyCode::Key1, KeyCode::Numpad1),
+            (KeyCode::Key2, KeyCode::Numpad2),
+            (KeyCode::Key3, KeyCode::Numpad3),
+        ];
+
+        let pressed = choice_keys
+            .iter()
+            .take(num_choices)
+            .position(|(key, numpad)| keys.just_pressed(*key) || keys.just_pressed(*numpad));
+
+        if let (Some(choice_index), Some(entity)) = (pressed, *offering_engine) {
+            info!("Selecting choice (option {} pressed)", choice_index + 1);
+            commands.add(remote::SelectChoiceCommand {
+                entity,
+                choice_index,
+            });
         }
     }
 }
@@ -305,6 +2426,17 @@ impl YarnPlugin {
 #[derive(Default)]
 pub struct YarnPluginBuilder {
     commands: Vec<(String, CommandHandlerFn)>,
+    entity_commands: Vec<(String, EntityCommandHandlerFn)>,
+    deferred_commands: Vec<(String, DeferredCommandHandlerFn)>,
+    disabled_builtins: HashSet<String>,
+    state_transitions: Option<StateTransitionsFn>,
+    schedules: YarnSchedules,
+    async_stepping: bool,
+    unknown_suspend_reason_handler: Option<UnknownSuspendReasonHandler>,
+    missing_line_policy: MissingLinePolicy,
+    line_reveal: bool,
+    line_middleware: Vec<LineMiddlewareFn>,
+    replaced_systems: HashSet<YarnSystemSet>,
 }
 
 impl YarnPluginBuilder {
@@ -315,6 +2447,28 @@ impl YarnPluginBuilder {
         self
     }
 
+    /// Adds the given yarn command handlers to the builder, keeping the existing commands in
+    /// place. Accepts anything that converts into the command names, so commands can be
+    /// registered with `[("a", a), ("b", b)]`. Returns the builder.
+    pub fn with_yarn_commands_from<I, N>(mut self, yarn_commands: I) -> Self
+    where
+        I: IntoIterator<Item = (N, CommandHandlerFn)>,
+        N: Into<String>,
+    {
+        self.commands.extend(
+            yarn_commands
+                .into_iter()
+                .map(|(command_name, command)| (command_name.into(), command)),
+        );
+        self
+    }
+
+    /// Adds all of the commands from a [YarnCommandProvider] to the builder, keeping the
+    /// existing commands in place. Returns the builder.
+    pub fn add_yarn_commands_from_provider<P: YarnCommandProvider>(self) -> Self {
+        self.with_yarn_commands_from(P::commands())
+    }
+
     /// Adds a command to the command handlers, keeping the existing commands in place.
     /// Returns the builder
     pub fn with_yarn_command<N: Into<String>>(
@@ -326,10 +2480,898 @@ impl YarnPluginBuilder {
         self
     }
 
+    /// Adds a command that receives the targeted entity to the command handlers, keeping
+    /// the existing commands in place. Returns the builder
+    pub fn with_yarn_entity_command<N: Into<String>>(
+        mut self,
+        command_name: N,
+        command: EntityCommandHandlerFn,
+    ) -> Self {
+        self.entity_commands.push((command_name.into(), command));
+        self
+    }
+
+    /// Adds a command that queues changes with [Commands] to the command handlers, keeping
+    /// the existing commands in place, see [DeferredCommandHandlerFn]. Returns the builder
+    pub fn with_yarn_deferred_command<N: Into<String>>(
+        mut self,
+        command_name: N,
+        command: DeferredCommandHandlerFn,
+    ) -> Self {
+        self.deferred_commands.push((command_name.into(), command));
+        self
+    }
+
+    /// Disables all of the [BUILTIN_COMMANDS], so commands with those names are passed to
+    /// registered handlers or raised as [BevyYarnEvent::Command] events like any other
+    /// command. Returns the builder
+    pub fn without_builtin_commands(mut self) -> Self {
+        self.disabled_builtins
+            .extend(BUILTIN_COMMANDS.iter().map(|name| name.to_string()));
+        self
+    }
+
+    /// Disables the built in command with the given name, see [BUILTIN_COMMANDS].
+    /// Returns the builder
+    pub fn without_builtin_command<N: Into<String>>(mut self, command_name: N) -> Self {
+        self.disabled_builtins.insert(command_name.into());
+        self
+    }
+
+    /// Moves the app to the `dialogue` state when a dialogue on an entity with the
+    /// [TakesFocus] marker starts running, and to the `resume` state when all such dialogues
+    /// have completed. The state must already be added to the app with `add_state`.
+    /// Returns the builder
+    pub fn with_state_transitions<S: States>(mut self, dialogue: S, resume: S) -> Self {
+        self.state_transitions = Some(Arc::new(move |app: &mut App, schedule| {
+            app.insert_resource(YarnStateTransitions {
+                dialogue: dialogue.clone(),
+                resume: resume.clone(),
+            })
+            .add_systems(
+                schedule,
+                YarnStateTransitions::<S>::drive_state.after(YarnSystemSet::ProcessEvents),
+            );
+        }));
+        self
+    }
+
+    /// Sets the schedule the systems that load dialogues are added to, see
+    /// [YarnSystemSet::LoadData]. Defaults to [PreUpdate]. Returns the builder
+    pub fn with_load_schedule(mut self, schedule: impl ScheduleLabel) -> Self {
+        self.schedules.load = Box::new(schedule);
+        self
+    }
+
+    /// Sets the schedule the systems that step dialogues and handle input are added to,
+    /// see [YarnSystemSet::ProcessEvents] and [YarnSystemSet::Input]. Defaults to [Update].
+    /// For instance use [FixedUpdate] to step dialogues deterministically, in which case
+    /// the default input handlers may miss key presses. Returns the builder
+    pub fn with_process_schedule(mut self, schedule: impl ScheduleLabel) -> Self {
+        self.schedules.process = Box::new(schedule);
+        self
+    }
+
+    /// Adds all of the plugin's systems to the given schedule, see
+    /// [YarnPluginBuilder::with_load_schedule] and [YarnPluginBuilder::with_process_schedule].
+    /// Returns the builder
+    pub fn with_schedule(self, schedule: impl ScheduleLabel + Clone) -> Self {
+        self.with_load_schedule(schedule.clone())
+            .with_process_schedule(schedule)
+    }
+
+    /// Steps each dialogue's virtual machine on the [AsyncComputeTaskPool] rather than in
+    /// [YarnSystemSet::ProcessEvents], for dialogue where a single step runs enough commands
+    /// to cause a frame hitch. The virtual machine runs until it reaches a line, options,
+    /// the end of the dialogue or a [CALL_COMMAND], and the results are turned into events
+    /// on the main thread once the task completes, usually the following frame. Command
+    /// handlers still run on the main thread, after the step has finished.
+    ///
+    /// While a step is running the engine's virtual machine, see
+    /// [BevyYarnDialogueEngine::vm_mut_unchecked], is an empty placeholder and step events
+    /// for that engine are ignored. [BevyYarnEvent::VariableSet] events for a step are sent
+    /// before its other events. Returns the builder
+    pub fn with_async_stepping(mut self) -> Self {
+        self.async_stepping = true;
+        self
+    }
+
+    /// Sets a function that handles suspend reasons the plugin doesn't know about, see
+    /// [UnknownSuspendReasonHandler]. By default these raise a [BevyYarnEvent::Error] and
+    /// stop stepping the dialogue. Returns the builder
+    pub fn with_unknown_suspend_reason_handler(
+        mut self,
+        handler: UnknownSuspendReasonHandler,
+    ) -> Self {
+        self.unknown_suspend_reason_handler = Some(handler);
+        self
+    }
+
+    /// Sets what happens when the dialogue reaches a line that is missing from the string
+    /// table, see [MissingLinePolicy]. Defaults to [MissingLinePolicy::Placeholder].
+    /// Returns the builder
+    pub fn with_missing_line_policy(mut self, policy: MissingLinePolicy) -> Self {
+        self.missing_line_policy = policy;
+        self
+    }
+
+    /// Reveals lines in two stages for typewriters: after a line is said, the first step
+    /// completes its reveal and raises [BevyYarnEvent::LineRevealComplete], and only the
+    /// second step advances the dialogue. Typewriters that finish revealing a line by
+    /// themselves call [BevyYarnDialogueEngine::finish_line_reveal] so the next step
+    /// advances straight away. Returns the builder
+    pub fn with_line_reveal(mut self) -> Self {
+        self.line_reveal = true;
+        self
+    }
+
+    /// Adds a function that changes the text of every line and choice before it is sent,
+    /// for instance to filter profanity or normalise quotes. Middleware runs in the order it
+    /// is registered, after substitutions and format functions, see [LineMiddlewareFn] for
+    /// details. Returns the builder
+    pub fn with_line_middleware(mut self, middleware: LineMiddlewareFn) -> Self {
+        self.line_middleware.push(middleware);
+        self
+    }
+
+    /// Doesn't add the plugin's system for the given step of [YarnSystemSet::ProcessEvents],
+    /// one of [YarnSystemSet::StepDialogue], [YarnSystemSet::ResolveLines] or
+    /// [YarnSystemSet::DispatchEvents], so it can be replaced by a custom system in the same
+    /// set. See [YarnPlugin::resolve_dialogue_lines] for an example. Returns the builder
+    pub fn without_default_system(mut self, step: YarnSystemSet) -> Self {
+        self.replaced_systems.insert(step);
+        self
+    }
+
     /// Builds a yarn plugin
     pub fn build(self) -> YarnPlugin {
         YarnPlugin {
             commands: self.commands,
+            entity_commands: self.entity_commands,
+            deferred_commands: self.deferred_commands,
+            disabled_builtins: self.disabled_builtins,
+            state_transitions: self.state_transitions,
+            schedules: self.schedules,
+            async_stepping: self.async_stepping,
+            unknown_suspend_reason_handler: self.unknown_suspend_reason_handler,
+            missing_line_policy: self.missing_line_policy,
+            line_reveal: self.line_reveal,
+            line_middleware: self.line_middleware,
+            replaced_systems: self.replaced_systems,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::{event::ManualEventReader, system::SystemState};
+
+    use super::*;
+    use crate::{
+        pipeline::{YarnStepOutput, YarnStepQueue},
+        prelude::BevyYarnDialogueEngineBundle,
+        testing::TestDialogue,
+    };
+
+    #[test]
+    fn engine_looks_up_lines_in_its_own_tables() {
+        let dialogue = TestDialogue::kitchen_sink();
+        let world = &dialogue.app.world;
+        let engine = world
+            .get::<BevyYarnDialogueEngine>(dialogue.engine)
+            .expect("engine entity exists");
+        let string_tables = world.resource::<Assets<BevyYarnStringTable>>();
+        let metadata_tables = world.resource::<Assets<BevyYarnMetadataTable>>();
+
+        let line_info = engine
+            .get_string_for_id("line:4a18032a", string_tables)
+            .expect("line is in the string table");
+        assert_eq!(line_info.id, "line:4a18032a");
+        assert!(line_info.text.starts_with("Welcome to my story!"));
+        assert!(engine
+            .get_string_for_id("line:missing", string_tables)
+            .is_none());
+
+        let metadata = engine
+            .get_line_metadata("line:f45b799b", metadata_tables)
+            .expect("line is in the metadata table");
+        assert_eq!(metadata.tags, vec!["lastline".to_string()]);
+        assert!(engine
+            .get_line_metadata("line:4a18032a", metadata_tables)
+            .is_none());
+    }
+
+    /// The events raised over a few frames by a headless kitchen sink dialogue spawned
+    /// with the given data
+    fn spawn_headless_kitchen_sink(app: &mut App, data: YarnData) -> Entity {
+        app.world
+            .spawn(BevyYarnDialogueEngineBundle {
+                data,
+                ..BevyYarnDialogueEngineBundle::headless(
+                    include_bytes!("../assets/kitchen_sink.yarnc"),
+                    include_bytes!("../assets/kitchen_sink.lines.csv"),
+                    include_bytes!("../assets/kitchen_sink.metadata.csv"),
+                )
+            })
+            .id()
+    }
+
+    fn headless_kitchen_sink_events(data: YarnData) -> (App, Vec<BevyYarnEvent>) {
+        let mut app = crate::testing::headless_app(YarnPlugin::default());
+        spawn_headless_kitchen_sink(&mut app, data);
+
+        let mut reader = ManualEventReader::<BevyYarnEvent>::default();
+        let mut events = Vec::new();
+        for _ in 0..3 {
+            app.update();
+            events.extend(
+                reader
+                    .iter(app.world.resource::<Events<BevyYarnEvent>>())
+                    .cloned(),
+            );
         }
+
+        (app, events)
+    }
+
+    #[test]
+    fn dialogue_starts_once_loaded() {
+        let (_, events) = headless_kitchen_sink_events(YarnData::default());
+
+        assert!(matches!(events[0], BevyYarnEvent::DialogueReady(_)));
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, BevyYarnEvent::Say(_))));
+    }
+
+    #[test]
+    fn dialogue_without_auto_start_waits_to_be_advanced() {
+        let (mut app, events) =
+            headless_kitchen_sink_events(YarnData::default().without_auto_start());
+
+        assert_eq!(events.len(), 1);
+        let BevyYarnEvent::DialogueReady(engine) = events[0] else {
+            panic!("expected DialogueReady, got {:?}", events[0]);
+        };
+
+        let mut reader = app
+            .world
+            .resource::<Events<BevyYarnEvent>>()
+            .get_reader_current();
+        let mut next_line = |app: &mut App| {
+            app.update();
+            reader
+                .iter(app.world.resource::<Events<BevyYarnEvent>>())
+                .find_map(|event| match event {
+                    BevyYarnEvent::Say(line) => Some(line.line.id.clone()),
+                    _ => None,
+                })
+        };
+
+        app.world
+            .resource_mut::<Events<BevyYarnStepDialogueEvent>>()
+            .send(BevyYarnStepDialogueEvent);
+        assert_eq!(next_line(&mut app), None);
+
+        app.world
+            .get_mut::<BevyYarnDialogueEngine>(engine)
+            .expect("engine entity exists")
+            .try_advance();
+        assert_eq!(next_line(&mut app).as_deref(), Some("line:4a18032a"));
+    }
+
+    /// The number of lines and choices each engine has raised
+    fn lines_said(app: &App, engines: &[Entity]) -> Vec<usize> {
+        engines
+            .iter()
+            .map(|engine| {
+                app.world
+                    .get::<BevyYarnDialogueEngine>(*engine)
+                    .map_or(0, |engine| engine.history.len())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn loading_a_dialogue_only_steps_that_dialogue() {
+        let mut app = crate::testing::headless_app(YarnPlugin::default());
+        let running = spawn_headless_kitchen_sink(&mut app, YarnData::default());
+        app.update();
+        app.update();
+        assert_eq!(lines_said(&app, &[running]), [1]);
+
+        let waiting =
+            spawn_headless_kitchen_sink(&mut app, YarnData::default().without_auto_start());
+        let started = spawn_headless_kitchen_sink(&mut app, YarnData::default());
+        app.update();
+        app.update();
+        assert_eq!(lines_said(&app, &[running, waiting, started]), [1, 0, 1]);
+
+        // step events step the started dialogues, but not the one waiting to be advanced
+        app.world
+            .resource_mut::<Events<BevyYarnStepDialogueEvent>>()
+            .send(BevyYarnStepDialogueEvent);
+        app.update();
+        assert_eq!(lines_said(&app, &[running, waiting, started]), [2, 0, 2]);
+
+        app.world
+            .get_mut::<BevyYarnDialogueEngine>(waiting)
+            .expect("engine entity exists")
+            .try_advance();
+        app.update();
+        assert_eq!(lines_said(&app, &[running, waiting, started]), [2, 1, 2]);
+    }
+
+    #[test]
+    fn replays_missed_lines_and_choices() {
+        let mut dialogue = TestDialogue::kitchen_sink();
+        let mut live = Vec::new();
+        while !live
+            .iter()
+            .any(|event| matches!(event, BevyYarnEvent::Choices { .. }))
+        {
+            live.extend(dialogue.step().into_iter().filter(|event| {
+                matches!(event, BevyYarnEvent::Say(_) | BevyYarnEvent::Choices { .. })
+            }));
+        }
+        assert!(live.iter().all(|event| !event.is_replay()));
+
+        // a reader attached mid-conversation only sees the replayed events
+        let world = &mut dialogue.app.world;
+        let mut reader = world
+            .resource::<Events<BevyYarnEvent>>()
+            .get_reader_current();
+        let mut state =
+            SystemState::<(Query<&BevyYarnDialogueEngine>, EventWriter<BevyYarnEvent>)>::new(world);
+        let (engines, mut writer) = state.get_mut(world);
+        engines
+            .get(dialogue.engine)
+            .expect("engine entity exists")
+            .replay_history(&mut writer);
+
+        let replayed = reader
+            .iter(world.resource::<Events<BevyYarnEvent>>())
+            .cloned()
+            .collect::<Vec<_>>();
+        assert!(replayed.iter().all(BevyYarnEvent::is_replay));
+        assert_eq!(
+            replayed,
+            live.into_iter()
+                .map(BevyYarnEvent::into_replay)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn history_forgets_the_oldest_events() {
+        let mut dialogue = TestDialogue::kitchen_sink();
+        let mut engine = dialogue.engine();
+        for index in 0..ENGINE_HISTORY_CAPACITY + 10 {
+            let line = Line {
+                id: format!("line:{index}"),
+                substitutions: Vec::new(),
+            };
+            engine.record_history(&BevyYarnEvent::Say(BevyYarnLine::new(line, "text")));
+        }
+        engine.record_history(&BevyYarnEvent::EndConversation {
+            engine_entity: Entity::PLACEHOLDER,
+            aborted: false,
+        });
+
+        assert_eq!(engine.history.len(), ENGINE_HISTORY_CAPACITY);
+        assert!(matches!(
+            engine.history.front(),
+            Some(BevyYarnEvent::Say(line)) if line.line.id == "line:10"
+        ));
+    }
+
+    /// The id of the first line said in the given events
+    fn first_line_id(events: &[BevyYarnEvent]) -> Option<&str> {
+        events.iter().find_map(|event| match event {
+            BevyYarnEvent::Say(line) => Some(line.line.id.as_str()),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn unavailable_choices_are_not_offered() {
+        let mut dialogue = TestDialogue::kitchen_sink();
+        let choices = dialogue.step_to_choices();
+
+        // "Option 2" is unavailable until $my_var is set, so "Option 3" is the second option
+        let offered = choices
+            .iter()
+            .map(|choice| (choice.line_id.as_str(), choice.option_index))
+            .collect::<Vec<_>>();
+        assert_eq!(offered, [("line:626b7eec", 0), ("line:37f2bb71", 1)]);
+
+        let mut engine = dialogue.engine();
+        assert!(!engine.select_option(2));
+        assert!(engine.select_option(1));
+
+        let events = dialogue.step();
+        assert_eq!(first_line_id(&events), Some("line:8da3b2e8"));
+    }
+
+    /// The arguments the custom `call` handler was last called with
+    #[derive(Resource)]
+    struct CustomCall(Vec<String>);
+
+    fn custom_call(world: &mut World, args: Vec<String>) {
+        world.insert_resource(CustomCall(args));
+    }
+
+    /// Dispatches the command with the given text on the dialogue's engine and applies the
+    /// queued handlers, returning whether the dialogue was suspended and the command
+    fn dispatch_command(dialogue: &mut TestDialogue, text: &str) -> (bool, BevyYarnCommand) {
+        let world = &mut dialogue.app.world;
+        let mut state = SystemState::<(
+            Commands,
+            Res<CommandHandlers>,
+            Res<BuiltinCommands>,
+            Query<&mut BevyYarnDialogueEngine>,
+        )>::new(world);
+        let (mut commands, command_handlers, builtin_commands, mut engines) = state.get_mut(world);
+
+        let mut command = BevyYarnCommand::parse(text);
+        let suspended = YarnPlugin::dispatch_yarn_command(
+            &mut commands,
+            &command_handlers,
+            &builtin_commands,
+            &mut engines
+                .get_mut(dialogue.engine)
+                .expect("engine entity exists"),
+            &mut command,
+        );
+        state.apply(world);
+
+        (suspended, command)
+    }
+
+    #[test]
+    fn builtin_commands_take_precedence_over_handlers() {
+        let mut dialogue = TestDialogue::with_plugin(
+            YarnPluginBuilder::default()
+                .with_yarn_command(CALL_COMMAND, custom_call)
+                .build(),
+        );
+
+        let (suspended, command) = dispatch_command(&mut dialogue, "call shared.yarnc");
+
+        assert!(suspended);
+        assert!(command.handled);
+        assert_eq!(
+            dialogue.engine().pending_call,
+            Some(("shared.yarnc".into(), "Start".into()))
+        );
+        assert!(!dialogue.app.world.contains_resource::<CustomCall>());
+    }
+
+    #[test]
+    fn disabled_builtin_commands_use_their_handlers() {
+        let plugins = [
+            YarnPluginBuilder::default()
+                .with_yarn_command(CALL_COMMAND, custom_call)
+                .without_builtin_command(CALL_COMMAND)
+                .build(),
+            YarnPluginBuilder::default()
+                .with_yarn_command(CALL_COMMAND, custom_call)
+                .without_builtin_commands()
+                .build(),
+        ];
+
+        for plugin in plugins {
+            let mut dialogue = TestDialogue::with_plugin(plugin);
+
+            let (suspended, command) = dispatch_command(&mut dialogue, "call shared.yarnc");
+
+            assert!(!suspended);
+            assert!(command.handled);
+            assert_eq!(dialogue.engine().pending_call, None);
+            assert_eq!(
+                dialogue.app.world.resource::<CustomCall>().0,
+                vec!["shared.yarnc".to_string()]
+            );
+        }
+    }
+
+    #[test]
+    fn node_accessors_follow_node_changes() {
+        let mut dialogue = TestDialogue::kitchen_sink();
+        assert_eq!(dialogue.engine().current_node_name(), Some("Start"));
+        assert_eq!(dialogue.engine().previous_node_name(), None);
+
+        let option_3 = dialogue.option_3_position();
+        assert_eq!(dialogue.engine().current_node_name(), Some("second"));
+        assert_eq!(dialogue.engine().previous_node_name(), Some("Start"));
+
+        dialogue.engine().select_option(option_3);
+        while dialogue.engine().current_node_name() == Some("second") {
+            dialogue.step();
+        }
+
+        assert_eq!(dialogue.engine().current_node_name(), Some("option3"));
+        assert_eq!(dialogue.engine().previous_node_name(), Some("second"));
+    }
+
+    /// Updates the app until an engine has loaded, returning its name
+    fn wait_for_engine_name(app: &mut App) -> Option<String> {
+        (0..1000).find_map(|_| {
+            app.update();
+            std::thread::sleep(std::time::Duration::from_millis(1));
+
+            app.world
+                .query::<&BevyYarnDialogueEngine>()
+                .iter(&app.world)
+                .next()
+                .map(|engine| engine.engine_name.clone())
+        })
+    }
+
+    #[test]
+    fn dialogues_are_spawned_from_scenes() {
+        let mut app = crate::testing::headless_app(YarnPlugin::default());
+        let type_registry = app.world.resource::<AppTypeRegistry>().clone();
+
+        let mut scene_world = World::new();
+        scene_world.spawn(YarnData::new("kitchen_sink.yarnc").without_auto_start());
+        DynamicScene::from_world(&scene_world, &type_registry)
+            .write_to_world(&mut app.world, &mut Default::default())
+            .expect("YarnData is registered for scenes");
+
+        assert_eq!(
+            wait_for_engine_name(&mut app).as_deref(),
+            Some("kitchen_sink.yarnc")
+        );
+    }
+
+    #[test]
+    fn scene_data_without_a_path_is_skipped() {
+        let mut app = crate::testing::headless_app(YarnPlugin::default());
+        let data = app.world.spawn(YarnData::default()).id();
+
+        for _ in 0..3 {
+            app.update();
+        }
+
+        assert!(app.world.get::<YarnData>(data).is_some());
+        assert!(app.world.get::<BevyYarnDialogueEngine>(data).is_none());
+    }
+
+    #[test]
+    fn stepping_waits_for_the_tables_to_load() {
+        let mut dialogue = TestDialogue::kitchen_sink();
+        let string_table = dialogue.engine().string_table.clone();
+        let table = dialogue
+            .app
+            .world
+            .resource_mut::<Assets<BevyYarnStringTable>>()
+            .remove(&string_table)
+            .expect("string table is loaded");
+
+        let events = dialogue.step();
+        assert_eq!(first_line_id(&events), None);
+        assert!(dialogue.engine().step_deferred);
+
+        dialogue
+            .app
+            .world
+            .resource_mut::<Assets<BevyYarnStringTable>>()
+            .set_untracked(&string_table, table);
+
+        // the deferred step is retried without a new step event
+        let events = dialogue.update();
+        assert_eq!(first_line_id(&events), Some("line:4a18032a"));
+        assert!(!dialogue.engine().step_deferred);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn variables_round_trip_through_json() {
+        let mut dialogue = TestDialogue::kitchen_sink();
+        let count = dialogue
+            .engine()
+            .set_variables_from_json(
+                r#"{ "$met_guard": true, "$gold": 10.5, "$name": "Sam", "$items": [1, 2] }"#,
+            )
+            .expect("variables are a JSON object");
+        assert_eq!(count, 3);
+        let json = dialogue.engine().export_variables_as_json();
+
+        let mut imported = TestDialogue::kitchen_sink();
+        assert_eq!(
+            imported.engine().set_variables_from_json(&json).ok(),
+            Some(3)
+        );
+
+        let variables = &imported.engine().vm.variable_storage;
+        assert_eq!(variables.get("$met_guard"), Some(&YarnValue::Bool(true)));
+        assert_eq!(variables.get("$gold"), Some(&YarnValue::Number(10.5)));
+        assert_eq!(variables.get("$name"), Some(&YarnValue::Str("Sam".into())));
+        assert!(variables.get("$items").is_none());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn variables_must_be_a_json_object() {
+        let mut dialogue = TestDialogue::kitchen_sink();
+
+        assert!(matches!(
+            dialogue.engine().set_variables_from_json("[true]"),
+            Err(BevyYarnError::NotAnObject)
+        ));
+        assert!(dialogue.engine().set_variables_from_json("{").is_err());
+    }
+
+    /// Selects the offered choice with the given line id and steps the dialogue until it
+    /// offers choices again
+    fn choose(
+        dialogue: &mut TestDialogue,
+        choices: &[BevyYarnChoice],
+        line_id: &str,
+    ) -> Vec<BevyYarnChoice> {
+        let choice = choices
+            .iter()
+            .find(|choice| choice.line_id.as_str() == line_id)
+            .expect("choice is offered");
+        assert!(dialogue.engine().select_option(choice.option_index));
+        dialogue.step_to_choices()
+    }
+
+    #[test]
+    fn choices_are_marked_once_their_destination_is_visited() {
+        let mut dialogue = TestDialogue::kitchen_sink();
+        let choices = dialogue.step_to_choices();
+        assert!(choices.iter().all(|choice| !choice.was_previously_selected));
+
+        // "Option 1" then "No" loops back to the first choices
+        let nested = choose(&mut dialogue, &choices, "line:626b7eec");
+        let choices = choose(&mut dialogue, &nested, "line:9467dfef");
+
+        let previously_selected = choices
+            .iter()
+            .filter(|choice| choice.was_previously_selected)
+            .map(|choice| choice.line_id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(previously_selected, ["line:626b7eec"]);
+    }
+
+    /// A kitchen sink dialogue with the given line missing from its string table
+    fn dialogue_missing_line(policy: MissingLinePolicy, line_id: &str) -> TestDialogue {
+        let mut dialogue = TestDialogue::with_plugin(
+            YarnPluginBuilder::default()
+                .with_missing_line_policy(policy)
+                .build(),
+        );
+
+        let lines = include_str!("../assets/kitchen_sink.lines.csv")
+            .lines()
+            .filter(|line| !line.starts_with(&format!("{line_id},")))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let table = BevyYarnStringTable::from_csv(lines.as_bytes()).expect("table is valid");
+        let string_table = dialogue.engine().string_table.clone();
+        dialogue
+            .app
+            .world
+            .resource_mut::<Assets<BevyYarnStringTable>>()
+            .set_untracked(&string_table, table);
+
+        dialogue
+    }
+
+    #[test]
+    fn missing_lines_are_said_with_a_placeholder() {
+        let mut dialogue = dialogue_missing_line(MissingLinePolicy::Placeholder, "line:4a18032a");
+
+        let events = dialogue.step();
+        let line = events.iter().find_map(|event| match event {
+            BevyYarnEvent::Say(line) => Some(line),
+            _ => None,
+        });
+
+        assert_eq!(
+            line.map(|line| line.line.id.as_str()),
+            Some("line:4a18032a")
+        );
+        assert_eq!(
+            line.map(|line| &*line.formatted_text),
+            Some("<missing_string: line:4a18032a>")
+        );
+    }
+
+    #[test]
+    fn missing_lines_can_be_skipped() {
+        let mut dialogue = dialogue_missing_line(MissingLinePolicy::SkipLine, "line:4a18032a");
+
+        let events = dialogue.step();
+
+        assert_eq!(first_line_id(&events), Some("line:dc28045d"));
+    }
+
+    #[test]
+    fn missing_lines_can_stop_the_dialogue() {
+        let mut dialogue = dialogue_missing_line(MissingLinePolicy::Error, "line:4a18032a");
+
+        let events = dialogue.step();
+
+        assert_eq!(first_line_id(&events), None);
+        assert!(events.iter().any(|event| matches!(
+            event,
+            BevyYarnEvent::Error(BevyYarnError::MissingLine(line_id))
+                if line_id.as_str() == "line:4a18032a"
+        )));
+        assert!(dialogue.engine().last_error.is_some());
+    }
+
+    #[test]
+    fn missing_choice_lines_are_always_offered() {
+        let mut dialogue = dialogue_missing_line(MissingLinePolicy::SkipLine, "line:626b7eec");
+
+        let choices = dialogue.step_to_choices();
+        let option_1 = choices
+            .iter()
+            .find(|choice| choice.line_id.as_str() == "line:626b7eec")
+            .expect("missing choices are still offered");
+
+        assert_eq!(
+            &*option_1.formatted_line.formatted_text,
+            "<missing_string: line:626b7eec>"
+        );
+    }
+
+    /// Resolves lines with their line id as their text
+    fn resolve_lines_as_ids(mut queue: ResMut<YarnStepQueue>) {
+        for entry in queue.entries.iter_mut() {
+            if let YarnStepOutput::Unresolved {
+                reason: SuspendReason::Line(line),
+                ..
+            } = &entry.output
+            {
+                let say = BevyYarnEvent::Say(BevyYarnLine::new(line.clone(), line.id.clone()));
+                entry.output = YarnStepOutput::Event(say);
+            }
+        }
+    }
+
+    /// Upper cases the text of the resolved lines
+    fn shout_lines(mut queue: ResMut<YarnStepQueue>) {
+        for entry in queue.entries.iter_mut() {
+            if let YarnStepOutput::Event(BevyYarnEvent::Say(line)) = &mut entry.output {
+                line.formatted_text = line.formatted_text.to_uppercase().into();
+            }
+        }
+    }
+
+    /// The formatted text of the first line said in the given events
+    fn first_line_text(events: &[BevyYarnEvent]) -> Option<&str> {
+        events.iter().find_map(|event| match event {
+            BevyYarnEvent::Say(line) => Some(&*line.formatted_text),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn default_systems_can_be_replaced() {
+        let mut dialogue = TestDialogue::with_plugin(
+            YarnPluginBuilder::default()
+                .without_default_system(YarnSystemSet::ResolveLines)
+                .build(),
+        );
+        dialogue.app.add_systems(
+            Update,
+            resolve_lines_as_ids.in_set(YarnSystemSet::ResolveLines),
+        );
+
+        let events = dialogue.step();
+
+        assert_eq!(first_line_text(&events), Some("line:4a18032a"));
+    }
+
+    #[test]
+    fn systems_can_run_between_the_default_systems() {
+        let mut dialogue = TestDialogue::kitchen_sink();
+        dialogue.app.add_systems(
+            Update,
+            shout_lines
+                .after(YarnSystemSet::ResolveLines)
+                .before(YarnSystemSet::DispatchEvents),
+        );
+
+        let events = dialogue.step();
+
+        assert_eq!(
+            first_line_text(&events),
+            Some("WELCOME TO MY STORY! PRESS SPACE TO ADVANCE THE DIALOGUE.")
+        );
+    }
+
+    #[test]
+    fn try_advance_applies_the_selected_choice_through_to_the_end() {
+        let mut dialogue = TestDialogue::kitchen_sink();
+        let option_three = dialogue.option_3_position();
+
+        assert!(!dialogue.engine().try_advance());
+        assert!(dialogue.engine().select_option(option_three));
+
+        let mut events = Vec::new();
+        for _ in 0..10 {
+            if !dialogue.engine().try_advance() {
+                break;
+            }
+            events.extend(dialogue.update());
+        }
+
+        assert_eq!(first_line_id(&events), Some("line:8da3b2e8"));
+        assert!(events.contains(&BevyYarnEvent::EndConversation {
+            engine_entity: dialogue.engine,
+            aborted: false,
+        }));
+        assert!(!dialogue.engine().try_advance());
+    }
+
+    #[test]
+    fn despawned_engines_end_their_own_conversation() {
+        let mut dialogue = TestDialogue::kitchen_sink();
+        let second = dialogue.spawn_engine("second");
+        dialogue.step();
+
+        dialogue.app.world.despawn(second);
+        let events = dialogue.update();
+
+        assert_eq!(
+            events
+                .iter()
+                .filter(|event| matches!(event, BevyYarnEvent::EndConversation { .. }))
+                .collect::<Vec<_>>(),
+            vec![&BevyYarnEvent::EndConversation {
+                engine_entity: second,
+                aborted: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn callers_resume_when_the_called_program_fails_to_load() {
+        let mut dialogue = TestDialogue::kitchen_sink();
+        dialogue.step();
+        dialogue.engine().pending_call = Some(("missing.yarnc".into(), "Start".into()));
+
+        let mut events = Vec::new();
+        for _ in 0..1000 {
+            events.extend(dialogue.update());
+            if first_line_id(&events).is_some() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+
+        assert!(
+            events.contains(&BevyYarnEvent::Error(BevyYarnError::CallFailed(
+                "missing.yarnc".into()
+            )))
+        );
+        assert_eq!(first_line_id(&events), Some("line:dc28045d"));
+        assert_eq!(dialogue.engine().pending_call, None);
+    }
+
+    #[test]
+    fn manifest_loader_is_given_the_translated_locales_of_the_chain() {
+        let mut dialogue = TestDialogue::kitchen_sink();
+        dialogue.app.insert_resource(YarnLocaleChain(vec![
+            "pt-BR".into(),
+            LOCALE.into(),
+            "de".into(),
+        ]));
+        dialogue.update();
+
+        assert_eq!(
+            dialogue
+                .app
+                .world
+                .resource::<YarnManifestLocales>()
+                .translated(),
+            vec!["pt-BR", "de"]
+        );
     }
 }
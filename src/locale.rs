@@ -0,0 +1,112 @@
+//! Localisation support, resolving each line from an ordered chain of
+//! localised string tables.
+
+use std::sync::{Arc, RwLock};
+
+use bevy::prelude::{AssetServer, Assets, DetectChanges, Handle, Res, Resource};
+
+use crate::{
+    assets::{get_table_pathbuf_from_yarnc_path, BevyYarnStringTable},
+    LOCALE,
+};
+
+/// A resource containing the ordered chain of locales to look up lines in, for instance
+/// `pt-BR`, then `pt`, then `en`. Each line is taken from the first locale's string table
+/// that contains the line id. If no table in the chain contains the line, the base string
+/// table is used with the [LOCALE] locale.
+///
+/// The base string table (e.g. `story.lines.csv`) is used for the [LOCALE] locale, and
+/// the tables for other locales are loaded from `story.<locale>.lines.csv` next to the base
+/// table. The chain is read when a dialogue is loaded, so changes only apply to dialogues
+/// loaded afterwards.
+///
+/// For a `.yarnmanifest`, the [crate::assets::BevyYarnManifestAssetLoader] merges the
+/// `<file>.<locale>.lines.csv` table next to each yarnc file into one table per locale, so
+/// there is no combined table to provide. These are merged when the manifest is loaded, so
+/// locales added to the chain afterwards are only found once a manifest is loaded again.
+#[derive(Debug, Clone, Resource)]
+pub struct YarnLocaleChain(pub Vec<String>);
+
+impl Default for YarnLocaleChain {
+    fn default() -> Self {
+        Self(vec![LOCALE.into()])
+    }
+}
+
+impl YarnLocaleChain {
+    /// Loads the string table for each locale in the chain, for the given yarnc file. The
+    /// program's own string table is used for [LOCALE].
+    pub(crate) fn load_tables(
+        &self,
+        asset_server: &AssetServer,
+        yarnc_path: &str,
+        base_table: &Handle<BevyYarnStringTable>,
+    ) -> Vec<(String, Handle<BevyYarnStringTable>)> {
+        self.0
+            .iter()
+            .map(|locale| {
+                let table = if locale == LOCALE {
+                    base_table.clone()
+                } else if yarnc_path.ends_with(".yarnmanifest") {
+                    asset_server.load(&format!("{yarnc_path}#{locale}.lines"))
+                } else {
+                    asset_server.load(get_table_pathbuf_from_yarnc_path(
+                        yarnc_path,
+                        &format!("{locale}.lines"),
+                    ))
+                };
+
+                (locale.clone(), table)
+            })
+            .collect()
+    }
+}
+
+/// The locales of the [YarnLocaleChain], shared with the
+/// [crate::assets::BevyYarnManifestAssetLoader], which loads on other threads, so it can
+/// merge the locale tables of each yarnc file in a manifest
+#[derive(Debug, Clone, Default, Resource)]
+pub(crate) struct YarnManifestLocales(Arc<RwLock<Vec<String>>>);
+
+impl YarnManifestLocales {
+    /// Shares the locales of the chain with the manifest loader whenever the chain changes
+    pub(crate) fn sync(locale_chain: Res<YarnLocaleChain>, manifest_locales: Res<Self>) {
+        if !locale_chain.is_changed() {
+            return;
+        }
+
+        if let Ok(mut locales) = manifest_locales.0.write() {
+            locales.clone_from(&locale_chain.0);
+        }
+    }
+
+    /// The locales in the chain, other than [LOCALE] which uses the base string tables
+    pub(crate) fn translated(&self) -> Vec<String> {
+        self.0
+            .read()
+            .map(|locales| {
+                locales
+                    .iter()
+                    .filter(|locale| *locale != LOCALE)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Gets the string tables in the chain that have loaded, with their locale, for
+/// [crate::prelude::YarnTranslationContext::locale_tables]
+pub(crate) fn loaded_locale_tables<'a>(
+    locale_tables: &'a [(String, Handle<BevyYarnStringTable>)],
+    string_tables: &'a Assets<BevyYarnStringTable>,
+) -> Vec<(&'a str, &'a BevyYarnStringTable)> {
+    locale_tables
+        .iter()
+        .filter_map(|(locale, handle)| {
+            string_tables
+                .get(handle)
+                .map(|table| (locale.as_str(), table))
+        })
+        .collect()
+}
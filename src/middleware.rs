@@ -0,0 +1,83 @@
+//! Transforms applied to the text of every line and choice before it is sent, registered
+//! with [crate::YarnPluginBuilder::with_line_middleware].
+//!
+//! Middleware runs in [crate::YarnPlugin::resolve_dialogue_lines] once a line has been
+//! looked up in the string table, its substitutions completed, its format functions
+//! expanded and its character name and tags split off. Each middleware sees the changes of
+//! the ones registered before it, and the line is sent as [crate::prelude::BevyYarnEvent::Say]
+//! or as the [crate::prelude::BevyYarnChoice::formatted_line] of a choice once they have all
+//! run. As the substitutions have already been made, middleware such as a profanity filter
+//! also applies to substituted values like the player's name.
+
+use bevy::prelude::*;
+
+use crate::prelude::{BevyYarnEvent, BevyYarnLine};
+
+/// A function that changes a line before it is sent, see the [module documentation](self)
+pub type LineMiddlewareFn = fn(&mut BevyYarnLine, &MiddlewareCtx);
+
+/// The dialogue a line passed to a [LineMiddlewareFn] is from
+#[derive(Debug, Clone, Copy)]
+pub struct MiddlewareCtx<'a> {
+    /// The entity of the dialogue engine, or [Entity::PLACEHOLDER] for a resource engine
+    pub entity: Entity,
+    /// The node the dialogue is running
+    pub node: Option<&'a str>,
+    /// The locale of the string table the line's text was taken from
+    pub locale: &'a str,
+}
+
+/// The middleware registered with [crate::YarnPluginBuilder::with_line_middleware], in
+/// registration order
+#[derive(Resource, Default)]
+pub struct YarnLineMiddleware(pub(crate) Vec<LineMiddlewareFn>);
+
+impl YarnLineMiddleware {
+    /// Runs the middleware on the line of a [BevyYarnEvent::Say], or each choice of a
+    /// [BevyYarnEvent::Choices]. Middleware that changes the line id or the markup tags of
+    /// the text is warned about, as the line no longer matches its metadata.
+    pub(crate) fn apply(&self, event: &mut BevyYarnEvent, entity: Entity, node: Option<&str>) {
+        if self.0.is_empty() {
+            return;
+        }
+
+        let lines = match event {
+            BevyYarnEvent::Say(line) => vec![line],
+            BevyYarnEvent::Choices { choices, .. } => choices
+                .iter_mut()
+                .map(|choice| &mut choice.formatted_line)
+                .collect(),
+            _ => return,
+        };
+
+        for line in lines {
+            let line_id = line.line.id.clone();
+            let locale = line.resolved_locale.clone();
+            let context = MiddlewareCtx {
+                entity,
+                node,
+                locale: &locale,
+            };
+
+            for (index, middleware) in self.0.iter().enumerate() {
+                let markup = markup_tag_names(line);
+                middleware(line, &context);
+
+                if line.line.id != line_id {
+                    warn!("Line middleware {index} changed the id of line {line_id}");
+                }
+                if markup_tag_names(line) != markup {
+                    warn!("Line middleware {index} changed the markup of line {line_id}");
+                }
+            }
+        }
+    }
+}
+
+/// The markup tag names of the line, owned so the line can be changed
+fn markup_tag_names(line: &BevyYarnLine) -> Vec<String> {
+    line.markup_tag_names()
+        .into_iter()
+        .map(str::to_owned)
+        .collect()
+}
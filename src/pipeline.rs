@@ -0,0 +1,76 @@
+//! The results passed between the systems that step dialogues. Each frame,
+//! [crate::YarnPlugin::step_dialogue_vm] steps the virtual machines and queues their
+//! results in the [YarnStepQueue], [crate::YarnPlugin::resolve_dialogue_lines] looks up
+//! the text of the queued lines and choices, and
+//! [crate::YarnPlugin::dispatch_dialogue_events] sends the queued events. Systems added
+//! between the [crate::YarnSystemSet]s of these steps can read and change the queue.
+
+use bevy::prelude::*;
+use chapter::SuspendReason;
+
+use crate::{
+    assets::{BevyYarnMetadataTable, BevyYarnProgram, BevyYarnStringTable},
+    events::BevyYarnEvent,
+};
+
+/// The tables a queued line or set of choices is looked up in, taken from its dialogue
+/// when it was stepped as they change when sub-conversations start or finish
+#[derive(Debug, Clone)]
+pub struct YarnLineSources {
+    /// The program the line is from
+    pub program: Handle<BevyYarnProgram>,
+    /// The base string table of the dialogue
+    pub string_table: Handle<BevyYarnStringTable>,
+    /// The metadata table of the dialogue
+    pub metadata_table: Handle<BevyYarnMetadataTable>,
+    /// The string tables of the locale chain in order, with their locale
+    pub locale_tables: Vec<(String, Handle<BevyYarnStringTable>)>,
+}
+
+/// A result of stepping a dialogue, see [YarnStepQueue]
+#[derive(Debug, Clone)]
+pub enum YarnStepOutput {
+    /// A [SuspendReason::Line] or [SuspendReason::Options] from the virtual machine, which
+    /// is replaced with a [BevyYarnEvent::Say] or [BevyYarnEvent::Choices] event when it
+    /// is resolved
+    Unresolved {
+        /// The suspend reason from the virtual machine
+        reason: SuspendReason,
+        /// The tables to look the text up in
+        sources: YarnLineSources,
+    },
+    /// An event to send. [BevyYarnEvent::Say] and [BevyYarnEvent::Choices] events are
+    /// recorded in the [crate::history::DialogueHistory] when they are sent, and the lines
+    /// and end of barks are sent as [crate::prelude::BevyYarnBarkEvent]s instead.
+    Event(BevyYarnEvent),
+    /// The dialogue moved to the given node, which is recorded in the
+    /// [crate::history::DialogueHistory]
+    NodeChange(String),
+    /// The choice with the given option index was selected, which is recorded in the
+    /// [crate::history::DialogueHistory]
+    Selection(usize),
+}
+
+/// A queued result of stepping a dialogue, with the dialogue it came from
+#[derive(Debug, Clone)]
+pub struct YarnQueuedOutput {
+    /// The entity of the dialogue engine, or [Entity::PLACEHOLDER] for a resource engine
+    pub entity: Entity,
+    /// The [crate::BevyYarnDialogueEngine::engine_name] of the dialogue
+    pub engine_name: String,
+    /// Whether the dialogue is a [crate::prelude::YarnBark]
+    pub is_bark: bool,
+    /// The elapsed app time in seconds when the dialogue was stepped
+    pub time: f64,
+    /// The result
+    pub output: YarnStepOutput,
+}
+
+/// A resource with the results of stepping the dialogues this frame, in order. This is
+/// filled in [crate::YarnSystemSet::StepDialogue] and emptied in
+/// [crate::YarnSystemSet::DispatchEvents], see the [module documentation](self).
+#[derive(Resource, Debug, Default)]
+pub struct YarnStepQueue {
+    /// The queued results, oldest first
+    pub entries: Vec<YarnQueuedOutput>,
+}
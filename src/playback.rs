@@ -0,0 +1,276 @@
+//! Rules for how often nodes can be played, so ambient dialogue such as barks doesn't
+//! repeat too often.
+
+use std::{collections::HashMap, time::Duration};
+
+use bevy::prelude::*;
+
+use crate::{
+    assets::{BevyYarnProgram, NodeHeaders, TAGS_HEADER},
+    BevyYarnDialogueEngine,
+};
+
+/// The node tag for [NodePlaybackRule::Once]
+pub const ONCE_TAG: &str = "once";
+
+/// The node tag for [NodePlaybackRule::Cooldown], followed by the cooldown in seconds, for
+/// instance `cooldown:120`
+pub const COOLDOWN_TAG: &str = "cooldown";
+
+/// How often a node can be played, see [YarnNodePlaybackRules]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum NodePlaybackRule {
+    /// The node can be played any number of times
+    #[default]
+    Unlimited,
+    /// The node can only be played once per save, see
+    /// [YarnNodePlaybackRules::played_once_nodes]
+    Once,
+    /// The node can't be played again until the given time has passed since it last started
+    Cooldown(Duration),
+}
+
+impl NodePlaybackRule {
+    /// Parses the rule from the tags of a node, which are `once` or `cooldown:<seconds>`,
+    /// optionally starting with a `#`. Returns `None` if there are no playback tags.
+    pub fn from_tags(tags: &str) -> Option<Self> {
+        tags.split_whitespace()
+            .map(|tag| tag.trim_start_matches('#'))
+            .find_map(|tag| match tag.split_once(':') {
+                None if tag == ONCE_TAG => Some(Self::Once),
+                Some((COOLDOWN_TAG, seconds)) => match seconds.parse::<f32>() {
+                    Ok(seconds) if seconds >= 0.0 => {
+                        Some(Self::Cooldown(Duration::from_secs_f32(seconds)))
+                    }
+                    _ => {
+                        warn!("Ignoring invalid node tag {tag}, expected cooldown:<seconds>");
+                        None
+                    }
+                },
+                _ => None,
+            })
+    }
+}
+
+/// Values kept for each node of each program, keyed by the asset path of the program and
+/// the node name
+#[derive(Debug)]
+struct ProgramNodes<T>(HashMap<String, HashMap<String, T>>);
+
+impl<T> Default for ProgramNodes<T> {
+    fn default() -> Self {
+        Self(HashMap::new())
+    }
+}
+
+impl<T> ProgramNodes<T> {
+    fn get(&self, program: &str, node: &str) -> Option<&T> {
+        self.0.get(program).and_then(|nodes| nodes.get(node))
+    }
+
+    fn insert(&mut self, program: &str, node: &str, value: T) {
+        self.0
+            .entry(program.to_owned())
+            .or_default()
+            .insert(node.to_owned(), value);
+    }
+
+    fn remove(&mut self, program: &str, node: &str) {
+        if let Some(nodes) = self.0.get_mut(program) {
+            nodes.remove(node);
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().flat_map(|(program, nodes)| {
+            nodes
+                .keys()
+                .map(move |node| (program.as_str(), node.as_str()))
+        })
+    }
+}
+
+/// The asset path of a program, which its [YarnNodePlaybackRules] are kept under. This is
+/// empty for programs that weren't loaded from a file, such as
+/// [crate::prelude::YarnHeadlessData].
+pub(crate) fn program_path(
+    asset_server: &AssetServer,
+    program: &Handle<BevyYarnProgram>,
+) -> String {
+    asset_server
+        .get_handle_path(program)
+        .map(|path| path.path().to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// A resource with the [NodePlaybackRule] of each node, and when each node was last played.
+/// Rules are set with [YarnNodePlaybackRules::set_rule], or with `once` and
+/// `cooldown:<seconds>` node tags in the yarn files:
+///
+/// ```yarn
+/// title: WeatherBark
+/// tags: cooldown:120
+/// ---
+/// Guard: Looks like rain.
+/// ===
+/// ```
+///
+/// Rules set in code replace rules from tags. Rules apply to the nodes of one program, given
+/// by the asset path it was loaded from such as `npcs.yarnc`, so nodes with the same name in
+/// other programs aren't affected. [crate::prelude::YarnBark]s and [crate::prelude::YarnData]
+/// check [YarnNodePlaybackRules::can_play] before starting, and are removed without playing
+/// if their node can't be played. Nodes are marked as played when any dialogue moves to them.
+///
+/// The nodes played with [NodePlaybackRule::Once] should be saved alongside the dialogue
+/// variables, see [YarnNodePlaybackRules::played_once_nodes].
+#[derive(Resource, Debug, Default)]
+pub struct YarnNodePlaybackRules {
+    rules: ProgramNodes<NodePlaybackRule>,
+    tag_rules: ProgramNodes<NodePlaybackRule>,
+    played_once: ProgramNodes<()>,
+    last_played: ProgramNodes<Duration>,
+    now: Duration,
+}
+
+impl YarnNodePlaybackRules {
+    /// Sets the rule for the given node of the program loaded from the given asset path
+    pub fn set_rule(&mut self, program: &str, node: &str, rule: NodePlaybackRule) {
+        self.rules.insert(program, node, rule);
+    }
+
+    /// Sets the rule for the given node of the program loaded from the given asset path.
+    /// Returns the rules.
+    pub fn with_rule(mut self, program: &str, node: &str, rule: NodePlaybackRule) -> Self {
+        self.set_rule(program, node, rule);
+        self
+    }
+
+    /// The rule for the given node of a program, from [YarnNodePlaybackRules::set_rule] or
+    /// the node's tags, otherwise [NodePlaybackRule::Unlimited]
+    pub fn rule(&self, program: &str, node: &str) -> NodePlaybackRule {
+        self.rules
+            .get(program, node)
+            .or_else(|| self.tag_rules.get(program, node))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Whether the given node of a program can be played now under its rule
+    pub fn can_play(&self, program: &str, node: &str) -> bool {
+        match self.rule(program, node) {
+            NodePlaybackRule::Unlimited => true,
+            NodePlaybackRule::Once => self.played_once.get(program, node).is_none(),
+            NodePlaybackRule::Cooldown(cooldown) => self
+                .last_played
+                .get(program, node)
+                .map_or(true, |played| self.now.saturating_sub(*played) >= cooldown),
+        }
+    }
+
+    /// Marks the given node of a program as played now. This is done automatically when a
+    /// dialogue moves to a node, so is only needed for nodes played some other way.
+    pub fn mark_played(&mut self, program: &str, node: &str) {
+        self.last_played.insert(program, node, self.now);
+        if self.rule(program, node) == NodePlaybackRule::Once {
+            self.played_once.insert(program, node, ());
+        }
+    }
+
+    /// The program paths and names of the nodes with [NodePlaybackRule::Once] that have
+    /// been played, sorted
+    pub fn played_once_nodes(&self) -> Vec<(&str, &str)> {
+        let mut nodes = self.played_once.iter().collect::<Vec<_>>();
+        nodes.sort_unstable();
+        nodes
+    }
+
+    /// Forgets which nodes have been played, for instance when starting a new game
+    pub fn clear_played(&mut self) {
+        self.played_once.0.clear();
+        self.last_played.0.clear();
+    }
+
+    /// Exports the [YarnNodePlaybackRules::played_once_nodes] as a JSON array of
+    /// `[program, node]` pairs, to save along with
+    /// [crate::prelude::BevyYarnDialogueEngine::export_variables_as_json]
+    #[cfg(feature = "serde")]
+    pub fn export_played_once_as_json(&self) -> String {
+        serde_json::json!(self.played_once_nodes()).to_string()
+    }
+
+    /// Marks the nodes in a JSON array of `[program, node]` pairs exported with
+    /// [YarnNodePlaybackRules::export_played_once_as_json] as played once, returning the
+    /// number of nodes
+    #[cfg(feature = "serde")]
+    pub fn set_played_once_from_json(
+        &mut self,
+        json: &str,
+    ) -> Result<usize, crate::prelude::BevyYarnError> {
+        let nodes: Vec<(String, String)> = serde_json::from_str(json)?;
+        for (program, node) in nodes.iter() {
+            self.played_once.insert(program, node, ());
+        }
+        Ok(nodes.len())
+    }
+
+    /// Reads the rules of a program from the node tags of the given headers
+    fn add_tag_rules(&mut self, program: &str, headers: &NodeHeaders) {
+        for (node, node_headers) in headers {
+            match node_headers
+                .get(TAGS_HEADER)
+                .and_then(|tags| NodePlaybackRule::from_tags(tags))
+            {
+                Some(rule) => self.tag_rules.insert(program, node, rule),
+                None => self.tag_rules.remove(program, node),
+            };
+        }
+    }
+
+    /// Reads the rules from the node tags of programs as they load
+    pub(crate) fn collect_tag_rules(
+        mut program_events: EventReader<AssetEvent<BevyYarnProgram>>,
+        asset_server: Res<AssetServer>,
+        programs: Res<Assets<BevyYarnProgram>>,
+        mut rules: ResMut<YarnNodePlaybackRules>,
+    ) {
+        for event in program_events.iter() {
+            let (AssetEvent::Created { handle } | AssetEvent::Modified { handle }) = event else {
+                continue;
+            };
+
+            if let Some(program) = programs.get(handle) {
+                rules.add_tag_rules(&program_path(&asset_server, handle), &program.headers);
+            }
+        }
+    }
+
+    /// Marks nodes as played when a dialogue moves to them
+    pub(crate) fn record_playback(
+        time: Res<Time>,
+        asset_server: Res<AssetServer>,
+        mut rules: ResMut<YarnNodePlaybackRules>,
+        mut current_nodes: Local<HashMap<Entity, String>>,
+        resource_engine: Option<Res<BevyYarnDialogueEngine>>,
+        engines: Query<(Entity, &BevyYarnDialogueEngine)>,
+    ) {
+        rules.now = time.elapsed();
+
+        let engines = engines.iter().chain(
+            resource_engine
+                .as_deref()
+                .map(|engine| (Entity::PLACEHOLDER, engine)),
+        );
+
+        let mut previous_nodes = std::mem::take(&mut *current_nodes);
+        for (entity, engine) in engines {
+            let Some(node) = engine.current_node_name() else {
+                continue;
+            };
+
+            if previous_nodes.remove(&entity).as_deref() != Some(node) {
+                rules.mark_played(&program_path(&asset_server, &engine.program), node);
+            }
+            current_nodes.insert(entity, node.to_owned());
+        }
+    }
+}
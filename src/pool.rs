@@ -0,0 +1,104 @@
+//! Reuses dialogue engines for conversations that are run repeatedly, such as NPCs with
+//! repeatable dialogue, to avoid building a new virtual machine every time they start.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::BevyYarnDialogueEngine;
+
+/// The default value of [YarnEnginePool::max_per_program]
+pub const DEFAULT_POOL_SIZE: usize = 4;
+
+/// Add this to an entity with a [crate::prelude::YarnData] to return its dialogue engine to
+/// the [YarnEnginePool] when the dialogue completes. The engine is removed from the entity
+/// and reset to its start node, and the next [crate::prelude::YarnData] for the same yarnc
+/// path takes it from the pool rather than building a new one.
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct ReturnToPool {
+    /// Whether variables the dialogue set are kept when the engine is reset, so they carry
+    /// over to the next conversation that uses the engine. Otherwise they are cleared.
+    pub keep_variables: bool,
+}
+
+/// A resource holding completed dialogue engines that have been reset for reuse, by the
+/// path of the yarnc file they were loaded from. See [ReturnToPool].
+#[derive(Resource, Debug)]
+pub struct YarnEnginePool {
+    /// The most engines kept for each yarnc path. Engines returned to a full pool are dropped.
+    pub max_per_program: usize,
+    engines: HashMap<String, Vec<BevyYarnDialogueEngine>>,
+}
+
+impl Default for YarnEnginePool {
+    fn default() -> Self {
+        Self {
+            max_per_program: DEFAULT_POOL_SIZE,
+            engines: HashMap::new(),
+        }
+    }
+}
+
+impl YarnEnginePool {
+    /// The number of engines in the pool, across all yarnc paths
+    pub fn len(&self) -> usize {
+        self.engines.values().map(Vec::len).sum()
+    }
+
+    /// Whether the pool has no engines
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of engines in the pool for the given yarnc path
+    pub fn len_for(&self, yarnc_path: &str) -> usize {
+        self.engines.get(yarnc_path).map_or(0, Vec::len)
+    }
+
+    /// Drops every engine in the pool, for instance when the yarnc files are reloaded
+    pub fn clear(&mut self) {
+        self.engines.clear();
+    }
+
+    /// Takes a reset engine for the given yarnc path out of the pool, if there is one
+    pub(crate) fn take(&mut self, yarnc_path: &str) -> Option<BevyYarnDialogueEngine> {
+        self.engines.get_mut(yarnc_path)?.pop()
+    }
+
+    /// Adds a reset engine to the pool, dropping it if the pool is full
+    fn insert(&mut self, engine: BevyYarnDialogueEngine) {
+        let engines = self.engines.entry(engine.engine_name.clone()).or_default();
+
+        if engines.len() < self.max_per_program {
+            engines.push(engine);
+        }
+    }
+
+    /// A system that moves the engines of completed dialogues marked with [ReturnToPool]
+    /// into the pool
+    pub(crate) fn return_completed(
+        mut commands: Commands,
+        mut pool: ResMut<YarnEnginePool>,
+        mut yarn_engines: Query<(Entity, &mut BevyYarnDialogueEngine, &ReturnToPool)>,
+    ) {
+        for (entity, mut yarn_engine, return_to_pool) in yarn_engines.iter_mut() {
+            if !yarn_engine.is_complete {
+                continue;
+            }
+
+            // the placeholder is complete, so removing it doesn't abort the conversation
+            let mut placeholder = BevyYarnDialogueEngine::empty();
+            placeholder.is_complete = true;
+            let mut engine = std::mem::replace(&mut *yarn_engine, placeholder);
+            commands.entity(entity).remove::<BevyYarnDialogueEngine>();
+
+            match engine.reset("Start", return_to_pool.keep_variables) {
+                Ok(()) => {
+                    debug!("Returning {} to the engine pool", engine.engine_name);
+                    pool.insert(engine);
+                }
+                Err(e) => warn!("Unable to reset {} for reuse: {e}", engine.engine_name),
+            }
+        }
+    }
+}
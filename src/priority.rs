@@ -0,0 +1,58 @@
+//! Priorities between dialogues that run at the same time, so that for instance barks
+//! never talk over a story conversation.
+
+use bevy::prelude::*;
+
+use crate::BevyYarnDialogueEngine;
+
+/// The priority of the dialogue on this entity, add this next to the
+/// [crate::prelude::YarnData]. While a dialogue is running, every running dialogue with a
+/// lower priority is paused and resumes once no higher priority dialogue is running.
+/// Dialogues with the same priority don't pause each other. Dialogues without this
+/// component, including a resource engine, have a priority of 0.
+///
+/// Paused dialogues aren't stepped, step events sent while they are paused are ignored
+/// rather than held, and the default input handlers ignore them. Barks stop counting down
+/// to their next line while paused.
+///
+/// ```ignore
+/// commands.spawn((YarnData::new("story.yarnc"), DialoguePriority(10)));
+/// commands.spawn(YarnBark::new(asset_server.load("npcs.yarnc"), "WeatherBark"));
+/// ```
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Reflect)]
+#[reflect(Component, Default)]
+pub struct DialoguePriority(pub u8);
+
+impl DialoguePriority {
+    /// Pauses every running dialogue that has a lower priority than the highest priority
+    /// running dialogue, and resumes the others
+    pub(crate) fn arbitrate(
+        resource_engine: Option<ResMut<BevyYarnDialogueEngine>>,
+        mut engines: Query<(&mut BevyYarnDialogueEngine, Option<&DialoguePriority>)>,
+    ) {
+        let highest = engines
+            .iter()
+            .filter(|(engine, _)| !engine.is_complete)
+            .map(|(_, priority)| priority.copied().unwrap_or_default())
+            .chain(
+                resource_engine
+                    .as_deref()
+                    .filter(|engine| !engine.is_complete)
+                    .map(|_| DialoguePriority::default()),
+            )
+            .max()
+            .unwrap_or_default();
+
+        let engines = engines
+            .iter_mut()
+            .map(|(engine, priority)| (engine, priority.copied().unwrap_or_default()))
+            .chain(resource_engine.map(|engine| (engine.into(), DialoguePriority::default())));
+
+        for (mut engine, priority) in engines {
+            let paused = priority < highest;
+            if engine.priority_paused != paused {
+                engine.priority_paused = paused;
+            }
+        }
+    }
+}
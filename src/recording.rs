@@ -0,0 +1,287 @@
+//! Recording the player's inputs to dialogues and replaying them, for reproducing crashes
+//! and checking that narrative flows still play the same way after content changes.
+//!
+//! Insert a [DialogueRecording] resource to start recording. Every step of a dialogue is
+//! appended to the recording, with the node and last line the dialogue was showing. With
+//! the `serde` feature the recording can be saved and loaded. To replay it, start the same
+//! dialogues without [crate::prelude::YarnData::auto_start] and insert a [DialogueReplayer]:
+//!
+//! ```ignore
+//! commands.spawn(YarnData::new("story.yarnc").without_auto_start());
+//! commands.insert_resource(DialogueReplayer::new(recording));
+//! ```
+//!
+//! The replayer sends one recorded input per frame, checking first that the dialogue is
+//! showing the same node and line as when it was recorded. If it isn't, replaying stops
+//! and the difference is available from [DialogueReplayer::divergence]. Recordings can also
+//! be replayed headlessly with [crate::testing::YarnTestRunner::with_choices] and
+//! [DialogueRecording::selections].
+
+use std::fmt::Display;
+
+use bevy::prelude::*;
+
+use crate::{
+    assets::{BevyYarnMetadataTable, BevyYarnStringTable},
+    events::BevyYarnStepDialogueEvent,
+    history::{DialogueHistory, DialogueHistoryEntry},
+    ids::LineId,
+    BevyYarnDialogueEngine,
+};
+
+/// An input to a dialogue in a [DialogueRecording]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DialogueInput {
+    /// The dialogue was stepped without selecting a choice
+    Continue,
+    /// The choice with the given [crate::prelude::BevyYarnChoice::option_index] was selected
+    /// and the dialogue was stepped
+    SelectOption(usize),
+}
+
+/// A single input in a [DialogueRecording], with the state of the dialogue it was sent to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DialogueRecordingEntry {
+    /// The [BevyYarnDialogueEngine::engine_name] of the dialogue
+    pub engine_name: String,
+    /// The node the dialogue was running
+    pub node: Option<String>,
+    /// The id of the last line the dialogue said, if any
+    pub last_line: Option<LineId>,
+    /// The input sent to the dialogue
+    pub input: DialogueInput,
+}
+
+impl DialogueRecordingEntry {
+    /// Creates an entry for the given input with the current state of the engine
+    fn from_engine(
+        engine: &BevyYarnDialogueEngine,
+        history: &DialogueHistory,
+        input: DialogueInput,
+    ) -> Self {
+        let last_line = history.entries.iter().rev().find_map(|entry| match entry {
+            DialogueHistoryEntry::Line {
+                engine_name, line, ..
+            } if *engine_name == engine.engine_name => Some(LineId(line.line.id.clone())),
+            _ => None,
+        });
+
+        Self {
+            engine_name: engine.engine_name.clone(),
+            node: engine.current_node_name().map(str::to_owned),
+            last_line,
+            input,
+        }
+    }
+}
+
+impl Display for DialogueRecordingEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} to {} at node {} after line {}",
+            self.input,
+            self.engine_name,
+            self.node.as_deref().unwrap_or("<none>"),
+            self.last_line.as_ref().map_or("<none>", |id| id.0.as_str())
+        )
+    }
+}
+
+/// A resource that records every input sent to the dialogues while it exists, see the
+/// [module documentation](self). Barks and dialogues paused by a
+/// [crate::prelude::DialoguePriority] aren't recorded as they ignore step events.
+#[derive(Resource, Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DialogueRecording {
+    /// The seed of the game's random number generator, if the dialogue depends on it. This
+    /// isn't used by the plugin, and should be set and restored by the game.
+    pub seed: Option<u64>,
+    /// The recorded inputs, oldest first
+    pub entries: Vec<DialogueRecordingEntry>,
+}
+
+impl DialogueRecording {
+    /// Creates an empty recording with the seed of the game's random number generator
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            seed: Some(seed),
+            entries: Vec::new(),
+        }
+    }
+
+    /// The option indices of the recorded choice selections, in order, for replaying a
+    /// single dialogue headlessly with [crate::testing::YarnTestRunner::with_choices]
+    pub fn selections(&self) -> Vec<usize> {
+        self.entries
+            .iter()
+            .filter_map(|entry| match entry.input {
+                DialogueInput::SelectOption(index) => Some(index),
+                DialogueInput::Continue => None,
+            })
+            .collect()
+    }
+
+    /// Records an entry for each step of a dialogue this frame, from step events or
+    /// [BevyYarnDialogueEngine::try_advance]
+    pub(crate) fn record(
+        mut recording: ResMut<DialogueRecording>,
+        mut step_events: EventReader<BevyYarnStepDialogueEvent>,
+        history: Res<DialogueHistory>,
+        string_tables: Res<Assets<BevyYarnStringTable>>,
+        metadata_tables: Res<Assets<BevyYarnMetadataTable>>,
+        resource_engine: Option<Res<BevyYarnDialogueEngine>>,
+        engines: Query<&BevyYarnDialogueEngine>,
+    ) {
+        let steps = step_events.iter().count();
+
+        let engines = engines
+            .iter()
+            .chain(resource_engine.as_deref())
+            .filter(|engine| !engine.is_complete && !engine.is_bark && !engine.priority_paused);
+
+        for engine in engines {
+            // step events only step dialogues that have started, and dialogues advanced
+            // before their tables have loaded are stepped once they load
+            let engine_steps = if engine.started { steps } else { 0 };
+            let deferred = engine.step_deferred
+                && string_tables.contains(&engine.string_table)
+                && metadata_tables.contains(&engine.metadata_table);
+
+            // only the first step of the frame applies the selection
+            for step in 0..engine_steps.max(deferred as usize) {
+                let input = match engine.selected_option {
+                    Some(index) if step == 0 => DialogueInput::SelectOption(index),
+                    _ => DialogueInput::Continue,
+                };
+
+                let entry = DialogueRecordingEntry::from_engine(engine, &history, input);
+                recording.entries.push(entry);
+            }
+        }
+    }
+}
+
+/// The first difference between a [DialogueRecording] and the replayed dialogue
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayDivergence {
+    /// The index of the entry that couldn't be replayed
+    pub index: usize,
+    /// The recorded entry
+    pub expected: DialogueRecordingEntry,
+    /// The state of the dialogue when the entry was replayed, or `None` if the dialogue had
+    /// already completed
+    pub actual: Option<DialogueRecordingEntry>,
+}
+
+impl Display for ReplayDivergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "replay diverged at entry {}", self.index)?;
+        writeln!(f, "- {}", self.expected)?;
+        match self.actual {
+            Some(ref actual) => write!(f, "+ {actual}"),
+            None => write!(f, "+ {} had completed", self.expected.engine_name),
+        }
+    }
+}
+
+impl std::error::Error for ReplayDivergence {}
+
+/// A resource that replays a [DialogueRecording] while it exists, see the
+/// [module documentation](self)
+#[derive(Resource, Debug)]
+pub struct DialogueReplayer {
+    recording: DialogueRecording,
+    next_entry: usize,
+    divergence: Option<ReplayDivergence>,
+}
+
+impl DialogueReplayer {
+    /// Creates a replayer for the given recording
+    pub fn new(recording: DialogueRecording) -> Self {
+        Self {
+            recording,
+            next_entry: 0,
+            divergence: None,
+        }
+    }
+
+    /// The recording being replayed
+    pub fn recording(&self) -> &DialogueRecording {
+        &self.recording
+    }
+
+    /// Whether every entry has been replayed without diverging
+    pub fn is_finished(&self) -> bool {
+        self.divergence.is_none() && self.next_entry >= self.recording.entries.len()
+    }
+
+    /// The first difference between the recording and the replayed dialogue, if replaying
+    /// stopped because the dialogue diverged
+    pub fn divergence(&self) -> Option<&ReplayDivergence> {
+        self.divergence.as_ref()
+    }
+
+    /// Applies the next recorded input to its dialogue once it has loaded and is ready for
+    /// input, stepping only that dialogue, and stops if the dialogue has diverged from the
+    /// recording
+    pub(crate) fn replay(
+        mut replayer: ResMut<DialogueReplayer>,
+        history: Res<DialogueHistory>,
+        resource_engine: Option<ResMut<BevyYarnDialogueEngine>>,
+        mut engines: Query<&mut BevyYarnDialogueEngine>,
+    ) {
+        if replayer.divergence.is_some() {
+            return;
+        }
+
+        let index = replayer.next_entry;
+        let Some(expected) = replayer.recording.entries.get(index).cloned() else {
+            return;
+        };
+
+        // wait for the dialogue to load
+        let Some(engine) = engines
+            .iter_mut()
+            .map(Mut::into_inner)
+            .chain(resource_engine.map(ResMut::into_inner))
+            .find(|engine| engine.engine_name == expected.engine_name)
+        else {
+            return;
+        };
+
+        // wait for steps and sub-conversations that are still running
+        if engine.step_deferred
+            || engine.pending_call.is_some()
+            || engine.async_step.is_some()
+            || engine.priority_paused
+        {
+            return;
+        }
+
+        let actual = (!engine.is_complete)
+            .then(|| DialogueRecordingEntry::from_engine(engine, &history, expected.input));
+
+        let replayed = actual.as_ref() == Some(&expected)
+            && match expected.input {
+                DialogueInput::SelectOption(option_index) => engine.select_option(option_index),
+                DialogueInput::Continue => true,
+            };
+
+        if !replayed {
+            let divergence = ReplayDivergence {
+                index,
+                expected,
+                actual,
+            };
+            error!("{divergence}");
+            replayer.divergence = Some(divergence);
+            return;
+        }
+
+        replayer.next_entry += 1;
+        engine.try_advance();
+    }
+}
@@ -0,0 +1,275 @@
+//! Support for running the dialogue on an authoritative server and rendering it on remote
+//! clients. With the `serde` feature, [crate::prelude::BevyYarnEvent]s can be serialized
+//! and sent to the clients, which reply with [BevyYarnSelectOptionEvent]s when the player
+//! picks a choice. Within the app, a [SelectChoiceCommand] routes a selection to the
+//! engine that offered the choices.
+
+use bevy::{ecs::system::Command, prelude::*};
+
+use crate::BevyYarnDialogueEngine;
+
+/// An event that selects a choice and steps the dialogue, the counterpart of
+/// [crate::prelude::BevyYarnEvent::Choices] for clients that can't access the
+/// [BevyYarnDialogueEngine], for instance because they are on another machine. Serializable
+/// with the `serde` feature.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BevyYarnSelectOptionEvent {
+    /// The entity of the dialogue engine, or [Entity::PLACEHOLDER] for a resource engine
+    /// started with [crate::prelude::YarnDialogue]
+    pub entity: Entity,
+    /// The [crate::prelude::BevyYarnChoice::option_index] of the selected choice
+    pub option_index: usize,
+}
+
+impl BevyYarnSelectOptionEvent {
+    /// Selects the choices from [BevyYarnSelectOptionEvent]s, stepping only the selected
+    /// engine for each valid selection. Invalid selections are logged and ignored.
+    pub(crate) fn apply_selections(
+        mut events: EventReader<BevyYarnSelectOptionEvent>,
+        mut resource_engine: Option<ResMut<BevyYarnDialogueEngine>>,
+        mut engines: Query<&mut BevyYarnDialogueEngine>,
+    ) {
+        for event in events.iter() {
+            let engine = if event.entity == Entity::PLACEHOLDER {
+                resource_engine.as_deref_mut()
+            } else {
+                engines.get_mut(event.entity).ok().map(Mut::into_inner)
+            };
+
+            match engine {
+                Some(engine) if engine.select_option(event.option_index) => {
+                    engine.try_advance();
+                }
+                Some(_) => warn!(
+                    "Option {} isn't available on {:?}, ignoring the selection",
+                    event.option_index, event.entity
+                ),
+                None => warn!(
+                    "No dialogue engine on {:?}, ignoring the selection",
+                    event.entity
+                ),
+            }
+        }
+    }
+}
+
+/// A command that selects a choice on the engine that offered it and steps that engine, so
+/// a selection goes to the right engine and other running dialogues don't advance
+///
+/// ```ignore
+/// if let BevyYarnEvent::Choices { engine_entity, .. } = event {
+///     commands.add(SelectChoiceCommand {
+///         entity: *engine_entity,
+///         choice_index: 0,
+///     });
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SelectChoiceCommand {
+    /// The [crate::prelude::BevyYarnEvent::Choices::engine_entity] of the choices
+    pub entity: Entity,
+    /// The position of the selected choice in the [crate::prelude::BevyYarnEvent::Choices]
+    /// event, which is its [crate::prelude::BevyYarnChoice::option_index]
+    pub choice_index: usize,
+}
+
+impl Command for SelectChoiceCommand {
+    fn apply(self, world: &mut World) {
+        let select = |mut engine: Mut<BevyYarnDialogueEngine>| {
+            engine.select_option(self.choice_index) && engine.try_advance()
+        };
+        let selected = if self.entity == Entity::PLACEHOLDER {
+            world
+                .get_resource_mut::<BevyYarnDialogueEngine>()
+                .map(select)
+        } else {
+            world
+                .get_mut::<BevyYarnDialogueEngine>(self.entity)
+                .map(select)
+        };
+
+        match selected {
+            Some(true) => {}
+            Some(false) => warn!(
+                "Choice {} isn't available on {:?}, ignoring the selection",
+                self.choice_index, self.entity
+            ),
+            None => warn!(
+                "No dialogue engine on {:?}, ignoring the selection",
+                self.entity
+            ),
+        }
+    }
+}
+
+/// Serializes the virtual machine's lines by their id and substitutions
+#[cfg(feature = "serde")]
+pub(crate) mod serde_line {
+    use std::sync::Arc;
+
+    use chapter::Line;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(remote = "Line")]
+    struct LineDef {
+        id: String,
+        substitutions: Vec<String>,
+    }
+
+    pub(crate) fn serialize<S: Serializer>(
+        line: &Arc<Line>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        LineDef::serialize(line, serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Arc<Line>, D::Error> {
+        LineDef::deserialize(deserializer).map(Arc::new)
+    }
+}
+
+/// Serializes yarn values as a plain bool, number or string, the same as
+/// [crate::BevyYarnDialogueEngine::export_variables_as_json]
+#[cfg(feature = "serde")]
+pub(crate) mod serde_yarn_value {
+    use chapter::YarnValue;
+    use serde::{ser::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum PlainValue {
+        Bool(bool),
+        Number(f32),
+        Str(String),
+    }
+
+    pub(crate) fn serialize<S: Serializer>(
+        value: &YarnValue,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            YarnValue::Bool(value) => PlainValue::Bool(*value),
+            YarnValue::Number(value) => PlainValue::Number(*value),
+            YarnValue::Str(value) => PlainValue::Str(value.clone()),
+            value => {
+                return Err(S::Error::custom(format!(
+                    "unsupported yarn value {value:?}"
+                )))
+            }
+        }
+        .serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<YarnValue, D::Error> {
+        Ok(match PlainValue::deserialize(deserializer)? {
+            PlainValue::Bool(value) => YarnValue::Bool(value),
+            PlainValue::Number(value) => YarnValue::Number(value),
+            PlainValue::Str(value) => YarnValue::Str(value),
+        })
+    }
+}
+
+/// Serializes a map of yarn variables with their values as plain booleans, numbers or
+/// strings, see [serde_yarn_value]
+#[cfg(feature = "serde")]
+pub(crate) mod serde_yarn_variables {
+    use std::collections::HashMap;
+
+    use chapter::YarnValue;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct PlainVariable(#[serde(with = "super::serde_yarn_value")] YarnValue);
+
+    pub(crate) fn serialize<S: Serializer>(
+        variables: &HashMap<String, YarnValue>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(
+            variables
+                .iter()
+                .map(|(name, value)| (name, PlainVariable(value.clone()))),
+        )
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<String, YarnValue>, D::Error> {
+        Ok(HashMap::<String, PlainVariable>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(name, PlainVariable(value))| (name, value))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{events::BevyYarnStepDialogueEvent, testing::TestDialogue};
+
+    /// The number of lines and choices the engine has raised
+    fn history_len(dialogue: &TestDialogue, engine: Entity) -> usize {
+        dialogue
+            .app
+            .world
+            .get::<BevyYarnDialogueEngine>(engine)
+            .expect("engine entity exists")
+            .history
+            .len()
+    }
+
+    #[test]
+    fn selections_only_step_the_engine_that_offered_the_choices() {
+        let mut dialogue = TestDialogue::kitchen_sink();
+        let position = dialogue.option_3_position();
+
+        // the second engine is showing a line while the first shows choices
+        let second = dialogue.spawn_engine("second");
+        dialogue
+            .app
+            .world
+            .get_mut::<BevyYarnDialogueEngine>(second)
+            .expect("engine entity exists")
+            .try_advance();
+        dialogue.update();
+        let second_lines = history_len(&dialogue, second);
+        let first_lines = history_len(&dialogue, dialogue.engine);
+
+        SelectChoiceCommand {
+            entity: dialogue.engine,
+            choice_index: position,
+        }
+        .apply(&mut dialogue.app.world);
+        assert!(dialogue
+            .app
+            .world
+            .resource::<Events<BevyYarnStepDialogueEvent>>()
+            .is_empty());
+        dialogue.update();
+
+        assert!(history_len(&dialogue, dialogue.engine) > first_lines);
+        assert_eq!(history_len(&dialogue, second), second_lines);
+    }
+
+    #[test]
+    fn selections_for_missing_engines_are_ignored() {
+        let mut dialogue = TestDialogue::kitchen_sink();
+        dialogue.step_to_choices();
+        let missing = dialogue.app.world.spawn_empty().id();
+
+        SelectChoiceCommand {
+            entity: missing,
+            choice_index: 0,
+        }
+        .apply(&mut dialogue.app.world);
+
+        assert_eq!(dialogue.engine().selected_option, None);
+        assert!(!dialogue.engine().step_deferred);
+    }
+}
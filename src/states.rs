@@ -0,0 +1,50 @@
+//! Integration with bevy [States], for games that switch into a dialogue state while
+//! a conversation is running, see [crate::YarnPluginBuilder::with_state_transitions].
+
+use bevy::prelude::*;
+
+use crate::BevyYarnDialogueEngine;
+
+/// A marker for dialogues that take focus from the game, such as conversations with NPCs.
+/// Add this to the entity with the [crate::prelude::YarnData]. When state transitions are
+/// configured with [crate::YarnPluginBuilder::with_state_transitions], the dialogue state
+/// is entered while any engine with this marker is running and not paused by a higher
+/// [crate::prelude::DialoguePriority]. Engines without the marker, such as barks and ambient
+/// dialogue, never change the state.
+#[derive(Component, Debug, Default, Clone, Copy, Reflect)]
+#[reflect(Component, Default)]
+pub struct TakesFocus;
+
+/// The states to move between as focused dialogues start and finish
+#[derive(Resource)]
+pub(crate) struct YarnStateTransitions<S: States> {
+    pub(crate) dialogue: S,
+    pub(crate) resume: S,
+}
+
+impl<S: States> YarnStateTransitions<S> {
+    /// Moves to the dialogue state when the first focused dialogue starts running, and to
+    /// the resume state when the last one completes. The state is only changed when the
+    /// dialogues start or finish, so other state changes in between are left alone.
+    pub(crate) fn drive_state(
+        transitions: Res<YarnStateTransitions<S>>,
+        mut next_state: ResMut<NextState<S>>,
+        mut was_active: Local<bool>,
+        engines: Query<&BevyYarnDialogueEngine, With<TakesFocus>>,
+    ) {
+        let is_active = engines
+            .iter()
+            .any(|engine| !engine.is_complete && !engine.is_priority_paused());
+
+        if is_active == *was_active {
+            return;
+        }
+
+        *was_active = is_active;
+        next_state.set(if is_active {
+            transitions.dialogue.clone()
+        } else {
+            transitions.resume.clone()
+        });
+    }
+}
@@ -0,0 +1,58 @@
+//! Per-character styling for dialogue lines, used to colour the speaker's
+//! name and line text differently for each character.
+
+use std::collections::HashMap;
+
+use bevy::prelude::{Color, Font, Handle, Resource};
+
+/// The style to use when displaying a line said by a character
+#[derive(Debug, Clone)]
+pub struct YarnCharacterStyle {
+    /// The colour to display the character's name in
+    pub name_color: Color,
+    /// The colour to display the line text in
+    pub text_color: Color,
+    /// An optional font to use for the line, if not set the UI's default font is used
+    pub font: Option<Handle<Font>>,
+}
+
+impl Default for YarnCharacterStyle {
+    fn default() -> Self {
+        Self {
+            name_color: Color::WHITE,
+            text_color: Color::WHITE,
+            font: None,
+        }
+    }
+}
+
+/// A resource mapping character names (as found in [crate::prelude::BevyYarnLine::character])
+/// to the style that should be used to display their lines.
+#[derive(Debug, Default, Resource)]
+pub struct YarnCharacterStyles {
+    /// The style to use for lines with no character, or for characters with no registered style
+    pub default_style: YarnCharacterStyle,
+
+    styles: HashMap<String, YarnCharacterStyle>,
+}
+
+impl YarnCharacterStyles {
+    /// Adds or replaces the style for the given character. Returns the resource.
+    pub fn with_style<N: Into<String>>(mut self, character: N, style: YarnCharacterStyle) -> Self {
+        self.insert(character, style);
+        self
+    }
+
+    /// Adds or replaces the style for the given character
+    pub fn insert<N: Into<String>>(&mut self, character: N, style: YarnCharacterStyle) {
+        self.styles.insert(character.into(), style);
+    }
+
+    /// Gets the style for the given character, falling back to the default style
+    /// if there is no character or the character has no registered style.
+    pub fn get_style(&self, character: Option<&str>) -> &YarnCharacterStyle {
+        character
+            .and_then(|name| self.styles.get(name))
+            .unwrap_or(&self.default_style)
+    }
+}
@@ -0,0 +1,644 @@
+//! A headless dialogue runner and random playthrough fuzzer for validating yarn
+//! content in tests, without graphics or an asset server. For instance:
+//!
+//! ```ignore
+//! let run = YarnTestRunner::new(
+//!     include_bytes!("../assets/kitchen_sink.yarnc"),
+//!     include_bytes!("../assets/kitchen_sink.lines.csv"),
+//!     include_bytes!("../assets/kitchen_sink.metadata.csv"),
+//! )
+//! .with_choices([0, 1])
+//! .run()
+//! .expect("dialogue runs to completion");
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
+
+use bevy::{
+    ecs::event::{Events, ManualEventReader},
+    prelude::*,
+};
+use chapter::{Program, VirtualMachine, YarnValue};
+use prost::Message;
+
+use crate::{
+    assets::{
+        decode_node_headers, BevyYarnMetadataTable, BevyYarnProgram, BevyYarnStringTable,
+        SUPPORTED_PROGRAM_VERSION,
+    },
+    data::BevyYarnChoice,
+    events::{BevyYarnEvent, BevyYarnStepDialogueEvent},
+    history::{DialogueHistory, DialogueHistoryEntry},
+    BevyYarnDialogueEngine, YarnPlugin,
+};
+
+/// The maximum number of times the dialogue is stepped before a run is abandoned
+pub const DEFAULT_MAX_STEPS: usize = 10_000;
+
+/// The reasons a [YarnTestRunner] run can fail
+#[derive(Debug)]
+pub enum YarnTestError {
+    /// The compiled program could not be decoded
+    InvalidProgram(prost::DecodeError),
+    /// One of the CSV tables could not be parsed
+    InvalidTable(csv::Error),
+    /// The start node could not be set on the virtual machine
+    InvalidStartNode(String),
+    /// The dialogue offered choices but the script had no choices left
+    NotEnoughChoices {
+        /// The number of choices that were offered
+        offered: usize,
+    },
+    /// The script provided a choice index that wasn't offered by the dialogue
+    InvalidChoice {
+        /// The scripted choice index
+        index: usize,
+        /// The number of choices that were offered
+        offered: usize,
+    },
+    /// The virtual machine raised an error while stepping the dialogue
+    Dialogue(String),
+    /// The dialogue did not complete within the maximum number of steps
+    StepLimitReached(usize),
+}
+
+impl Display for YarnTestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidProgram(e) => write!(f, "failed to decode yarn program: {e}"),
+            Self::InvalidTable(e) => write!(f, "failed to parse yarn table: {e}"),
+            Self::InvalidStartNode(e) => write!(f, "failed to set start node: {e}"),
+            Self::NotEnoughChoices { offered } => write!(
+                f,
+                "dialogue offered {offered} choices but the script has no choices left"
+            ),
+            Self::InvalidChoice { index, offered } => write!(
+                f,
+                "scripted choice {index} is not valid, dialogue offered {offered} choices"
+            ),
+            Self::Dialogue(e) => write!(f, "dialogue raised an error: {e}"),
+            Self::StepLimitReached(steps) => {
+                write!(f, "dialogue did not complete within {steps} steps")
+            }
+        }
+    }
+}
+
+impl std::error::Error for YarnTestError {}
+
+impl From<prost::DecodeError> for YarnTestError {
+    fn from(value: prost::DecodeError) -> Self {
+        Self::InvalidProgram(value)
+    }
+}
+
+impl From<csv::Error> for YarnTestError {
+    fn from(value: csv::Error) -> Self {
+        Self::InvalidTable(value)
+    }
+}
+
+/// A minimal app without graphics or an asset server to run dialogue with the given plugin
+pub(crate) fn headless_app(plugin: YarnPlugin) -> App {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, AssetPlugin::default(), plugin))
+        .init_resource::<Input<KeyCode>>();
+    app
+}
+
+/// The result of a successful [YarnTestRunner] run
+#[derive(Debug)]
+pub struct YarnTestRun {
+    /// Every event raised by the dialogue, in order
+    pub events: Vec<BevyYarnEvent>,
+    /// The variables stored in the virtual machine when the dialogue completed
+    pub variables: HashMap<String, YarnValue>,
+    /// The names of the nodes that the dialogue visited, including the start node
+    pub visited_nodes: HashSet<String>,
+}
+
+/// Runs a compiled yarn program to completion in a minimal headless bevy app,
+/// automatically stepping the dialogue and selecting scripted choices.
+pub struct YarnTestRunner<'a> {
+    program: &'a [u8],
+    lines: &'a [u8],
+    metadata: &'a [u8],
+    choices: Vec<usize>,
+    start_node: String,
+    max_steps: usize,
+    plugin: YarnPlugin,
+}
+
+impl<'a> YarnTestRunner<'a> {
+    /// Creates a runner from the contents of a yarnc file and its lines and metadata CSV files
+    pub fn new(program: &'a [u8], lines: &'a [u8], metadata: &'a [u8]) -> Self {
+        Self {
+            program,
+            lines,
+            metadata,
+            choices: Vec::new(),
+            start_node: "Start".into(),
+            max_steps: DEFAULT_MAX_STEPS,
+            plugin: YarnPlugin::default(),
+        }
+    }
+
+    /// Sets the choice indices to select, in order, each time the dialogue offers choices.
+    /// Returns the runner.
+    pub fn with_choices<I: IntoIterator<Item = usize>>(mut self, choices: I) -> Self {
+        self.choices = choices.into_iter().collect();
+        self
+    }
+
+    /// Sets the node to start the dialogue from, the default is `Start`. Returns the runner.
+    pub fn with_start_node<N: Into<String>>(mut self, start_node: N) -> Self {
+        self.start_node = start_node.into();
+        self
+    }
+
+    /// Sets the maximum number of times to step the dialogue before failing, the
+    /// default is [DEFAULT_MAX_STEPS]. Returns the runner.
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Sets the plugin to run the dialogue with, for instance one built with
+    /// [crate::YarnPluginBuilder] that has command handlers registered. Returns the runner.
+    pub fn with_plugin(mut self, plugin: YarnPlugin) -> Self {
+        self.plugin = plugin;
+        self
+    }
+
+    /// Runs the dialogue to completion, returning every event raised and the final variables
+    pub fn run(mut self) -> Result<YarnTestRun, YarnTestError> {
+        let mut choices = std::mem::take(&mut self.choices).into_iter();
+
+        match self.play(|offered| {
+            choices.next().ok_or(YarnTestError::NotEnoughChoices {
+                offered: offered.len(),
+            })
+        })? {
+            (run, None) => Ok(run),
+            (_, Some(error)) => Err(error),
+        }
+    }
+
+    /// Builds a headless app with the plugin and spawns the dialogue engine, ready to step
+    fn build_app(&self) -> Result<(App, Entity), YarnTestError> {
+        let program = Program::decode(self.program)?;
+        let headers = decode_node_headers(self.program)?;
+        let string_table = BevyYarnStringTable::from_csv(self.lines)?;
+        let metadata_table = BevyYarnMetadataTable::from_csv(self.metadata)?;
+
+        let mut app = headless_app(self.plugin.clone());
+        // keep the whole run, as the visited nodes are read from the history
+        app.insert_resource(DialogueHistory::with_max_entries(usize::MAX));
+
+        let mut vm = VirtualMachine::new(program.clone());
+        vm.set_node(&self.start_node)
+            .map_err(|e| YarnTestError::InvalidStartNode(format!("{e:?}")))?;
+
+        let string_table = app
+            .world
+            .resource_mut::<Assets<BevyYarnStringTable>>()
+            .add(string_table);
+        let metadata_table = app
+            .world
+            .resource_mut::<Assets<BevyYarnMetadataTable>>()
+            .add(metadata_table);
+        let program = app
+            .world
+            .resource_mut::<Assets<BevyYarnProgram>>()
+            .add(BevyYarnProgram {
+                program,
+                format_version: SUPPORTED_PROGRAM_VERSION,
+                headers,
+                string_table: string_table.clone(),
+                metadata_table: metadata_table.clone(),
+            });
+
+        let mut engine = BevyYarnDialogueEngine::new(
+            "yarn_test_runner".into(),
+            &self.start_node,
+            vm,
+            program,
+            string_table,
+            metadata_table,
+        );
+        // the runner steps the dialogue with step events from the first step
+        engine.started = true;
+        let engine = app.world.spawn(engine).id();
+
+        Ok((app, engine))
+    }
+
+    /// Plays the dialogue, calling `choose` to pick a choice index each time the dialogue
+    /// offers choices. Errors while setting up the app are returned as an error, errors
+    /// while running the dialogue are returned alongside the events raised before the error.
+    fn play<F>(self, mut choose: F) -> Result<(YarnTestRun, Option<YarnTestError>), YarnTestError>
+    where
+        F: FnMut(&[BevyYarnChoice]) -> Result<usize, YarnTestError>,
+    {
+        let (mut app, engine) = self.build_app()?;
+
+        let mut reader = ManualEventReader::<BevyYarnEvent>::default();
+        let mut run = YarnTestRun {
+            events: Vec::new(),
+            variables: HashMap::new(),
+            visited_nodes: HashSet::from([self.start_node.clone()]),
+        };
+
+        let error = 'steps: {
+            for _ in 0..self.max_steps {
+                app.world
+                    .resource_mut::<Events<BevyYarnStepDialogueEvent>>()
+                    .send(BevyYarnStepDialogueEvent);
+                app.update();
+
+                let new_events = reader
+                    .iter(app.world.resource::<Events<BevyYarnEvent>>())
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let mut yarn_engine = app
+                    .world
+                    .get_mut::<BevyYarnDialogueEngine>(engine)
+                    .expect("engine entity exists");
+                run.variables = yarn_engine.vm.variable_storage.clone();
+
+                if let Some(error) = yarn_engine.last_error.take() {
+                    break 'steps Some(YarnTestError::Dialogue(error));
+                }
+
+                for event in new_events {
+                    if let BevyYarnEvent::Choices {
+                        choices: ref offered,
+                        ..
+                    } = event
+                    {
+                        let index = match choose(offered) {
+                            Ok(index) => index,
+                            Err(error) => break 'steps Some(error),
+                        };
+
+                        if !yarn_engine.select_option(index) {
+                            break 'steps Some(YarnTestError::InvalidChoice {
+                                index,
+                                offered: offered.len(),
+                            });
+                        }
+                    }
+
+                    run.events.push(event);
+                }
+
+                if yarn_engine.is_complete {
+                    break 'steps None;
+                }
+            }
+
+            Some(YarnTestError::StepLimitReached(self.max_steps))
+        };
+
+        run.visited_nodes.extend(
+            app.world
+                .resource::<DialogueHistory>()
+                .entries
+                .iter()
+                .filter_map(|entry| match entry {
+                    DialogueHistoryEntry::NodeChange { node, .. } => Some(node.clone()),
+                    _ => None,
+                }),
+        );
+
+        Ok((run, error))
+    }
+}
+
+/// A summary of the issues found by a [YarnFuzzer]
+#[derive(Debug, Default)]
+pub struct YarnFuzzReport {
+    /// The number of runs that were played
+    pub runs: usize,
+    /// The indices of runs that did not complete within the maximum number of steps
+    pub non_terminating_runs: Vec<usize>,
+    /// The indices of runs that failed with an error, and the error
+    pub failed_runs: Vec<(usize, String)>,
+    /// The nodes in the program that were not reached by any run, sorted by name
+    pub unreached_nodes: Vec<String>,
+    /// The names of commands that were raised without a registered handler, sorted by name
+    pub unhandled_commands: Vec<String>,
+    /// The line ids that were said or offered but are missing from the string table, sorted
+    pub missing_line_ids: Vec<String>,
+}
+
+impl YarnFuzzReport {
+    /// Returns true if the fuzzer found no issues
+    pub fn is_clean(&self) -> bool {
+        self.non_terminating_runs.is_empty()
+            && self.failed_runs.is_empty()
+            && self.unreached_nodes.is_empty()
+            && self.unhandled_commands.is_empty()
+            && self.missing_line_ids.is_empty()
+    }
+}
+
+impl Display for YarnFuzzReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Played {} runs", self.runs)?;
+
+        if self.is_clean() {
+            return writeln!(f, "No issues found");
+        }
+
+        if !self.non_terminating_runs.is_empty() {
+            writeln!(
+                f,
+                "{} runs did not terminate: {:?}",
+                self.non_terminating_runs.len(),
+                self.non_terminating_runs
+            )?;
+        }
+
+        for (run, error) in self.failed_runs.iter() {
+            writeln!(f, "Run {run} failed: {error}")?;
+        }
+
+        if !self.unreached_nodes.is_empty() {
+            writeln!(
+                f,
+                "Nodes never reached: {}",
+                self.unreached_nodes.join(", ")
+            )?;
+        }
+
+        if !self.unhandled_commands.is_empty() {
+            writeln!(
+                f,
+                "Commands with no registered handler: {}",
+                self.unhandled_commands.join(", ")
+            )?;
+        }
+
+        if !self.missing_line_ids.is_empty() {
+            writeln!(
+                f,
+                "Line ids missing from the string table: {}",
+                self.missing_line_ids.join(", ")
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Plays a compiled yarn program many times, picking random choices from a seeded
+/// random number generator, and reports on content issues found across all runs.
+pub struct YarnFuzzer<'a> {
+    program: &'a [u8],
+    lines: &'a [u8],
+    metadata: &'a [u8],
+    seed: u64,
+    runs: usize,
+    max_steps: usize,
+    plugin: YarnPlugin,
+}
+
+impl<'a> YarnFuzzer<'a> {
+    /// Creates a fuzzer from the contents of a yarnc file and its lines and metadata CSV files
+    pub fn new(program: &'a [u8], lines: &'a [u8], metadata: &'a [u8]) -> Self {
+        Self {
+            program,
+            lines,
+            metadata,
+            seed: 0,
+            runs: 100,
+            max_steps: DEFAULT_MAX_STEPS,
+            plugin: YarnPlugin::default(),
+        }
+    }
+
+    /// Sets the seed for picking random choices, the default is 0. Returns the fuzzer.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets the number of runs to play, the default is 100. Returns the fuzzer.
+    pub fn with_runs(mut self, runs: usize) -> Self {
+        self.runs = runs;
+        self
+    }
+
+    /// Sets the maximum number of steps per run before the run is considered to never
+    /// terminate, the default is [DEFAULT_MAX_STEPS]. Returns the fuzzer.
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Sets the plugin to run the dialogue with, which should have the same command
+    /// handlers registered as the game. Returns the fuzzer.
+    pub fn with_plugin(mut self, plugin: YarnPlugin) -> Self {
+        self.plugin = plugin;
+        self
+    }
+
+    /// Plays all the runs and builds a report of the issues found
+    pub fn run(self) -> Result<YarnFuzzReport, YarnTestError> {
+        let program = Program::decode(self.program)?;
+        let string_table = BevyYarnStringTable::from_csv(self.lines)?;
+
+        let mut rng = fastrand::Rng::with_seed(self.seed);
+        let mut report = YarnFuzzReport {
+            runs: self.runs,
+            ..Default::default()
+        };
+        let mut visited_nodes = HashSet::new();
+        let mut unhandled_commands = HashSet::new();
+        let mut missing_line_ids = HashSet::new();
+
+        for run_index in 0..self.runs {
+            let (run, error) = YarnTestRunner::new(self.program, self.lines, self.metadata)
+                .with_max_steps(self.max_steps)
+                .with_plugin(self.plugin.clone())
+                .play(|offered| {
+                    if offered.is_empty() {
+                        Err(YarnTestError::NotEnoughChoices { offered: 0 })
+                    } else {
+                        Ok(rng.usize(..offered.len()))
+                    }
+                })?;
+
+            match error {
+                None => {}
+                Some(YarnTestError::StepLimitReached(_)) => {
+                    report.non_terminating_runs.push(run_index)
+                }
+                Some(error) => report.failed_runs.push((run_index, error.to_string())),
+            }
+
+            visited_nodes.extend(run.visited_nodes);
+
+            for event in run.events.iter() {
+                let line_ids = match event {
+                    BevyYarnEvent::Say(line) => vec![line.line.id.as_str()],
+                    BevyYarnEvent::Choices { choices, .. } => choices
+                        .iter()
+                        .map(|choice| choice.line_id.as_str())
+                        .collect(),
+                    BevyYarnEvent::Command(command) => {
+                        if !command.handled {
+                            unhandled_commands.insert(command.command_name.clone());
+                        }
+                        continue;
+                    }
+                    _ => continue,
+                };
+
+                missing_line_ids.extend(
+                    line_ids
+                        .into_iter()
+                        .filter(|id| string_table.line_info(id).is_none())
+                        .map(str::to_owned),
+                );
+            }
+        }
+
+        report.unreached_nodes = program
+            .nodes
+            .keys()
+            .filter(|node| !visited_nodes.contains(*node))
+            .cloned()
+            .collect();
+        report.unreached_nodes.sort();
+        report.unhandled_commands = unhandled_commands.into_iter().collect();
+        report.unhandled_commands.sort();
+        report.missing_line_ids = missing_line_ids.into_iter().collect();
+        report.missing_line_ids.sort();
+
+        Ok(report)
+    }
+}
+
+/// A dialogue stepped one frame at a time by the unit tests, to check the events and the
+/// engine state in between steps
+#[cfg(test)]
+pub(crate) struct TestDialogue {
+    pub(crate) app: App,
+    pub(crate) engine: Entity,
+    reader: ManualEventReader<BevyYarnEvent>,
+}
+
+#[cfg(test)]
+impl TestDialogue {
+    /// The kitchen sink example dialogue, run with the default plugin
+    pub(crate) fn kitchen_sink() -> Self {
+        Self::with_plugin(YarnPlugin::default())
+    }
+
+    /// The kitchen sink example dialogue, run with the given plugin
+    pub(crate) fn with_plugin(plugin: YarnPlugin) -> Self {
+        let (app, engine) = YarnTestRunner::new(
+            include_bytes!("../assets/kitchen_sink.yarnc"),
+            include_bytes!("../assets/kitchen_sink.lines.csv"),
+            include_bytes!("../assets/kitchen_sink.metadata.csv"),
+        )
+        .with_plugin(plugin)
+        .build_app()
+        .expect("kitchen sink example is valid");
+
+        Self {
+            app,
+            engine,
+            reader: ManualEventReader::default(),
+        }
+    }
+
+    /// Steps the dialogue once, returning the events raised
+    pub(crate) fn step(&mut self) -> Vec<BevyYarnEvent> {
+        self.app
+            .world
+            .resource_mut::<Events<BevyYarnStepDialogueEvent>>()
+            .send(BevyYarnStepDialogueEvent);
+        self.update()
+    }
+
+    /// Steps the dialogue until it offers choices, returning the choices offered
+    pub(crate) fn step_to_choices(&mut self) -> Vec<BevyYarnChoice> {
+        for _ in 0..DEFAULT_MAX_STEPS {
+            let choices = self.step().into_iter().find_map(|event| match event {
+                BevyYarnEvent::Choices { choices, .. } => Some(choices),
+                _ => None,
+            });
+
+            if let Some(choices) = choices {
+                return choices;
+            }
+        }
+
+        panic!("dialogue offered no choices within {DEFAULT_MAX_STEPS} steps");
+    }
+
+    /// Steps the kitchen sink dialogue until it offers choices, returning the position of
+    /// option 3, which is also its option index
+    pub(crate) fn option_3_position(&mut self) -> usize {
+        self.step_to_choices()
+            .iter()
+            .position(|choice| choice.line_id.as_str() == "line:37f2bb71")
+            .expect("option 3 is offered")
+    }
+
+    /// Updates the app without stepping the dialogue, returning the events raised
+    pub(crate) fn update(&mut self) -> Vec<BevyYarnEvent> {
+        self.app.update();
+        self.reader
+            .iter(self.app.world.resource::<Events<BevyYarnEvent>>())
+            .cloned()
+            .collect()
+    }
+
+    /// Spawns another engine running the same program from its start node, which is stepped
+    /// along with the first
+    pub(crate) fn spawn_engine(&mut self, engine_name: &str) -> Entity {
+        let engine = self.engine();
+        let (program, string_table, metadata_table) = (
+            engine.program.clone(),
+            engine.string_table.clone(),
+            engine.metadata_table.clone(),
+        );
+
+        let mut vm = VirtualMachine::new(
+            self.app
+                .world
+                .resource::<Assets<BevyYarnProgram>>()
+                .get(&program)
+                .expect("program is loaded")
+                .program
+                .clone(),
+        );
+        vm.set_node("Start").expect("program has a Start node");
+
+        let mut engine = BevyYarnDialogueEngine::new(
+            engine_name.into(),
+            "Start",
+            vm,
+            program,
+            string_table,
+            metadata_table,
+        );
+        engine.started = true;
+        self.app.world.spawn(engine).id()
+    }
+
+    /// The dialogue engine
+    pub(crate) fn engine(&mut self) -> Mut<'_, BevyYarnDialogueEngine> {
+        self.app
+            .world
+            .get_mut::<BevyYarnDialogueEngine>(self.engine)
+            .expect("engine entity exists")
+    }
+}
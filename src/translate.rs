@@ -0,0 +1,149 @@
+//! Translates the suspend reasons of the yarn virtual machine into [BevyYarnEvent]s.
+//!
+//! This is separate from stepping the virtual machine and running commands, so that the
+//! events raised for a given suspend reason can be checked without an app.
+
+use std::{collections::HashMap, sync::Arc};
+
+use bevy::prelude::Entity;
+use chapter::{Line, SuspendReason};
+
+use crate::{
+    assets::{
+        node_title, BevyYarnMetadataTable, BevyYarnNamedSubstitutions, BevyYarnStringTable,
+        NodeHeaders, YarnMissingStringPolicy,
+    },
+    prelude::{
+        BevyYarnChoice, BevyYarnCommand, BevyYarnEvent, BevyYarnLine, YarnActorConfig,
+        YarnLineTagKeys, YarnTimingConfig,
+    },
+    LOCALE,
+};
+
+/// The tables and settings used to build lines and choices in [suspend_reason_to_event]
+#[derive(Debug, Clone, Copy)]
+pub struct YarnTranslationContext<'a> {
+    /// The base string table of the dialogue, used for [LOCALE] and for lines that aren't
+    /// in any of the [YarnTranslationContext::locale_tables]
+    pub string_table: &'a BevyYarnStringTable,
+    /// The metadata table of the dialogue, used for line tags
+    pub metadata_table: &'a BevyYarnMetadataTable,
+    /// The loaded string tables of the locale chain in order, with their locale, see
+    /// [crate::prelude::YarnLocaleChain]
+    pub locale_tables: &'a [(&'a str, &'a BevyYarnStringTable)],
+    /// The values for named substitutions in lines
+    pub named_substitutions: &'a BevyYarnNamedSubstitutions,
+    /// The text used for lines that are missing from the string table
+    pub missing_string_policy: &'a YarnMissingStringPolicy,
+    /// The reading speed used for [BevyYarnLine::estimated_duration]
+    pub timing: &'a YarnTimingConfig,
+    /// The structured tags read into the well known fields of lines, such as
+    /// [BevyYarnLine::emotion]
+    pub tag_keys: &'a YarnLineTagKeys,
+    /// The prefixes and tags of narration and stage direction lines, used for
+    /// [BevyYarnLine::actor_type]
+    pub actor_config: &'a YarnActorConfig,
+    /// The headers of each node in the running program, used for
+    /// [BevyYarnChoice::destination_title]
+    pub node_headers: &'a NodeHeaders,
+    /// The number of times the dialogue has visited each node, used for
+    /// [BevyYarnChoice::was_previously_selected]
+    pub visited_nodes: &'a HashMap<String, u32>,
+    /// The entity of the dialogue engine, or [Entity::PLACEHOLDER] for a resource engine,
+    /// used for [BevyYarnEvent::Choices]
+    pub engine_entity: Entity,
+}
+
+impl<'a> YarnTranslationContext<'a> {
+    /// Finds the first string table in the locale chain that contains the given line id,
+    /// and its locale. Falls back to the base string table and [LOCALE] if none of them do.
+    pub fn resolve_table(&self, line_id: &str) -> (&'a str, &'a BevyYarnStringTable) {
+        self.locale_tables
+            .iter()
+            .find(|(_, table)| table.line_info(line_id).is_some())
+            .copied()
+            .unwrap_or((LOCALE, self.string_table))
+    }
+
+    /// Builds the [BevyYarnLine] for a line from the virtual machine
+    pub fn line(&self, line: &Line) -> BevyYarnLine {
+        let (locale, table) = self.resolve_table(&line.id);
+        let (character, formatted_text) = table.get_shared_final_text(
+            line,
+            locale,
+            self.named_substitutions,
+            self.missing_string_policy,
+        );
+
+        let (tags, structured_tags) = self.metadata_table.get_parsed_tags_for_line(line);
+        let estimated_duration = self.timing.estimate(&formatted_text, &structured_tags);
+        let emotion = YarnLineTagKeys::find(&self.tag_keys.emotion, &structured_tags);
+        let voice = YarnLineTagKeys::find(&self.tag_keys.voice, &structured_tags);
+        let actor_type = self.actor_config.actor_type(character.as_deref(), &tags);
+
+        BevyYarnLine {
+            formatted_text,
+            raw_text: table.shared_raw_text(&line.id).unwrap_or_else(|| "".into()),
+            line: Arc::new(line.clone()),
+            character,
+            actor_type,
+            tags,
+            structured_tags,
+            resolved_locale: locale.to_owned(),
+            estimated_duration,
+            emotion,
+            voice,
+            speaker_entity: None,
+            is_replay: false,
+        }
+    }
+}
+
+/// Translates a suspend reason from the virtual machine into the event it raises, using the
+/// given tables. Lines raise [BevyYarnEvent::Say], options raise [BevyYarnEvent::Choices],
+/// commands raise [BevyYarnEvent::Command] (not yet marked as handled) and completing the
+/// dialogue raises [BevyYarnEvent::EndConversation]. Other suspend reasons don't raise
+/// events, so return `None`.
+///
+/// This doesn't change the dialogue or run commands, which is done by the
+/// [crate::YarnPlugin] systems.
+///
+/// ```ignore
+/// let event = suspend_reason_to_event(&SuspendReason::Line(line), &context);
+/// assert!(matches!(event, Some(BevyYarnEvent::Say(line)) if &*line.formatted_text == "Hello"));
+/// ```
+pub fn suspend_reason_to_event(
+    reason: &SuspendReason,
+    context: &YarnTranslationContext,
+) -> Option<BevyYarnEvent> {
+    match reason {
+        SuspendReason::Line(line) => Some(BevyYarnEvent::Say(context.line(line))),
+        SuspendReason::Options(options) => Some(BevyYarnEvent::Choices {
+            engine_entity: context.engine_entity,
+            choices: options
+                .iter()
+                .enumerate()
+                .map(|(option_index, choice)| BevyYarnChoice {
+                    line_id: choice.line.id.clone().into(),
+                    option_index,
+                    formatted_line: context.line(&choice.line),
+                    destination_node: choice.destination_node.clone().into(),
+                    destination_title: node_title(context.node_headers, &choice.destination_node)
+                        .to_owned(),
+                    was_previously_selected: context
+                        .visited_nodes
+                        .contains_key(&choice.destination_node),
+                })
+                .collect(),
+            is_replay: false,
+        }),
+        SuspendReason::Command(cmd_text) => {
+            Some(BevyYarnEvent::Command(BevyYarnCommand::parse(cmd_text)))
+        }
+        SuspendReason::DialogueComplete(_) => Some(BevyYarnEvent::EndConversation {
+            engine_entity: context.engine_entity,
+            aborted: false,
+        }),
+        _ => None,
+    }
+}
@@ -0,0 +1,292 @@
+//! A minimal dialogue box and choice list, for getting dialogue on screen without writing
+//! a UI. See [YarnDefaultUiPlugin].
+
+use bevy::prelude::*;
+
+use crate::prelude::{
+    BevyYarnChoice, BevyYarnEvent, BevyYarnLine, BevyYarnStepDialogueEvent, SelectChoiceCommand,
+    YarnCharacterStyles, YarnGlobalPause, YarnSystemSet,
+};
+
+/// Spawns a simple dialogue box along the bottom of the screen that shows each
+/// [BevyYarnEvent::Say] line, with a clickable list of choices for [BevyYarnEvent::Choices].
+/// Clicking the dialogue box steps the dialogue, and clicking a choice selects it.
+///
+/// Character names and text are coloured using [YarnCharacterStyles], and the layout can be
+/// tweaked by inserting a [YarnDefaultUiSettings] resource before adding the plugin. For
+/// anything more involved, use the marker components to restyle the spawned entities or
+/// leave this plugin out and build your own UI from [BevyYarnEvent]s.
+#[derive(Default)]
+pub struct YarnDefaultUiPlugin;
+
+impl Plugin for YarnDefaultUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<YarnDefaultUiSettings>()
+            .add_systems(Startup, Self::spawn_ui)
+            .add_systems(
+                Update,
+                (
+                    Self::handle_yarn_events.after(YarnSystemSet::ProcessEvents),
+                    (Self::handle_dialogue_box_clicks, Self::handle_choice_clicks)
+                        .in_set(YarnSystemSet::Input)
+                        .run_if(YarnGlobalPause::is_running),
+                ),
+            );
+    }
+}
+
+/// Settings for the UI spawned by [YarnDefaultUiPlugin]
+#[derive(Resource, Debug, Clone)]
+pub struct YarnDefaultUiSettings {
+    /// The font size used for lines and choices
+    pub font_size: f32,
+    /// The height of the dialogue box
+    pub height: Val,
+    /// The background colour of the dialogue box
+    pub background_color: Color,
+    /// The text colour of choices
+    pub choice_color: Color,
+    /// The text colour of a choice while the mouse is over it
+    pub choice_hover_color: Color,
+}
+
+impl Default for YarnDefaultUiSettings {
+    fn default() -> Self {
+        Self {
+            font_size: 18.,
+            height: Val::Px(180.),
+            background_color: Color::rgba(0., 0., 0., 0.8),
+            choice_color: Color::GRAY,
+            choice_hover_color: Color::WHITE,
+        }
+    }
+}
+
+/// Marks the root node of the dialogue box spawned by [YarnDefaultUiPlugin]
+#[derive(Component)]
+pub struct YarnDefaultUiRoot;
+
+/// Marks the text entity that shows the current line in the dialogue box
+#[derive(Component)]
+pub struct YarnDefaultUiLineText;
+
+/// Marks the node that choices are spawned into
+#[derive(Component)]
+pub struct YarnDefaultUiChoiceList;
+
+/// A choice button, holding the engine that offered the choice and the position of the
+/// choice in its [BevyYarnEvent::Choices] event
+#[derive(Component)]
+pub struct YarnDefaultUiChoice {
+    /// The [BevyYarnEvent::Choices::engine_entity] of the choice
+    pub engine_entity: Entity,
+    /// The position of the choice in the [BevyYarnEvent::Choices] event, see
+    /// [SelectChoiceCommand::choice_index]
+    pub choice_index: usize,
+}
+
+impl YarnDefaultUiPlugin {
+    fn spawn_ui(mut commands: Commands, settings: Res<YarnDefaultUiSettings>) {
+        commands
+            .spawn((
+                ButtonBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        left: Val::Px(0.),
+                        right: Val::Px(0.),
+                        bottom: Val::Px(0.),
+                        height: settings.height,
+                        flex_direction: FlexDirection::Column,
+                        padding: UiRect::all(Val::Px(16.)),
+                        row_gap: Val::Px(8.),
+                        ..default()
+                    },
+                    background_color: settings.background_color.into(),
+                    visibility: Visibility::Hidden,
+                    ..default()
+                },
+                YarnDefaultUiRoot,
+            ))
+            .with_children(|root| {
+                root.spawn((TextBundle::default(), YarnDefaultUiLineText));
+                root.spawn((
+                    NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Column,
+                            row_gap: Val::Px(4.),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    YarnDefaultUiChoiceList,
+                ));
+            });
+    }
+
+    fn line_sections(
+        line: &BevyYarnLine,
+        styles: &YarnCharacterStyles,
+        font_size: f32,
+    ) -> Vec<TextSection> {
+        let style = styles.get_style(line.character.as_deref());
+        let font = style.font.clone().unwrap_or_default();
+        let mut sections = Vec::with_capacity(2);
+
+        if let Some(ref character) = line.character {
+            sections.push(TextSection {
+                value: format!("{character}: "),
+                style: TextStyle {
+                    font: font.clone(),
+                    font_size,
+                    color: style.name_color,
+                },
+            });
+        }
+
+        sections.push(TextSection {
+            value: line.formatted_text.to_string(),
+            style: TextStyle {
+                font,
+                font_size,
+                color: style.text_color,
+            },
+        });
+
+        sections
+    }
+
+    fn spawn_choices(
+        commands: &mut Commands,
+        choice_list: Entity,
+        engine_entity: Entity,
+        choices: &[BevyYarnChoice],
+        settings: &YarnDefaultUiSettings,
+    ) {
+        commands.entity(choice_list).with_children(|list| {
+            for (position, choice) in choices.iter().enumerate() {
+                list.spawn((
+                    ButtonBundle {
+                        background_color: Color::NONE.into(),
+                        ..default()
+                    },
+                    YarnDefaultUiChoice {
+                        engine_entity,
+                        choice_index: position,
+                    },
+                ))
+                .with_children(|button| {
+                    button.spawn(TextBundle::from_section(
+                        format!("{}. {}", position + 1, choice.formatted_line.formatted_text),
+                        TextStyle {
+                            font_size: settings.font_size,
+                            color: settings.choice_color,
+                            ..default()
+                        },
+                    ));
+                });
+            }
+        });
+    }
+
+    /// Updates the dialogue box from the events raised by the dialogue engines
+    fn handle_yarn_events(
+        mut commands: Commands,
+        mut events: EventReader<BevyYarnEvent>,
+        settings: Res<YarnDefaultUiSettings>,
+        styles: Res<YarnCharacterStyles>,
+        mut roots: Query<&mut Visibility, With<YarnDefaultUiRoot>>,
+        mut line_texts: Query<&mut Text, With<YarnDefaultUiLineText>>,
+        choice_lists: Query<Entity, With<YarnDefaultUiChoiceList>>,
+    ) {
+        for event in events.iter() {
+            let visibility = match event {
+                BevyYarnEvent::Say(line) => {
+                    for mut text in line_texts.iter_mut() {
+                        text.sections = Self::line_sections(line, &styles, settings.font_size);
+                    }
+                    for choice_list in choice_lists.iter() {
+                        commands.entity(choice_list).despawn_descendants();
+                    }
+                    Visibility::Inherited
+                }
+                BevyYarnEvent::Choices {
+                    engine_entity,
+                    choices,
+                    ..
+                } => {
+                    for choice_list in choice_lists.iter() {
+                        commands.entity(choice_list).despawn_descendants();
+                        Self::spawn_choices(
+                            &mut commands,
+                            choice_list,
+                            *engine_entity,
+                            choices,
+                            &settings,
+                        );
+                    }
+                    Visibility::Inherited
+                }
+                BevyYarnEvent::EndConversation { .. } => Visibility::Hidden,
+                _ => continue,
+            };
+
+            for mut root_visibility in roots.iter_mut() {
+                *root_visibility = visibility;
+            }
+        }
+    }
+
+    /// Steps the dialogue when the dialogue box is clicked while no choices are shown
+    fn handle_dialogue_box_clicks(
+        roots: Query<&Interaction, (Changed<Interaction>, With<YarnDefaultUiRoot>)>,
+        choices: Query<(), With<YarnDefaultUiChoice>>,
+        mut event_sender: EventWriter<BevyYarnStepDialogueEvent>,
+    ) {
+        if !choices.is_empty() {
+            return;
+        }
+
+        if roots
+            .iter()
+            .any(|interaction| *interaction == Interaction::Pressed)
+        {
+            event_sender.send(BevyYarnStepDialogueEvent);
+        }
+    }
+
+    /// Highlights hovered choices and selects clicked ones on the engine that offered them
+    fn handle_choice_clicks(
+        mut commands: Commands,
+        settings: Res<YarnDefaultUiSettings>,
+        buttons: Query<(&Interaction, &YarnDefaultUiChoice, &Children), Changed<Interaction>>,
+        mut texts: Query<&mut Text>,
+    ) {
+        for (interaction, choice, children) in buttons.iter() {
+            let color = match interaction {
+                Interaction::Hovered | Interaction::Pressed => settings.choice_hover_color,
+                Interaction::None => settings.choice_color,
+            };
+
+            for child in children.iter() {
+                if let Ok(mut text) = texts.get_mut(*child) {
+                    for section in text.sections.iter_mut() {
+                        section.style.color = color;
+                    }
+                }
+            }
+
+            if *interaction != Interaction::Pressed {
+                continue;
+            }
+
+            info!(
+                "Selecting choice (choice {} clicked)",
+                choice.choice_index + 1
+            );
+            commands.add(SelectChoiceCommand {
+                entity: choice.engine_entity,
+                choice_index: choice.choice_index,
+            });
+        }
+    }
+}